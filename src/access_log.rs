@@ -0,0 +1,317 @@
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use crate::{
+    bytes::ToBytes,
+    middleware::{Middleware, Rejected},
+    spec::{date::DateTime, header},
+    Request, Response,
+};
+
+/// How each access log line is written.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Common Log Format
+    #[default]
+    Common,
+    /// Common Log Format followed by the referer and the user agent
+    Combined,
+    /// One JSON object per line, also with the duration
+    Json,
+}
+
+enum Output {
+    Stdout,
+    File { path: PathBuf, file: File },
+}
+
+/// Middleware writing a line per response to stdout or to a file.
+///
+/// It should be the outermost middleware, so the logged size is the size of
+/// the body as sent.
+pub struct AccessLog {
+    format: LogFormat,
+    output: Mutex<Output>,
+}
+
+impl AccessLog {
+    pub fn stdout(format: LogFormat) -> AccessLog {
+        AccessLog {
+            format,
+            output: Mutex::new(Output::Stdout),
+        }
+    }
+
+    /// Appends to the file at `path`, creating it if needed.
+    pub fn file(path: impl Into<PathBuf>, format: LogFormat) -> io::Result<AccessLog> {
+        let path = path.into();
+        let file = open(&path)?;
+        Ok(AccessLog {
+            format,
+            output: Mutex::new(Output::File { path, file }),
+        })
+    }
+
+    /// Opens the file again, so logs are written to a new file once the old one
+    /// was moved away, as logrotate does before sending `SIGHUP`.
+    pub fn reopen(&self) -> io::Result<()> {
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Output::File { path, file } = &mut *output {
+            *file = open(path)?;
+        }
+        Ok(())
+    }
+
+    /// The line for `request`, `None` when it could not be parsed.
+    fn line(
+        &self,
+        peer_addr: Option<SocketAddr>,
+        request: Option<&Request>,
+        response: &Response,
+        now: SystemTime,
+    ) -> String {
+        let duration = request
+            .and_then(Request::received_at)
+            .map(|received_at| received_at.elapsed())
+            .unwrap_or_default();
+        let time = DateTime::from_system_time(now.checked_sub(duration).unwrap_or(now));
+        let host = peer_addr.map_or_else(|| "-".to_string(), |addr| addr.ip().to_string());
+        let request_line = request.map(|request| {
+            [
+                request.request_line.method.as_bytes(),
+                b" ",
+                &request.request_line.request_uri.target(),
+                b" ",
+                &request.request_line.http_version.into_bytes(),
+            ]
+            .concat()
+        });
+        let status = response.status().code();
+        let bytes = response.body().len();
+        let referer = request.and_then(|request| request.headers().get(header::REFERER));
+        let user_agent = request.and_then(|request| request.headers().get(header::USER_AGENT));
+
+        let mut line = String::new();
+        match self.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let _ = write!(
+                    line,
+                    "{host} - - [{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000] \"{}\" {status} ",
+                    time.day,
+                    time.month_name(),
+                    time.year,
+                    time.hour,
+                    time.minute,
+                    time.second,
+                    request_line.as_deref().map_or("-".to_string(), escape),
+                );
+                match bytes {
+                    0 => line.push('-'),
+                    bytes => line.push_str(&bytes.to_string()),
+                }
+                if self.format == LogFormat::Combined {
+                    let quoted = |value: Option<&[u8]>| value.map_or("-".to_string(), escape);
+                    let _ = write!(line, " \"{}\" \"{}\"", quoted(referer), quoted(user_agent));
+                }
+            }
+            LogFormat::Json => {
+                let string = |value: Option<&[u8]>| value.map_or("null".to_string(), json_string);
+                let _ = write!(
+                    line,
+                    "{{\"time\":\"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z\",\"remote_addr\":{},\
+                     \"request\":{},\"status\":{status},\"bytes\":{bytes},\"duration_ms\":{:.3},\
+                     \"referer\":{},\"user_agent\":{}}}",
+                    time.year,
+                    time.month,
+                    time.day,
+                    time.hour,
+                    time.minute,
+                    time.second,
+                    string(peer_addr.is_some().then_some(host.as_bytes())),
+                    string(request_line.as_deref()),
+                    duration.as_secs_f64() * 1000.0,
+                    string(referer),
+                    string(user_agent),
+                );
+            }
+        }
+        line.push('\n');
+        line
+    }
+}
+
+impl AccessLog {
+    fn write(&self, line: String) {
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        // a log that cannot be written does not fail the request
+        let _ = match &mut *output {
+            Output::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            Output::File { file, .. } => file.write_all(line.as_bytes()),
+        };
+    }
+}
+
+impl Middleware for AccessLog {
+    fn after(&self, request: &Request, response: &mut Response) {
+        self.write(self.line(
+            request.peer_addr(),
+            Some(request),
+            response,
+            SystemTime::now(),
+        ));
+    }
+
+    fn rejected(&self, request: Rejected, response: &Response) {
+        let line = match request {
+            Rejected::Request(request) => self.line(
+                request.peer_addr(),
+                Some(request),
+                response,
+                SystemTime::now(),
+            ),
+            Rejected::Unparsed(peer_addr) => {
+                self.line(peer_addr, None, response, SystemTime::now())
+            }
+        };
+        self.write(line);
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Escapes a value written between double quotes, like Apache does.
+fn escape(value: &[u8]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for &c in value {
+        match c {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(c as char);
+            }
+            b' '..=b'~' => escaped.push(c as char),
+            c => {
+                let _ = write!(escaped, "\\x{c:02x}");
+            }
+        }
+    }
+    escaped
+}
+
+/// A JSON string, bytes that are not UTF-8 are replaced.
+fn json_string(value: &[u8]) -> String {
+    let mut string = String::with_capacity(value.len() + 2);
+    string.push('"');
+    for c in String::from_utf8_lossy(value).chars() {
+        match c {
+            '"' | '\\' => {
+                string.push('\\');
+                string.push(c);
+            }
+            c if c.is_control() => {
+                let _ = write!(string, "\\u{:04x}", c as u32);
+            }
+            c => string.push(c),
+        }
+    }
+    string.push('"');
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, time::Duration};
+
+    use super::*;
+    use crate::{parser::Parse, Status};
+
+    fn line(format: LogFormat, request: &str) -> String {
+        let mut request = Request::convert(request).unwrap();
+        request.peer_addr = Some("127.0.0.1:50000".parse().unwrap());
+        let mut response = Response::new(Status::OK);
+        response.set_body(b"hello".to_vec());
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1445412480);
+        AccessLog::stdout(format).line(request.peer_addr(), Some(&request), &response, now)
+    }
+
+    const REQUEST: &str = "GET /echo/a%20\"b\"?x=1 HTTP/1.1\r\n\
+                           User-Agent: curl/8.0\r\nReferer: http://example.com/\r\n\r\n";
+
+    #[test]
+    fn common() {
+        assert_eq!(
+            line(LogFormat::Common, REQUEST),
+            "127.0.0.1 - - [21/Oct/2015:07:28:00 +0000] \
+             \"GET /echo/a%20\\\"b\\\"?x=1 HTTP/1.1\" 200 5\n"
+        );
+    }
+
+    #[test]
+    fn combined() {
+        assert_eq!(
+            line(LogFormat::Combined, "POST /files/a HTTP/1.0\r\n\r\n"),
+            "127.0.0.1 - - [21/Oct/2015:07:28:00 +0000] \"POST /files/a HTTP/1.0\" 200 5 \"-\" \"-\"\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            line(LogFormat::Json, REQUEST),
+            "{\"time\":\"2015-10-21T07:28:00Z\",\"remote_addr\":\"127.0.0.1\",\
+             \"request\":\"GET /echo/a%20\\\"b\\\"?x=1 HTTP/1.1\",\"status\":200,\"bytes\":5,\
+             \"duration_ms\":0.000,\"referer\":\"http://example.com/\",\"user_agent\":\"curl/8.0\"}\n"
+        );
+    }
+
+    #[test]
+    fn unparsed() {
+        let response = Response::new(Status::BadRequest);
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1445412480);
+        let peer_addr = Some("127.0.0.1:50000".parse().unwrap());
+        assert_eq!(
+            AccessLog::stdout(LogFormat::Combined).line(peer_addr, None, &response, now),
+            "127.0.0.1 - - [21/Oct/2015:07:28:00 +0000] \"-\" 400 - \"-\" \"-\"\n"
+        );
+        assert_eq!(
+            AccessLog::stdout(LogFormat::Json).line(peer_addr, None, &response, now),
+            "{\"time\":\"2015-10-21T07:28:00Z\",\"remote_addr\":\"127.0.0.1\",\
+             \"request\":null,\"status\":400,\"bytes\":0,\"duration_ms\":0.000,\
+             \"referer\":null,\"user_agent\":null}\n"
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(escape(b"a\"\\\x01\xff"), "a\\\"\\\\\\x01\\xff");
+        assert_eq!(json_string(b"a\"\n\xff"), "\"a\\\"\\u000a\u{fffd}\"");
+    }
+
+    #[test]
+    fn reopen() {
+        let path = std::env::temp_dir().join(format!("access-log-{}", std::process::id()));
+        let rotated = path.with_extension("1");
+        let log = AccessLog::file(&path, LogFormat::Common).unwrap();
+        let request = Request::convert("GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = Response::new(Status::OK);
+
+        log.after(&request, &mut response);
+        fs::rename(&path, &rotated).unwrap();
+        log.after(&request, &mut response);
+        log.reopen().unwrap();
+        log.after(&request, &mut response);
+
+        let lines = |path| fs::read_to_string(path).unwrap().lines().count();
+        assert_eq!((lines(&rotated), lines(&path)), (2, 1));
+        fs::remove_file(path).unwrap();
+        fs::remove_file(rotated).unwrap();
+    }
+}
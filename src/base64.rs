@@ -0,0 +1,81 @@
+//! Base64 of RFC 4648, for the few header fields carrying binary data.
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encodes with the standard alphabet and padding.
+pub(crate) fn encode(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let mut bytes = [0; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => output.push(STANDARD[(bits >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => output.push('='),
+            }
+        }
+    }
+    output
+}
+
+/// Decodes the standard alphabet, padding is optional.
+pub(crate) fn decode(input: &[u8]) -> Option<Vec<u8>> {
+    decode_with(input, STANDARD)
+}
+
+/// Decodes the URL and filename safe alphabet, padding is optional.
+pub(crate) fn decode_url(input: &[u8]) -> Option<Vec<u8>> {
+    decode_with(input, URL_SAFE)
+}
+
+fn decode_with(input: &[u8], alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let input = input
+        .strip_suffix(b"==")
+        .or(input.strip_suffix(b"="))
+        .unwrap_or(input);
+    if input.len() % 4 == 1 {
+        return None;
+    }
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let sextet = alphabet.iter().position(|a| a == c)?;
+            bits |= (sextet as u32) << (18 - 6 * i);
+        }
+        output.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_padding() {
+        // RFC 4648 section 10
+        for (input, output) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(input.as_bytes()), output);
+            assert_eq!(decode(output.as_bytes()), Some(input.as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn alphabets() {
+        assert_eq!(decode(b"+/8"), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode_url(b"-_8"), Some(vec![0xfb, 0xff]));
+        assert_eq!(decode_url(b"+/8"), None);
+        assert_eq!(decode(b"Zm9vY"), None);
+    }
+}
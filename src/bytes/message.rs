@@ -1,6 +1,9 @@
 use itertools::Itertools;
 
-use crate::spec::message::{FieldContent, FieldName, FieldValue, MessageBody, MessageHeader};
+use crate::spec::{
+    header::HeaderMap,
+    message::{FieldName, FieldValue, MessageBody, MessageHeader},
+};
 
 use super::ToBytes;
 
@@ -10,20 +13,9 @@ impl ToBytes for FieldName {
     }
 }
 
-impl ToBytes for FieldContent {
-    fn into_bytes(self) -> Vec<u8> {
-        self.0
-    }
-}
-
 impl ToBytes for FieldValue {
-    #[allow(unstable_name_collisions)]
     fn into_bytes(self) -> Vec<u8> {
         self.0
-            .into_iter()
-            .map(ToBytes::into_bytes)
-            .intersperse_with(|| b" ".into())
-            .concat()
     }
 }
 
@@ -37,6 +29,15 @@ impl ToBytes for MessageHeader {
     }
 }
 
+impl ToBytes for HeaderMap {
+    fn into_bytes(self) -> Vec<u8> {
+        self.0
+            .into_iter()
+            .map(|header| [header.into_bytes(), b"\r\n".into()].concat())
+            .concat()
+    }
+}
+
 impl ToBytes for MessageBody {
     fn into_bytes(self) -> Vec<u8> {
         self.0
@@ -52,19 +53,10 @@ mod test {
         assert_eq!(FieldName(b"name".to_vec()).into_bytes(), b"name")
     }
 
-    #[test]
-    fn field_content() {
-        assert_eq!(FieldContent(b"content".to_vec()).into_bytes(), b"content")
-    }
-
     #[test]
     fn field_value() {
         assert_eq!(
-            FieldValue(vec![
-                FieldContent(b"content1".to_vec()),
-                FieldContent(b"content2".to_vec()),
-            ])
-            .into_bytes(),
+            FieldValue(b"content1 content2".to_vec()).into_bytes(),
             b"content1 content2"
         )
     }
@@ -74,10 +66,7 @@ mod test {
         assert_eq!(
             MessageHeader {
                 field_name: FieldName(b"name".to_vec()),
-                field_value: Some(FieldValue(vec![
-                    FieldContent(b"content1".to_vec()),
-                    FieldContent(b"content2".to_vec()),
-                ]))
+                field_value: Some(FieldValue(b"content1 content2".to_vec()))
             }
             .into_bytes(),
             b"name: content1 content2"
@@ -96,6 +85,14 @@ mod test {
         )
     }
 
+    #[test]
+    fn header_map() {
+        let mut headers = HeaderMap::new();
+        headers.append("name1", "value1");
+        headers.append("name2", "");
+        assert_eq!(headers.into_bytes(), b"name1: value1\r\nname2:\r\n")
+    }
+
     #[test]
     fn message_body() {
         assert_eq!(MessageBody(b"body".to_vec()).into_bytes(), b"body")
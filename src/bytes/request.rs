@@ -0,0 +1,76 @@
+use crate::spec::request::{Request, RequestLine};
+
+use super::ToBytes;
+
+impl ToBytes for RequestLine {
+    fn into_bytes(self) -> Vec<u8> {
+        [
+            self.method.as_bytes(),
+            b" ",
+            &self.request_uri.target(),
+            b" ",
+            &self.http_version.into_bytes(),
+            b"\r\n",
+        ]
+        .concat()
+    }
+}
+
+/// The head of the request, the body is sent separately.
+impl ToBytes for Request {
+    fn into_bytes(self) -> Vec<u8> {
+        [
+            self.request_line.into_bytes(),
+            self.headers.into_bytes(),
+            b"\r\n".into(),
+        ]
+        .concat()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::spec::{header::HeaderMap, protocol::HttpVersion, request::Method, uri::RequestURI};
+
+    use super::*;
+
+    #[test]
+    fn request_line() {
+        assert_eq!(
+            RequestLine {
+                method: Method::Post,
+                request_uri: RequestURI::Origin {
+                    path: b"/files/a".to_vec(),
+                    query: None,
+                },
+                http_version: HttpVersion { major: 1, minor: 1 },
+            }
+            .into_bytes(),
+            b"POST /files/a HTTP/1.1\r\n"
+        );
+    }
+
+    #[test]
+    fn request() {
+        let mut headers = HeaderMap::new();
+        headers.append("Host", "localhost");
+        headers.append("Content-Length", "2");
+        assert_eq!(
+            Request {
+                request_line: RequestLine {
+                    method: Method::Extension(b"PUT".to_vec()),
+                    request_uri: RequestURI::Origin {
+                        path: b"/".to_vec(),
+                        query: Some(b"a=1".to_vec()),
+                    },
+                    http_version: HttpVersion { major: 1, minor: 1 },
+                },
+                headers,
+                peer_addr: None,
+                received_at: None,
+            }
+            .into_bytes(),
+            b"PUT /?a=1 HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n"
+        );
+    }
+}
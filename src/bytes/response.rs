@@ -1,6 +1,4 @@
-use itertools::Itertools;
-
-use crate::spec::response::{Response, Status, StatusLine};
+use crate::spec::response::{InterimResponse, Response, Status, StatusLine};
 
 use super::ToBytes;
 
@@ -27,14 +25,17 @@ impl ToBytes for StatusLine {
     }
 }
 
+impl ToBytes for InterimResponse {
+    fn into_bytes(self) -> Vec<u8> {
+        [self.status_line.into_bytes(), b"\r\n".into()].concat()
+    }
+}
+
 impl ToBytes for Response {
     fn into_bytes(self) -> Vec<u8> {
         [
             self.status_line.into_bytes(),
-            self.headers
-                .into_iter()
-                .map(|header| [header.into_bytes(), b"\r\n".into()].concat())
-                .concat(),
+            self.headers.into_bytes(),
             b"\r\n".into(),
             self.body.map(ToBytes::into_bytes).unwrap_or_default(),
         ]
@@ -45,12 +46,17 @@ impl ToBytes for Response {
 #[cfg(test)]
 mod test {
     use crate::spec::{
-        message::{FieldContent, FieldName, FieldValue, MessageBody, MessageHeader},
+        message::{FieldName, FieldValue, MessageBody, MessageHeader},
         protocol::HttpVersion,
     };
 
     use super::*;
 
+    #[test]
+    fn status_100() {
+        assert_eq!(Status::Continue.into_bytes(), b"100 Continue");
+    }
+
     #[test]
     fn status_200() {
         assert_eq!(Status::OK.into_bytes(), b"200 OK");
@@ -61,11 +67,45 @@ mod test {
         assert_eq!(Status::Created.into_bytes(), b"201 Created");
     }
 
+    #[test]
+    fn status_400() {
+        assert_eq!(Status::BadRequest.into_bytes(), b"400 Bad Request");
+    }
+
+    #[test]
+    fn status_401() {
+        assert_eq!(Status::Unauthorized.into_bytes(), b"401 Unauthorized");
+    }
+
     #[test]
     fn status_404() {
         assert_eq!(Status::NotFound.into_bytes(), b"404 Not Found");
     }
 
+    #[test]
+    fn status_413() {
+        assert_eq!(
+            Status::PayloadTooLarge.into_bytes(),
+            b"413 Payload Too Large"
+        );
+    }
+
+    #[test]
+    fn status_415() {
+        assert_eq!(
+            Status::UnsupportedMediaType.into_bytes(),
+            b"415 Unsupported Media Type"
+        );
+    }
+
+    #[test]
+    fn status_503() {
+        assert_eq!(
+            Status::ServiceUnavailable.into_bytes(),
+            b"503 Service Unavailable"
+        );
+    }
+
     #[test]
     fn status_line() {
         assert_eq!(
@@ -78,6 +118,20 @@ mod test {
         );
     }
 
+    #[test]
+    fn interim_response() {
+        assert_eq!(
+            InterimResponse {
+                status_line: StatusLine {
+                    http_version: HttpVersion { major: 1, minor: 1 },
+                    status: Status::Continue,
+                },
+            }
+            .into_bytes(),
+            b"HTTP/1.1 100 Continue\r\n\r\n"
+        );
+    }
+
     #[test]
     fn request() {
         assert_eq!(
@@ -89,19 +143,14 @@ mod test {
                 headers: vec![
                     MessageHeader {
                         field_name: FieldName(b"header1".to_vec()),
-                        field_value: Some(FieldValue(vec![
-                            FieldContent(b"a".to_vec()),
-                            FieldContent(b"b".to_vec()),
-                        ]))
+                        field_value: Some(FieldValue(b"a b".to_vec()))
                     },
                     MessageHeader {
                         field_name: FieldName(b"header2".to_vec()),
-                        field_value: Some(FieldValue(vec![
-                            FieldContent(b"c".to_vec()),
-                            FieldContent(b"d".to_vec()),
-                        ]))
+                        field_value: Some(FieldValue(b"c d".to_vec()))
                     }
-                ],
+                ]
+                .into(),
                 body: Some(MessageBody(b"message body".to_vec())),
             }
             .into_bytes(),
@@ -0,0 +1,514 @@
+//! A small blocking HTTP/1.1 client, for integration tests and tools.
+//!
+//! Connections are kept open between requests to the same server, a reused
+//! connection the server closed meanwhile is replaced transparently for
+//! idempotent requests. The reverse proxy sends its requests the same way.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bytes::ToBytes,
+    parser::{Limits, Parse, ParseError, StreamParser},
+    spec::{
+        header::{self, HeaderMap},
+        protocol::HttpVersion,
+        request::{BodyLength, Method, Request, RequestLine},
+        response::{Response, Status},
+        uri::RequestURI,
+    },
+};
+
+/// Idle connections kept per server.
+const MAX_IDLE: usize = 8;
+/// How long an idle connection is kept, servers tend to close them after a while.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends requests to `http://` URLs and reads their whole response.
+///
+/// ```no_run
+/// use codecrafters_http_server::client::Client;
+///
+/// let client = Client::new();
+/// let response = client.get("http://127.0.0.1:4221/echo/hello")?;
+/// assert_eq!(response.body(), b"hello");
+/// let response = client
+///     .request("POST", "http://127.0.0.1:4221/files/hello")?
+///     .body("hello")
+///     .send()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Client {
+    pool: Pool,
+    timeout: Duration,
+    limits: Limits,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client {
+            pool: Pool::new(),
+            timeout: DEFAULT_TIMEOUT,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Sets how long to wait to connect, send the request or receive the response, 30 seconds by default.
+    pub fn timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the limits on the response, its body is held in memory up to `body` bytes.
+    pub fn limits(mut self, limits: Limits) -> Client {
+        self.limits = limits;
+        self
+    }
+
+    pub fn get(&self, url: &str) -> io::Result<Response> {
+        self.request("GET", url)?.send()
+    }
+
+    /// Starts a request to `url`, which has to be an `http://` URL.
+    pub fn request(&self, method: &str, url: &str) -> io::Result<RequestBuilder<'_>> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let method = match method {
+            "GET" => Method::Get,
+            "POST" => Method::Post,
+            _ if is_token(method) => Method::Extension(method.as_bytes().to_vec()),
+            _ => return Err(invalid("invalid method")),
+        };
+        let Ok(RequestURI::Absolute {
+            scheme,
+            authority,
+            path,
+            query,
+        }) = RequestURI::convert(url)
+        else {
+            return Err(invalid("expected an absolute URL"));
+        };
+        if !scheme.eq_ignore_ascii_case(b"http") {
+            return Err(invalid("only http:// URLs are supported"));
+        }
+        let authority = String::from_utf8(authority).expect("URLs are ascii");
+        // the default port, unless the authority ends with one (or an IPv6 address)
+        let address = match authority.rsplit_once(':') {
+            Some((_, port)) if !port.contains(']') => authority.clone(),
+            _ => format!("{authority}:80"),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.append(header::HOST, &authority);
+        Ok(RequestBuilder {
+            client: self,
+            address,
+            request: Request {
+                request_line: RequestLine {
+                    method,
+                    // an empty path in absolute-form is the root
+                    request_uri: RequestURI::Origin {
+                        path: if path.is_empty() { b"/".to_vec() } else { path },
+                        query,
+                    },
+                    http_version: HttpVersion { major: 1, minor: 1 },
+                },
+                headers,
+                peer_addr: None,
+                received_at: None,
+            },
+            body: vec![],
+        })
+    }
+}
+
+impl Default for Client {
+    fn default() -> Client {
+        Client::new()
+    }
+}
+
+/// `token` from RFC 9110 section 5.6.2.
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c))
+}
+
+/// A request being built, sent with [`RequestBuilder::send`].
+pub struct RequestBuilder<'a> {
+    client: &'a Client,
+    address: String,
+    request: Request,
+    body: Vec<u8>,
+}
+
+impl RequestBuilder<'_> {
+    /// Adds a header, after the ones with the same name.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request.headers.append(name, value);
+        self
+    }
+
+    /// Sets the body, sent with a `Content-Length`.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sends the request and reads the response, skipping interim ones.
+    pub fn send(mut self) -> io::Result<Response> {
+        let method = self.request.request_line.method.clone();
+        let headers = &mut self.request.headers;
+        headers.remove(header::TRANSFER_ENCODING);
+        // a length on a `GET` without a body is allowed, but unusual
+        let bodyless =
+            matches!(&method, Method::Get) || method == Method::Extension(b"HEAD".into());
+        if !self.body.is_empty() || !bodyless {
+            headers.insert(header::CONTENT_LENGTH, self.body.len().to_string());
+        }
+
+        let mut bytes = self.request.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        self.client.pool.send(
+            &self.address,
+            &bytes,
+            &method,
+            self.client.timeout,
+            self.client.limits,
+        )
+    }
+}
+
+/// Idle connections by server address, the most recently used last.
+pub(crate) struct Pool {
+    idle: Mutex<Vec<(String, TcpStream, Instant)>>,
+}
+
+impl Pool {
+    pub(crate) const fn new() -> Pool {
+        Pool {
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sends a serialized request to `address` and reads the response with its body.
+    ///
+    /// Timeouts are `TimedOut` errors, an invalid response is `InvalidData`.
+    pub(crate) fn send(
+        &self,
+        address: &str,
+        request: &[u8],
+        method: &Method,
+        timeout: Duration,
+        limits: Limits,
+    ) -> io::Result<Response> {
+        // a request that is safe to send twice is retried once on a stale connection
+        let idempotent = match method {
+            Method::Get => true,
+            Method::Post => false,
+            Method::Extension(method) => {
+                [&b"HEAD"[..], b"PUT", b"DELETE", b"OPTIONS", b"TRACE"].contains(&method.as_slice())
+            }
+        };
+        // an idle connection keeps the timeouts of the request that opened it
+        let reused = self
+            .take(address)
+            .filter(|stream| set_timeouts(stream, timeout).is_ok());
+        if let Some(stream) = reused {
+            match exchange(&stream, request, method, limits) {
+                Ok((response, reusable)) => {
+                    if reusable {
+                        self.put(address, stream);
+                    }
+                    return Ok(response);
+                }
+                Err(Attempt::Stale(_)) if idempotent => {}
+                Err(Attempt::Stale(e) | Attempt::Failed(e)) => return Err(e),
+            }
+        }
+
+        let stream = connect(address, timeout)?;
+        match exchange(&stream, request, method, limits) {
+            Ok((response, reusable)) => {
+                if reusable {
+                    self.put(address, stream);
+                }
+                Ok(response)
+            }
+            Err(Attempt::Stale(e) | Attempt::Failed(e)) => Err(e),
+        }
+    }
+
+    fn take(&self, address: &str) -> Option<TcpStream> {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        idle.retain(|(_, _, since)| since.elapsed() < IDLE_TIMEOUT);
+        let position = idle.iter().rposition(|(a, _, _)| a == address)?;
+        Some(idle.remove(position).1)
+    }
+
+    fn put(&self, address: &str, stream: TcpStream) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.iter().filter(|(a, _, _)| a == address).count() >= MAX_IDLE {
+            let oldest = idle
+                .iter()
+                .position(|(a, _, _)| a == address)
+                .expect("the server has idle connections");
+            idle.remove(oldest);
+        }
+        idle.push((address.to_string(), stream, Instant::now()));
+    }
+}
+
+fn connect(address: &str, timeout: Duration) -> io::Result<TcpStream> {
+    let mut error = io::Error::new(io::ErrorKind::NotFound, "no address for the server");
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => {
+                set_timeouts(&stream, timeout)?;
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+fn set_timeouts(stream: &TcpStream, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))
+}
+
+enum Attempt {
+    /// Nothing was received, the server may have closed the connection before the request.
+    Stale(io::Error),
+    Failed(io::Error),
+}
+
+/// One request and response on `stream`, also tells whether it can be reused.
+fn exchange(
+    mut stream: &TcpStream,
+    request: &[u8],
+    method: &Method,
+    limits: Limits,
+) -> Result<(Response, bool), Attempt> {
+    stream.write_all(request).map_err(Attempt::Stale)?;
+
+    let mut parser = StreamParser::with_limits(Timeout(stream), limits);
+    let mut response = loop {
+        match parser.parse::<Response>() {
+            // interim responses are dropped, only a switch of protocol is final
+            Ok(response) if matches!(response.status().code(), 100 | 102..=199) => continue,
+            Ok(response) => break response,
+            Err(ParseError::Incomplete { buffered: 0 }) => {
+                return Err(Attempt::Stale(io::ErrorKind::UnexpectedEof.into()))
+            }
+            Err(ParseError::Io(e)) if e.kind() == io::ErrorKind::ConnectionReset => {
+                return Err(Attempt::Stale(e))
+            }
+            Err(e) => return Err(Attempt::Failed(e.into())),
+        }
+    };
+
+    let length = response.body_length(method);
+    let mut body = vec![];
+    parser
+        .body(length)
+        .read_to_end(&mut body)
+        .map_err(Attempt::Failed)?;
+    response.set_body(body);
+
+    let close = response
+        .headers
+        .get_field_value(header::CONNECTION)
+        .is_some_and(|value| {
+            value
+                .list()
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"close"))
+        });
+    let (unread, _) = parser.into_parts();
+    let reusable = length != BodyLength::UntilClose
+        && response.status() != Status::SwitchingProtocols
+        && response.status_line.http_version >= (HttpVersion { major: 1, minor: 1 })
+        && !close
+        // anything sent after the response would be mistaken for the next one
+        && unread.is_empty();
+    Ok((response, reusable))
+}
+
+/// Reads from a server, an expired read timeout is an error instead of a retry.
+struct Timeout<'a>(&'a TcpStream);
+
+impl Read for Timeout<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.read(buf) {
+            // the parser waits for non-blocking readers to be ready, which never ends here
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::ErrorKind::TimedOut.into()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::BufRead, net::TcpListener, thread};
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::{handle_stream, middleware::Chain, Cli};
+
+    /// Serves a single connection, so every request has to reuse it.
+    fn serve() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_stream(&Cli::parse_from(["server"]), &Chain::default(), stream).unwrap();
+        });
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn requests() {
+        let server = serve();
+        let client = Client::new();
+        let response = client.get(&format!("{server}/echo/hello")).unwrap();
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            response.headers().get("content-type"),
+            Some(&b"text/plain"[..])
+        );
+        assert_eq!(response.body(), b"hello");
+
+        let response = client
+            .request("POST", &format!("{server}/echo"))
+            .unwrap()
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("text=again")
+            .send()
+            .unwrap();
+        assert_eq!(response.body(), b"again");
+
+        let response = client
+            .request("GET", &format!("{server}/user-agent"))
+            .unwrap()
+            .header("User-Agent", "client")
+            .send()
+            .unwrap();
+        assert_eq!(response.body(), b"client");
+    }
+
+    #[test]
+    fn reused_connection_timeouts() {
+        let address = serve().trim_start_matches("http://").to_string();
+        let pool = Pool::new();
+        let request = b"GET /echo/hello HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        for seconds in [5, 1] {
+            let timeout = Duration::from_secs(seconds);
+            let response = pool
+                .send(&address, request, &Method::Get, timeout, Limits::default())
+                .unwrap();
+            assert_eq!(response.body(), b"hello");
+            let idle = pool.idle.lock().unwrap();
+            assert_eq!(idle.len(), 1);
+            assert_eq!(idle[0].1.read_timeout().unwrap(), Some(timeout));
+            assert_eq!(idle[0].1.write_timeout().unwrap(), Some(timeout));
+        }
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let client = Client::new();
+        let error = |result: io::Result<RequestBuilder>| result.err().unwrap().kind();
+        assert_eq!(
+            error(client.request("GET", "/echo")),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            error(client.request("GET", "https://localhost/")),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            error(client.request("G T", "http://localhost/")),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    /// Answers each request with the next of `responses`, each on a new connection.
+    fn stub(responses: Vec<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for (stream, response) in listener.incoming().zip(responses) {
+                let mut stream = stream.unwrap();
+                let mut reader = io::BufReader::new(&stream);
+                let mut line = String::new();
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{address}/")
+    }
+
+    #[test]
+    fn response_bodies() {
+        let server = stub(vec![
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n5\r\nhello\r\n1;ext=1\r\n!\r\n0\r\nTrailer: 1\r\n\r\n",
+            "HTTP/1.0 404 Not Found\r\n\r\nuntil close",
+        ]);
+        let client = Client::new();
+        let response = client.get(&server).unwrap();
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(response.body(), b"hello!");
+        let response = client.get(&server).unwrap();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.body(), b"until close");
+    }
+
+    #[test]
+    fn timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // accepted, never answered
+        thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(2));
+        });
+        let client = Client::new().timeout(Duration::from_millis(100));
+        let error = client.get(&format!("http://{address}/")).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn stale_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        // the first connection is closed after its response, without telling
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+            }
+        });
+        let client = Client::new();
+        let url = format!("http://{address}/");
+        assert_eq!(client.get(&url).unwrap().body(), b"ok");
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(client.get(&url).unwrap().body(), b"ok");
+    }
+}
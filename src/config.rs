@@ -0,0 +1,403 @@
+//! The TOML file given with `--config`, every setting is optional.
+//!
+//! ```toml
+//! listen = ["127.0.0.1:4221", "[::1]:4221"]
+//!
+//! [routes]
+//! metrics = true
+//! health = "/healthz"
+//! ready = "/readyz"
+//! proxy_timeout = 30
+//! proxy = [{ path = "/api", upstream = "127.0.0.1:8080" }]
+//!
+//! [mounts]
+//! "/files" = "/srv/files"
+//!
+//! [limits]
+//! request_line = 8192
+//! header_bytes = 65536
+//! headers = 100
+//! body = 10485760
+//! json = 1048576
+//!
+//! [compression]
+//! gzip = true
+//!
+//! [connections]
+//! concurrent_pipelining = false
+//!
+//! [logging]
+//! access_log = "/var/log/http/access.log"
+//! format = "combined"
+//!
+//! # with the tls feature
+//! [tls]
+//! certificates = [{ cert = "/etc/http/cert.pem", key = "/etc/http/key.pem" }]
+//! ```
+//!
+//! Arguments given on the command line take precedence over the file, a
+//! setting turned on in the file is turned off with its `--no-` flag. The
+//! file is read again on `SIGHUP`, and applied to the connections accepted
+//! afterwards when it is valid.
+
+use std::{collections::BTreeMap, fs, io, path::PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::{access_log::LogFormat, proxy::ProxyRule, Cli};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("cannot read the config file: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid config file: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid config file: {0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    listen: Option<Vec<String>>,
+    routes: Routes,
+    /// Directories by the path they are served under.
+    mounts: BTreeMap<String, PathBuf>,
+    limits: Limits,
+    compression: Compression,
+    connections: Connections,
+    logging: Logging,
+    #[cfg(feature = "tls")]
+    tls: Tls,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Routes {
+    metrics: Option<bool>,
+    health: Option<String>,
+    ready: Option<String>,
+    proxy: Option<Vec<Proxy>>,
+    proxy_timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Proxy {
+    path: String,
+    upstream: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Limits {
+    request_line: Option<usize>,
+    header_bytes: Option<usize>,
+    headers: Option<usize>,
+    body: Option<usize>,
+    json: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Compression {
+    gzip: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Connections {
+    concurrent_pipelining: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Logging {
+    access_log: Option<PathBuf>,
+    format: Option<String>,
+}
+
+#[cfg(feature = "tls")]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Tls {
+    certificates: Option<Vec<Certificate>>,
+}
+
+#[cfg(feature = "tls")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Certificate {
+    cert: PathBuf,
+    key: PathBuf,
+}
+
+impl Config {
+    pub fn load(path: &std::path::Path) -> Result<Config, ConfigError> {
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Sets the settings of `cli` the command line left to their default.
+    pub fn apply(self, cli: &mut Cli) -> Result<(), ConfigError> {
+        let explicit = cli.explicit.clone();
+        let set = |id: &str| explicit.iter().any(|given| given == id);
+        // the value of the file, unless the argument `id` was given
+        fn fill<T>(field: &mut T, value: Option<T>, given: bool) {
+            if let (Some(value), false) = (value, given) {
+                *field = value;
+            }
+        }
+
+        fill(&mut cli.listen, self.listen, set("listen"));
+
+        let proxy = match self.routes.proxy {
+            Some(rules) => Some(
+                rules
+                    .into_iter()
+                    .map(|rule| format!("{}={}", rule.path, rule.upstream).parse::<ProxyRule>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(ConfigError::Invalid)?,
+            ),
+            None => None,
+        };
+        fill(&mut cli.proxy, proxy, set("proxy"));
+        fill(
+            &mut cli.proxy_timeout,
+            self.routes.proxy_timeout,
+            set("proxy_timeout"),
+        );
+        fill(
+            &mut cli.metrics,
+            self.routes.metrics,
+            set("metrics") || set("no_metrics"),
+        );
+        fill(&mut cli.health_path, self.routes.health, set("health_path"));
+        fill(&mut cli.ready_path, self.routes.ready, set("ready_path"));
+
+        for (path, directory) in self.mounts {
+            // the only route serving a directory
+            if path.trim_end_matches('/') != "/files" {
+                return Err(ConfigError::Invalid(format!(
+                    "cannot mount {path}, only /files serves a directory"
+                )));
+            }
+            fill(&mut cli.directory, Some(Some(directory)), set("directory"));
+        }
+
+        let limits = self.limits;
+        fill(
+            &mut cli.max_request_line,
+            limits.request_line,
+            set("max_request_line"),
+        );
+        fill(
+            &mut cli.max_header_bytes,
+            limits.header_bytes,
+            set("max_header_bytes"),
+        );
+        fill(&mut cli.max_headers, limits.headers, set("max_headers"));
+        fill(&mut cli.max_body, limits.body, set("max_body"));
+        fill(&mut cli.max_json_body, limits.json, set("max_json_body"));
+
+        fill(
+            &mut cli.no_compression,
+            self.compression.gzip.map(|gzip| !gzip),
+            set("no_compression") || set("compression"),
+        );
+        fill(
+            &mut cli.concurrent_pipelining,
+            self.connections.concurrent_pipelining,
+            set("concurrent_pipelining") || set("no_concurrent_pipelining"),
+        );
+
+        let format = match self.logging.format {
+            Some(format) => Some(
+                LogFormat::from_str(&format, true)
+                    .map_err(|_| ConfigError::Invalid(format!("unknown log format {format:?}")))?,
+            ),
+            None => None,
+        };
+        fill(&mut cli.access_log_format, format, set("access_log_format"));
+        fill(
+            &mut cli.access_log,
+            self.logging.access_log.map(Some),
+            set("access_log"),
+        );
+
+        // the certificates of the file are replaced as a whole by the arguments
+        #[cfg(feature = "tls")]
+        if let (Some(certificates), false) =
+            (self.tls.certificates, set("tls_cert") || set("tls_key"))
+        {
+            (cli.tls_cert, cli.tls_key) = certificates
+                .into_iter()
+                .map(|certificate| (certificate.cert, certificate.key))
+                .unzip();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use clap::CommandFactory;
+
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        let matches = Cli::command()
+            .try_get_matches_from(["server"].iter().chain(args))
+            .unwrap();
+        Cli::from_matches(&matches).unwrap()
+    }
+
+    fn apply(args: &[&str], config: &str) -> Result<Cli, ConfigError> {
+        let mut cli = cli(args);
+        toml::from_str::<Config>(config)?.apply(&mut cli)?;
+        Ok(cli)
+    }
+
+    const CONFIG: &str = r#"
+listen = ["127.0.0.1:8080", "127.0.0.1:8081"]
+
+[routes]
+metrics = true
+proxy = [{ path = "/api", upstream = "127.0.0.1:9000" }]
+
+[mounts]
+"/files/" = "/srv/files"
+
+[limits]
+body = 1024
+
+[compression]
+gzip = false
+
+[connections]
+concurrent_pipelining = true
+
+[logging]
+format = "json"
+"#;
+
+    #[test]
+    fn settings() {
+        let cli = apply(&[], CONFIG).unwrap();
+        assert_eq!(cli.listen(), ["127.0.0.1:8080", "127.0.0.1:8081"]);
+        assert!(cli.metrics);
+        assert_eq!(
+            cli.proxies().rules,
+            vec!["/api=127.0.0.1:9000".parse().unwrap()]
+        );
+        assert_eq!(cli.proxies().timeout, Duration::from_secs(30));
+        assert_eq!(cli.directory, Some("/srv/files".into()));
+        assert_eq!(cli.limits().body, 1024);
+        assert_eq!(cli.limits().header_count, 100);
+        assert!(cli.no_compression);
+        assert!(cli.concurrent_pipelining);
+        assert_eq!(cli.access_log_format, LogFormat::Json);
+        assert_eq!(cli.access_log, None);
+
+        // an empty file changes nothing
+        let cli = apply(&[], "").unwrap();
+        assert_eq!(cli.listen(), ["127.0.0.1:4221"]);
+        assert!(!cli.no_compression);
+    }
+
+    #[test]
+    fn command_line_precedence() {
+        let cli = apply(
+            &[
+                "--listen",
+                "127.0.0.1:9999",
+                "--max-body",
+                "10",
+                "--directory",
+                "/tmp",
+                "--access-log-format",
+                "common",
+            ],
+            CONFIG,
+        )
+        .unwrap();
+        assert_eq!(cli.listen(), ["127.0.0.1:9999"]);
+        assert_eq!(cli.limits().body, 10);
+        assert_eq!(cli.directory, Some("/tmp".into()));
+        assert_eq!(cli.access_log_format, LogFormat::Common);
+        // the other settings still come from the file
+        assert!(cli.metrics);
+        assert!(cli.no_compression);
+    }
+
+    #[test]
+    fn command_line_turns_off() {
+        let cli = apply(
+            &[
+                "--no-metrics",
+                "--compression",
+                "--no-concurrent-pipelining",
+            ],
+            CONFIG,
+        )
+        .unwrap();
+        assert!(!cli.metrics);
+        assert!(!cli.no_compression);
+        assert!(!cli.concurrent_pipelining);
+
+        // the last one of a pair wins
+        let cli = apply(&["--no-metrics", "--metrics"], "").unwrap();
+        assert!(cli.metrics);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls() {
+        let config = "[tls]\ncertificates = [{ cert = \"a.pem\", key = \"a.key\" }, \
+            { cert = \"b.pem\", key = \"b.key\" }]";
+        let cli = apply(&[], config).unwrap();
+        assert_eq!(cli.tls_cert, [PathBuf::from("a.pem"), "b.pem".into()]);
+        assert_eq!(cli.tls_key, [PathBuf::from("a.key"), "b.key".into()]);
+
+        let cli = apply(&["--tls-cert", "c.pem", "--tls-key", "c.key"], config).unwrap();
+        assert_eq!(cli.tls_cert, [PathBuf::from("c.pem")]);
+        assert_eq!(cli.tls_key, [PathBuf::from("c.key")]);
+    }
+
+    #[test]
+    fn invalid() {
+        let invalid = |config| apply(&[], config).err().unwrap().to_string();
+        assert!(invalid("lisen = []").contains("unknown field `lisen`"));
+        assert!(invalid("[limits]\nbody = \"large\"").starts_with("invalid config file"));
+        assert_eq!(
+            invalid("[mounts]\n\"/static\" = \"/srv\""),
+            "invalid config file: cannot mount /static, only /files serves a directory"
+        );
+        assert!(
+            invalid("[routes]\nproxy = [{ path = \"api\", upstream = \"a:1\" }]")
+                .contains("does not start with /")
+        );
+        assert_eq!(
+            invalid("[logging]\nformat = \"xml\""),
+            "invalid config file: unknown log format \"xml\""
+        );
+    }
+
+    #[test]
+    fn with_config() {
+        let path = std::env::temp_dir().join(format!("config-{}.toml", std::process::id()));
+        fs::write(&path, "[limits]\nheaders = 7\n").unwrap();
+        let cli = cli(&["--config", path.to_str().unwrap()])
+            .with_config()
+            .unwrap();
+        assert_eq!(cli.limits().header_count, 7);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            self::cli(&["--config", path.to_str().unwrap()]).with_config(),
+            Err(ConfigError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    thread::{self, ScopedJoinHandle},
+    time::Instant,
+};
+
+use crate::{
+    event_stream::EventStream,
+    h2::{self, Upgrade},
+    handle_expect, handle_parse_error, handle_request, header,
+    metrics::{CountReceived, METRICS},
+    middleware::{Chain, Rejected},
+    parser::{ParseError, StreamParser},
+    reject,
+    request::Handler,
+    websocket::WebSocket,
+    BodyLength, Cli, Request, RequestURI, ServerResponse, Status,
+};
+
+/// A connection read by one thread while another one writes to it.
+pub trait Transport: Sync {
+    fn receive(&self, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn send(&self, data: &[u8]) -> io::Result<()>;
+
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Whether the connection is over TLS, which rules out `Upgrade: h2c`.
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+
+    /// Closes both directions, which also wakes up a blocked `receive`.
+    fn close(&self);
+}
+
+impl Transport for TcpStream {
+    fn receive(&self, buf: &mut [u8]) -> io::Result<usize> {
+        (&mut &*self).read(buf)
+    }
+
+    fn send(&self, data: &[u8]) -> io::Result<()> {
+        (&mut &*self).write_all(data)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+/// Requests of one connection handled at the same time with `--concurrent-pipelining`,
+/// later ones wait until the oldest is done.
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// The protocol an HTTP/1.1 connection switches to.
+enum Switch {
+    Http2(Upgrade),
+    /// Served by the route of the request that was upgraded.
+    WebSocket(RequestURI),
+    /// The rest of a `text/event-stream` response, written by the route of the request.
+    EventStream {
+        request_uri: RequestURI,
+        last_event_id: Option<Vec<u8>>,
+    },
+}
+
+/// An HTTP/1 connection waiting for its next request, which middleware may close
+/// until the request starts arriving.
+#[derive(Debug, Default)]
+pub struct IdleConnection {
+    /// Where the response to the awaited request goes, while nothing was received.
+    waiting: Mutex<Option<mpsc::Sender<ServerResponse>>>,
+}
+
+impl IdleConnection {
+    /// Closes the connection once the responses to earlier requests are written, unless
+    /// the next request started arriving in the meantime.
+    pub fn close(&self) {
+        if let Some(responses) = self.lock().take() {
+            let _ = responses.send(ServerResponse::Close(vec![]));
+        }
+    }
+
+    fn wait(&self, responses: &mpsc::Sender<ServerResponse>) {
+        *self.lock() = Some(responses.clone());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<mpsc::Sender<ServerResponse>>> {
+        self.waiting.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reads a shared transport, the connection is no longer idle once something arrived.
+struct Receiver<'a, T> {
+    transport: &'a T,
+    idle: &'a IdleConnection,
+}
+
+impl<T: Transport> Read for Receiver<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.transport.receive(buf)?;
+        if read > 0 {
+            self.idle.lock().take();
+        }
+        Ok(read)
+    }
+}
+
+/// Serves every request sent on `stream` until either side closes it, each one
+/// going through the middleware of `chain`.
+///
+/// One parser is kept for the whole connection, so pipelined requests already
+/// buffered are not lost. Responses are queued in request order and written by
+/// a dedicated thread, which lets bodyless requests be handled concurrently
+/// when `--concurrent-pipelining` is set.
+///
+/// Connections starting with the HTTP/2 preface, and the ones upgraded with
+/// `Upgrade: h2c`, are served as HTTP/2 instead. A route accepting a WebSocket
+/// handshake takes the connection over once its response is written.
+pub fn handle_stream(cli: &Cli, chain: &Chain, stream: impl Transport) -> io::Result<()> {
+    let idle = Arc::new(IdleConnection::default());
+    let receiver = Receiver {
+        transport: &stream,
+        idle: &idle,
+    };
+    let mut parser = StreamParser::with_limits(CountReceived(receiver), cli.limits());
+
+    if parser.starts_with(h2::PREFACE)? {
+        let (buffered, reader) = parser.into_parts();
+        return h2::serve(cli, chain, &stream, buffered.as_slice().chain(reader), None);
+    }
+
+    let switch = serve_http1(cli, chain, &stream, &mut parser, &idle)?;
+    let (buffered, reader) = parser.into_parts();
+    match switch {
+        Some(Switch::Http2(upgrade)) => h2::serve(
+            cli,
+            chain,
+            &stream,
+            buffered.as_slice().chain(reader),
+            Some(upgrade),
+        ),
+        Some(Switch::WebSocket(request_uri)) => {
+            let reader = io::Cursor::new(buffered).chain(reader);
+            Handler::websocket(
+                &request_uri,
+                WebSocket::new(&stream, reader, cli.limits().body),
+            )
+        }
+        Some(Switch::EventStream {
+            request_uri,
+            last_event_id,
+        }) => {
+            let served = Handler::event_stream(
+                &request_uri,
+                EventStream::new(&stream, last_event_id.as_deref()),
+            );
+            // the end of the body
+            stream.close();
+            served
+        }
+        None => Ok(()),
+    }
+}
+
+/// Serves HTTP/1 requests until the connection ends or switches to another protocol.
+fn serve_http1<R: Read>(
+    cli: &Cli,
+    chain: &Chain,
+    stream: &impl Transport,
+    parser: &mut StreamParser<R>,
+    idle: &Arc<IdleConnection>,
+) -> io::Result<Option<Switch>> {
+    thread::scope(|scope| {
+        // each request gets its own channel, an interim response may precede the final one
+        let (queue, pending) = mpsc::sync_channel(MAX_CONCURRENT_REQUESTS);
+        let writer = scope.spawn(|| write_responses(stream, pending));
+        let mut switch = None;
+        // whether each request handled concurrently closed the connection, oldest first
+        let mut running: VecDeque<ScopedJoinHandle<bool>> = VecDeque::new();
+
+        loop {
+            if closed(&mut running, |running| {
+                running.front().is_some_and(|handle| handle.is_finished())
+            }) {
+                break;
+            }
+
+            let (responses, receiver) = mpsc::channel();
+            if queue.send(receiver).is_err() {
+                // the writer is gone, the connection is closing
+                break;
+            }
+            // a pipelined request is already there
+            if !parser.is_buffered() {
+                idle.wait(&responses);
+                chain.idle(idle);
+            }
+
+            let mut request = match parser.parse::<Request>() {
+                Ok(request) => request,
+                // the client closed the connection between requests
+                Err(ParseError::Incomplete { buffered: 0 }) => break,
+                Err(e) => {
+                    // the access log records the rejected request
+                    if let Some(status) = handle_parse_error(&e) {
+                        let request = Rejected::Unparsed(stream.peer_addr().ok());
+                        let _ = responses.send(reject(chain, request, status));
+                    }
+                    break;
+                }
+            };
+
+            request.peer_addr = stream.peer_addr().ok();
+            request.received_at = Some(Instant::now());
+
+            // h2c is only for cleartext, over TLS HTTP/2 is negotiated with ALPN
+            let settings = h2::upgrade_settings(&request).filter(|_| !stream.is_encrypted());
+            if let Some(settings) = settings {
+                let _ = responses.send(ServerResponse::Continue(Upgrade::switching_protocols()));
+                switch = Some(Switch::Http2(Upgrade::new(request, settings)));
+                break;
+            }
+
+            match handle_expect(cli, chain, &request) {
+                Some(Status::Continue) => {
+                    let _ = responses.send(Handler::interim(&request));
+                }
+                Some(status) => {
+                    let _ = responses.send(reject(chain, Rejected::Request(&request), status));
+                    break;
+                }
+                None => {}
+            }
+
+            let length = request.body_length();
+            // no request can be read after one taking the connection over
+            let switching = Handler::may_switch(&request, &cli.proxies());
+            // nothing may run after a request closing the connection
+            let concurrent = cli.concurrent_pipelining
+                && length == BodyLength::Length(0)
+                && !switching
+                && !closes(&request);
+            if concurrent {
+                if closed(&mut running, |running| {
+                    running.len() >= MAX_CONCURRENT_REQUESTS
+                }) {
+                    break;
+                }
+                running.push_back(scope.spawn(move || {
+                    let resp = handle_request(cli.clone(), chain, request, &mut io::empty());
+                    let close = matches!(resp, ServerResponse::Close(_));
+                    let _ = responses.send(resp);
+                    close
+                }));
+            } else {
+                // the requests before may have closed the connection
+                if closed(&mut running, |running| !running.is_empty()) {
+                    break;
+                }
+                let request_uri = request.request_line.request_uri.clone();
+                let last_event_id = request
+                    .headers
+                    .get(header::LAST_EVENT_ID)
+                    .map(<[u8]>::to_vec);
+                let mut body = parser.body(length);
+                let resp = handle_request(cli.clone(), chain, request, &mut body);
+                let next = match resp {
+                    ServerResponse::Upgrade(_) => Some(Switch::WebSocket(request_uri)),
+                    ServerResponse::Stream(_) => Some(Switch::EventStream {
+                        request_uri,
+                        last_event_id,
+                    }),
+                    _ => None,
+                };
+                let close = matches!(resp, ServerResponse::Close(_));
+                let _ = responses.send(resp);
+                // leave the stream at the start of the next request
+                if close || body.discard().is_err() {
+                    break;
+                }
+                if next.is_some() {
+                    switch = next;
+                    break;
+                }
+            }
+        }
+
+        drop(queue);
+        writer.join().expect("response writer panicked")?;
+        Ok(switch)
+    })
+}
+
+/// Waits for the oldest concurrent requests while `wait` holds, returns whether one of
+/// them closed the connection.
+fn closed(
+    running: &mut VecDeque<ScopedJoinHandle<bool>>,
+    wait: impl Fn(&VecDeque<ScopedJoinHandle<bool>>) -> bool,
+) -> bool {
+    while wait(running) {
+        let handle = running.pop_front().expect("waiting on a running request");
+        if handle.join().expect("request handler panicked") {
+            return true;
+        }
+    }
+    false
+}
+
+/// Whether the client asked for the connection to be closed after this request.
+fn closes(request: &Request) -> bool {
+    request
+        .headers
+        .get_field_value(header::CONNECTION)
+        .is_some_and(|connection| {
+            connection
+                .list()
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"close"))
+        })
+}
+
+fn write_responses(
+    stream: &impl Transport,
+    pending: mpsc::Receiver<mpsc::Receiver<ServerResponse>>,
+) -> io::Result<()> {
+    for responses in pending {
+        for resp in responses {
+            let written = stream.send(resp.data());
+            if written.is_ok() {
+                METRICS.record_sent(resp.data().len());
+            }
+            if written.is_err() || matches!(resp, ServerResponse::Close(_)) {
+                // also wakes up the reader blocked on the socket
+                stream.close();
+                return written;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Server-Sent Events, the `text/event-stream` format of the HTML standard.
+//!
+//! A route answering with that content type gets the connection once the
+//! response head is written, and writes events as they happen. The body is
+//! delimited by closing the connection.
+
+use std::{
+    io,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bytes::ToBytes,
+    connection::Transport,
+    metrics::METRICS,
+    spec::{
+        header,
+        message::parameters,
+        response::{Response, Status},
+    },
+};
+
+pub const CONTENT_TYPE: &str = "text/event-stream";
+/// How long a stream stays silent before a comment is sent, so proxies keep it open.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A message of an event stream, `data` is sent as one line per line.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Event {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    pub fn new(data: impl Into<String>) -> Event {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    /// Sets the event type, `message` when unset. Line breaks are removed.
+    pub fn event(mut self, event: &str) -> Event {
+        self.event = Some(event.replace(['\r', '\n'], ""));
+        self
+    }
+
+    /// Sets the id sent back in `Last-Event-ID` on reconnection.
+    pub fn id(mut self, id: &str) -> Event {
+        self.id = Some(id.replace(['\r', '\n', '\0'], ""));
+        self
+    }
+
+    /// Sets how long the client waits before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Event {
+        self.retry = Some(retry);
+        self
+    }
+}
+
+impl ToBytes for Event {
+    fn into_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![];
+        if let Some(event) = self.event {
+            bytes.extend_from_slice(format!("event: {event}\n").as_bytes());
+        }
+        if let Some(id) = self.id {
+            bytes.extend_from_slice(format!("id: {id}\n").as_bytes());
+        }
+        if let Some(retry) = self.retry {
+            bytes.extend_from_slice(format!("retry: {}\n", retry.as_millis()).as_bytes());
+        }
+        for line in lines(&self.data) {
+            bytes.extend_from_slice(format!("data: {line}\n").as_bytes());
+        }
+        bytes.push(b'\n');
+        bytes
+    }
+}
+
+/// Splits on any of the line breaks of the format.
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split("\r\n").flat_map(|line| line.split(['\r', '\n']))
+}
+
+/// Whether the body of `response` is an event stream written after its head.
+pub(crate) fn is_event_stream(response: &Response) -> bool {
+    response.status() == Status::OK
+        && response
+            .headers
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|value| {
+                parameters(value)
+                    .0
+                    .eq_ignore_ascii_case(CONTENT_TYPE.as_bytes())
+            })
+}
+
+/// The body of an event stream response, written as events happen.
+///
+/// Idle streams get a comment every 15 seconds, which is also how a client
+/// that went away is noticed.
+pub struct EventStream<'a> {
+    transport: &'a dyn Transport,
+    last_event_id: Option<String>,
+    keep_alive: Duration,
+    last_sent: Instant,
+}
+
+impl<'a> EventStream<'a> {
+    pub(crate) fn new(
+        transport: &'a dyn Transport,
+        last_event_id: Option<&[u8]>,
+    ) -> EventStream<'a> {
+        EventStream {
+            transport,
+            last_event_id: last_event_id.map(|id| String::from_utf8_lossy(id).into_owned()),
+            keep_alive: KEEP_ALIVE,
+            last_sent: Instant::now(),
+        }
+    }
+
+    /// The id of the last event a reconnecting client received, to resume after it.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub fn set_keep_alive(&mut self, keep_alive: Duration) {
+        self.keep_alive = keep_alive;
+    }
+
+    pub fn send(&mut self, event: Event) -> io::Result<()> {
+        self.write(&event.into_bytes())
+    }
+
+    /// Sends a comment, which clients ignore.
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        let mut bytes = vec![];
+        for line in lines(text) {
+            bytes.extend_from_slice(format!(": {line}\n").as_bytes());
+        }
+        bytes.push(b'\n');
+        self.write(&bytes)
+    }
+
+    /// Waits for `duration`, keeping the stream alive meanwhile.
+    pub fn pause(&mut self, duration: Duration) -> io::Result<()> {
+        let end = Instant::now() + duration;
+        loop {
+            let now = Instant::now();
+            if now >= end {
+                return Ok(());
+            }
+            let keep_alive_at = self.last_sent + self.keep_alive;
+            if now >= keep_alive_at {
+                self.comment("keep-alive")?;
+                continue;
+            }
+            thread::sleep(end.min(keep_alive_at) - now);
+        }
+    }
+
+    /// Sends the events of `events` until every sender is gone, keeping the stream alive meanwhile.
+    pub fn forward(&mut self, events: &mpsc::Receiver<Event>) -> io::Result<()> {
+        loop {
+            let idle = (self.last_sent + self.keep_alive).saturating_duration_since(Instant::now());
+            match events.recv_timeout(idle) {
+                Ok(event) => self.send(event)?,
+                Err(RecvTimeoutError::Timeout) => self.comment("keep-alive")?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.transport.send(bytes)?;
+        METRICS.record_sent(bytes.len());
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::{handle_stream, middleware::Chain, Cli};
+
+    #[test]
+    fn events() {
+        assert_eq!(Event::new("hello").into_bytes(), b"data: hello\n\n");
+        assert_eq!(
+            Event::new("a\nb\r\nc")
+                .event("tick")
+                .id("7")
+                .retry(Duration::from_secs(2))
+                .into_bytes(),
+            b"event: tick\nid: 7\nretry: 2000\ndata: a\ndata: b\ndata: c\n\n"
+        );
+        // a line break would start another field
+        assert_eq!(
+            Event::new("").event("a\nid: 1").into_bytes(),
+            b"event: aid: 1\ndata: \n\n"
+        );
+    }
+
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (listener.accept().unwrap().0, client)
+    }
+
+    #[test]
+    fn keep_alive() {
+        let (server, mut client) = pair();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(150));
+            sender.send(Event::new("late").id("1")).unwrap();
+        });
+
+        let mut stream = EventStream::new(&server, Some(b"0"));
+        assert_eq!(stream.last_event_id(), Some("0"));
+        stream.set_keep_alive(Duration::from_millis(100));
+        stream.forward(&receiver).unwrap();
+        drop(server);
+
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        assert_eq!(received, ": keep-alive\n\nid: 1\ndata: late\n\n");
+    }
+
+    #[test]
+    fn resume_counter() {
+        let (server, mut client) = pair();
+        thread::spawn(move || {
+            let cli = Cli::parse_from(["server"]);
+            handle_stream(&cli, &Chain::default(), server).unwrap();
+        });
+        client
+            .write_all(b"GET /events/counter?count=3&interval=10 HTTP/1.1\r\nAccept-Encoding: gzip\r\nLast-Event-ID: 0\r\n\r\n")
+            .unwrap();
+
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        let (head, body) = received.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: text/event-stream\r\n"));
+        assert!(head.ends_with("Connection: close"));
+        assert!(!head.contains("Content-Length") && !head.contains("Content-Encoding"));
+        assert_eq!(
+            body,
+            "event: tick\nid: 1\ndata: 1\n\nevent: tick\nid: 2\ndata: 2\n\n"
+        );
+    }
+}
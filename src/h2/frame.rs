@@ -0,0 +1,466 @@
+use std::io::Read;
+
+use super::Error;
+
+/// The client connection preface, RFC 9113 section 3.4.
+pub(crate) const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Largest frame payload until the peer allows more with `SETTINGS_MAX_FRAME_SIZE`.
+pub(super) const DEFAULT_MAX_FRAME_SIZE: usize = 16 * 1024;
+/// Largest valid `SETTINGS_MAX_FRAME_SIZE`.
+pub(super) const MAX_FRAME_SIZE_LIMIT: u32 = (1 << 24) - 1;
+/// Largest flow control window.
+pub(super) const MAX_WINDOW: i64 = (1 << 31) - 1;
+/// Window of a connection or stream before any `WINDOW_UPDATE`.
+pub(super) const DEFAULT_WINDOW: i64 = 65_535;
+
+const HEADER_LENGTH: usize = 9;
+
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+/// Error codes of RFC 9113 section 7.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum ErrorCode {
+    ProtocolError = 0x1,
+    FlowControlError = 0x3,
+    StreamClosed = 0x5,
+    FrameSizeError = 0x6,
+    RefusedStream = 0x7,
+    CompressionError = 0x9,
+    EnhanceYourCalm = 0xb,
+}
+
+/// Identifiers of the SETTINGS parameters, RFC 9113 section 6.5.2.
+pub(super) mod setting {
+    pub const ENABLE_PUSH: u16 = 0x2;
+    pub const MAX_CONCURRENT_STREAMS: u16 = 0x3;
+    pub const INITIAL_WINDOW_SIZE: u16 = 0x4;
+    pub const MAX_FRAME_SIZE: u16 = 0x5;
+    pub const MAX_HEADER_LIST_SIZE: u16 = 0x6;
+}
+
+/// A frame without its padding. Clients cannot push, so `PUSH_PROMISE` is an error.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Frame {
+    Data {
+        stream: u32,
+        data: Vec<u8>,
+        /// Padding length including its length byte, it counts against flow control.
+        padding: usize,
+        end_stream: bool,
+    },
+    Headers {
+        stream: u32,
+        block: Vec<u8>,
+        end_stream: bool,
+        end_headers: bool,
+    },
+    Priority {
+        stream: u32,
+    },
+    RstStream {
+        stream: u32,
+        code: u32,
+    },
+    Settings {
+        ack: bool,
+        settings: Vec<(u16, u32)>,
+    },
+    Ping {
+        ack: bool,
+        data: [u8; 8],
+    },
+    GoAway {
+        last_stream: u32,
+        code: u32,
+    },
+    WindowUpdate {
+        stream: u32,
+        increment: u32,
+    },
+    Continuation {
+        stream: u32,
+        block: Vec<u8>,
+        end_headers: bool,
+    },
+    /// Frames of unknown types are ignored.
+    Unknown {
+        kind: u8,
+    },
+}
+
+impl Frame {
+    /// Reads the next frame, larger payloads than `max_size` are a connection error.
+    pub fn read(reader: &mut impl Read, max_size: usize) -> Result<Frame, Error> {
+        let mut header = [0; HEADER_LENGTH];
+        reader.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+        let (kind, flags) = (header[3], header[4]);
+        let stream = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & !(1 << 31);
+        if length > max_size {
+            return Err(Error::Connection(ErrorCode::FrameSizeError));
+        }
+        let mut payload = vec![0; length];
+        reader.read_exact(&mut payload)?;
+
+        let on_stream = || match stream {
+            0 => Err(Error::Connection(ErrorCode::ProtocolError)),
+            _ => Ok(stream),
+        };
+        let on_connection = || match stream {
+            0 => Ok(()),
+            _ => Err(Error::Connection(ErrorCode::ProtocolError)),
+        };
+        let frame = match kind {
+            0x0 => {
+                let stream = on_stream()?;
+                let (data, padding) = unpad(payload, flags)?;
+                Frame::Data {
+                    stream,
+                    data,
+                    padding,
+                    end_stream: flags & END_STREAM != 0,
+                }
+            }
+            0x1 => {
+                let stream = on_stream()?;
+                let (mut block, _) = unpad(payload, flags)?;
+                if flags & PRIORITY != 0 {
+                    if block.len() < 5 {
+                        return Err(Error::Connection(ErrorCode::FrameSizeError));
+                    }
+                    block.drain(..5);
+                }
+                Frame::Headers {
+                    stream,
+                    block,
+                    end_stream: flags & END_STREAM != 0,
+                    end_headers: flags & END_HEADERS != 0,
+                }
+            }
+            0x2 => {
+                let stream = on_stream()?;
+                check_length(&payload, 5)?;
+                Frame::Priority { stream }
+            }
+            0x3 => {
+                let stream = on_stream()?;
+                check_length(&payload, 4)?;
+                Frame::RstStream {
+                    stream,
+                    code: be_u32(&payload),
+                }
+            }
+            0x4 => {
+                on_connection()?;
+                let ack = flags & ACK != 0;
+                if payload.len() % 6 != 0 || ack && !payload.is_empty() {
+                    return Err(Error::Connection(ErrorCode::FrameSizeError));
+                }
+                let settings = payload
+                    .chunks(6)
+                    .map(|setting| {
+                        (
+                            u16::from_be_bytes([setting[0], setting[1]]),
+                            be_u32(&setting[2..]),
+                        )
+                    })
+                    .collect();
+                Frame::Settings { ack, settings }
+            }
+            0x5 => return Err(Error::Connection(ErrorCode::ProtocolError)),
+            0x6 => {
+                on_connection()?;
+                check_length(&payload, 8)?;
+                Frame::Ping {
+                    ack: flags & ACK != 0,
+                    data: payload.try_into().expect("length checked"),
+                }
+            }
+            0x7 => {
+                on_connection()?;
+                if payload.len() < 8 {
+                    return Err(Error::Connection(ErrorCode::FrameSizeError));
+                }
+                Frame::GoAway {
+                    last_stream: be_u32(&payload) & !(1 << 31),
+                    code: be_u32(&payload[4..]),
+                }
+            }
+            0x8 => {
+                check_length(&payload, 4)?;
+                Frame::WindowUpdate {
+                    stream,
+                    increment: be_u32(&payload) & !(1 << 31),
+                }
+            }
+            0x9 => Frame::Continuation {
+                stream: on_stream()?,
+                block: payload,
+                end_headers: flags & END_HEADERS != 0,
+            },
+            kind => Frame::Unknown { kind },
+        };
+        Ok(frame)
+    }
+
+    /// Appends the frame to `out`, headers are not split into continuations here.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+        match self {
+            Frame::Data {
+                stream,
+                data,
+                padding,
+                end_stream,
+            } => {
+                let flags = flag(*end_stream, END_STREAM) | flag(*padding > 0, PADDED);
+                header(out, data.len() + padding, 0x0, flags, *stream);
+                if *padding > 0 {
+                    out.push((padding - 1) as u8);
+                }
+                out.extend_from_slice(data);
+                out.resize(out.len() + padding.saturating_sub(1), 0);
+            }
+            Frame::Headers {
+                stream,
+                block,
+                end_stream,
+                end_headers,
+            } => {
+                let flags = flag(*end_stream, END_STREAM) | flag(*end_headers, END_HEADERS);
+                header(out, block.len(), 0x1, flags, *stream);
+                out.extend_from_slice(block);
+            }
+            Frame::Priority { stream } => {
+                header(out, 5, 0x2, 0, *stream);
+                // no dependency and the default weight of 16
+                out.extend_from_slice(&[0, 0, 0, 0, 15]);
+            }
+            Frame::RstStream { stream, code } => {
+                header(out, 4, 0x3, 0, *stream);
+                out.extend_from_slice(&code.to_be_bytes());
+            }
+            Frame::Settings { ack, settings } => {
+                header(out, settings.len() * 6, 0x4, flag(*ack, ACK), 0);
+                for (id, value) in settings {
+                    out.extend_from_slice(&id.to_be_bytes());
+                    out.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            Frame::Ping { ack, data } => {
+                header(out, 8, 0x6, flag(*ack, ACK), 0);
+                out.extend_from_slice(data);
+            }
+            Frame::GoAway { last_stream, code } => {
+                header(out, 8, 0x7, 0, 0);
+                out.extend_from_slice(&last_stream.to_be_bytes());
+                out.extend_from_slice(&code.to_be_bytes());
+            }
+            Frame::WindowUpdate { stream, increment } => {
+                header(out, 4, 0x8, 0, *stream);
+                out.extend_from_slice(&increment.to_be_bytes());
+            }
+            Frame::Continuation {
+                stream,
+                block,
+                end_headers,
+            } => {
+                header(
+                    out,
+                    block.len(),
+                    0x9,
+                    flag(*end_headers, END_HEADERS),
+                    *stream,
+                );
+                out.extend_from_slice(block);
+            }
+            Frame::Unknown { kind } => header(out, 0, *kind, 0, 0),
+        }
+    }
+}
+
+/// Parses a SETTINGS payload given without a frame header, like in `HTTP2-Settings`.
+pub(super) fn settings_payload(payload: &[u8]) -> Option<Vec<(u16, u32)>> {
+    let mut frame = vec![];
+    header(&mut frame, payload.len(), 0x4, 0, 0);
+    frame.extend_from_slice(payload);
+    match Frame::read(&mut &frame[..], payload.len()) {
+        Ok(Frame::Settings { settings, .. }) => Some(settings),
+        _ => None,
+    }
+}
+
+fn header(out: &mut Vec<u8>, length: usize, kind: u8, flags: u8, stream: u32) {
+    out.extend_from_slice(&(length as u32).to_be_bytes()[1..]);
+    out.extend_from_slice(&[kind, flags]);
+    out.extend_from_slice(&stream.to_be_bytes());
+}
+
+/// Removes the padding of a frame with the `PADDED` flag, returns the padding length.
+fn unpad(mut payload: Vec<u8>, flags: u8) -> Result<(Vec<u8>, usize), Error> {
+    if flags & PADDED == 0 {
+        return Ok((payload, 0));
+    }
+    let Some(&pad_length) = payload.first() else {
+        return Err(Error::Connection(ErrorCode::FrameSizeError));
+    };
+    let padding = pad_length as usize + 1;
+    if padding > payload.len() {
+        return Err(Error::Connection(ErrorCode::ProtocolError));
+    }
+    payload.truncate(payload.len() - pad_length as usize);
+    payload.remove(0);
+    Ok((payload, padding))
+}
+
+fn check_length(payload: &[u8], length: usize) -> Result<(), Error> {
+    match payload.len() == length {
+        true => Ok(()),
+        false => Err(Error::Connection(ErrorCode::FrameSizeError)),
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(frame: Frame) {
+        let mut encoded = vec![];
+        frame.encode(&mut encoded);
+        let read = Frame::read(&mut &encoded[..], DEFAULT_MAX_FRAME_SIZE).unwrap();
+        assert_eq!(read, frame);
+    }
+
+    #[test]
+    fn frames() {
+        round_trip(Frame::Data {
+            stream: 1,
+            data: b"hello".to_vec(),
+            padding: 4,
+            end_stream: true,
+        });
+        round_trip(Frame::Headers {
+            stream: 3,
+            block: vec![0x82],
+            end_stream: false,
+            end_headers: true,
+        });
+        round_trip(Frame::Priority { stream: 5 });
+        round_trip(Frame::RstStream { stream: 1, code: 8 });
+        round_trip(Frame::Settings {
+            ack: false,
+            settings: vec![(setting::INITIAL_WINDOW_SIZE, 10)],
+        });
+        round_trip(Frame::Settings {
+            ack: true,
+            settings: vec![],
+        });
+        round_trip(Frame::Ping {
+            ack: true,
+            data: *b"12345678",
+        });
+        round_trip(Frame::GoAway {
+            last_stream: 7,
+            code: 1,
+        });
+        round_trip(Frame::WindowUpdate {
+            stream: 0,
+            increment: 100,
+        });
+        round_trip(Frame::Continuation {
+            stream: 1,
+            block: vec![0x84],
+            end_headers: false,
+        });
+        round_trip(Frame::Unknown { kind: 0xa });
+    }
+
+    #[test]
+    fn headers_with_priority() {
+        let frame = [
+            0,
+            0,
+            8,
+            0x1,
+            PADDED | PRIORITY | END_HEADERS,
+            0,
+            0,
+            0,
+            1,
+            1,
+            0,
+            0,
+            0,
+            3,
+            15,
+            0x82,
+            0,
+        ];
+        assert_eq!(
+            Frame::read(&mut &frame[..], DEFAULT_MAX_FRAME_SIZE).unwrap(),
+            Frame::Headers {
+                stream: 1,
+                block: vec![0x82],
+                end_stream: false,
+                end_headers: true,
+            }
+        );
+    }
+
+    fn read_error(frame: &[u8]) -> Option<ErrorCode> {
+        match Frame::read(&mut &frame[..], DEFAULT_MAX_FRAME_SIZE) {
+            Err(Error::Connection(code)) => Some(code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn invalid_frames() {
+        // larger than the maximum frame size
+        assert_eq!(
+            read_error(&[0, 0x40, 1, 0x0, 0, 0, 0, 0, 1]),
+            Some(ErrorCode::FrameSizeError)
+        );
+        // data on the connection
+        assert_eq!(
+            read_error(&[0, 0, 0, 0x0, 0, 0, 0, 0, 0]),
+            Some(ErrorCode::ProtocolError)
+        );
+        // settings with a partial parameter
+        assert_eq!(
+            read_error(&[0, 0, 3, 0x4, 0, 0, 0, 0, 0, 0, 1, 0]),
+            Some(ErrorCode::FrameSizeError)
+        );
+        // padding longer than the payload
+        assert_eq!(
+            read_error(&[0, 0, 2, 0x0, PADDED, 0, 0, 0, 1, 5, 0]),
+            Some(ErrorCode::ProtocolError)
+        );
+        // push promise from a client
+        assert_eq!(
+            read_error(&[0, 0, 0, 0x5, 0, 0, 0, 0, 1]),
+            Some(ErrorCode::ProtocolError)
+        );
+    }
+
+    #[test]
+    fn settings_header() {
+        assert_eq!(
+            settings_payload(&[0, 3, 0, 0, 0, 100, 0, 4, 0, 1, 0, 0]),
+            Some(vec![
+                (setting::MAX_CONCURRENT_STREAMS, 100),
+                (setting::INITIAL_WINDOW_SIZE, 65536)
+            ])
+        );
+        assert_eq!(settings_payload(&[0, 3, 0]), None);
+    }
+}
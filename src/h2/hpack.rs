@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+
+use super::huffman;
+
+/// The static table of RFC 7541 appendix A, index 1 is the first entry.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Size of the dynamic table the decoder allows, the default of `SETTINGS_HEADER_TABLE_SIZE`.
+pub(super) const TABLE_SIZE: usize = 4096;
+
+pub(super) type Field = (Vec<u8>, Vec<u8>);
+
+/// A header block that cannot be decoded, a connection error.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid header block")]
+pub(super) struct DecodeError;
+
+/// Decodes header blocks, keeping the dynamic table between them.
+#[derive(Debug)]
+pub(super) struct Decoder {
+    /// Newest entries first.
+    table: VecDeque<Field>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: TABLE_SIZE,
+        }
+    }
+
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<Field>, DecodeError> {
+        let mut fields = vec![];
+        let mut first = true;
+        while let Some(&byte) = block.first() {
+            match byte {
+                // indexed header field
+                0x80.. => {
+                    let index = integer(&mut block, 7)?;
+                    fields.push(self.get(index)?);
+                }
+                // literal with incremental indexing
+                0x40.. => {
+                    let field = self.literal(&mut block, 6)?;
+                    self.insert(field.clone());
+                    fields.push(field);
+                }
+                // dynamic table size update, only at the start of a block
+                0x20.. => {
+                    let size = integer(&mut block, 5)?;
+                    if !first || size > TABLE_SIZE {
+                        return Err(DecodeError);
+                    }
+                    self.max_size = size;
+                    self.evict(0);
+                }
+                // literal without indexing or never indexed
+                _ => fields.push(self.literal(&mut block, 4)?),
+            }
+            first = matches!(byte, 0x20..=0x3f) && first;
+        }
+        Ok(fields)
+    }
+
+    fn get(&self, index: usize) -> Result<Field, DecodeError> {
+        match index {
+            0 => Err(DecodeError),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.into(), value.into()))
+            }
+            _ => self.table.get(index - 62).cloned().ok_or(DecodeError),
+        }
+    }
+
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<Field, DecodeError> {
+        let name = match integer(block, prefix)? {
+            0 => string(block)?,
+            index => self.get(index)?.0,
+        };
+        Ok((name, string(block)?))
+    }
+
+    fn insert(&mut self, field: Field) {
+        let size = entry_size(&field);
+        self.evict(size);
+        // an entry larger than the table empties it and is not added
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front(field);
+        }
+    }
+
+    /// Evicts the oldest entries until `room` more bytes fit.
+    fn evict(&mut self, room: usize) {
+        while self.size + room > self.max_size {
+            match self.table.pop_back() {
+                Some(field) => self.size -= entry_size(&field),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Encodes header blocks without the dynamic table, so the peer's table size does not matter.
+#[derive(Debug, Default)]
+pub(super) struct Encoder;
+
+impl Encoder {
+    pub fn encode<'a>(&self, fields: impl IntoIterator<Item = (&'a [u8], &'a [u8])>) -> Vec<u8> {
+        let mut block = vec![];
+        for (name, value) in fields {
+            match STATIC_TABLE.iter().position(|(n, _)| n.as_bytes() == name) {
+                // literal without indexing, indexed name
+                Some(index) => encode_integer(&mut block, 0x00, 4, index + 1),
+                // literal without indexing, new name
+                None => {
+                    block.push(0x00);
+                    encode_string(&mut block, name);
+                }
+            }
+            encode_string(&mut block, value);
+        }
+        block
+    }
+}
+
+/// Size of a table entry, counting 32 bytes of overhead.
+fn entry_size((name, value): &Field) -> usize {
+    name.len() + value.len() + 32
+}
+
+/// Decodes an integer whose first byte has a `prefix` bits long part.
+fn integer(block: &mut &[u8], prefix: u8) -> Result<usize, DecodeError> {
+    let (&first, rest) = block.split_first().ok_or(DecodeError)?;
+    *block = rest;
+    let max = (1 << prefix) - 1;
+    let mut value = (first & max) as usize;
+    if value < max as usize {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(DecodeError)?;
+        *block = rest;
+        // larger values are only sent by hostile peers
+        if shift > 28 {
+            return Err(DecodeError);
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn string(block: &mut &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let huffman = block.first().ok_or(DecodeError)? & 0x80 != 0;
+    let length = integer(block, 7)?;
+    if length > block.len() {
+        return Err(DecodeError);
+    }
+    let (string, rest) = block.split_at(length);
+    *block = rest;
+    match huffman {
+        true => huffman::decode(string).ok_or(DecodeError),
+        false => Ok(string.to_vec()),
+    }
+}
+
+fn encode_integer(block: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let max = (1 << prefix) - 1;
+    if value < max {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | max as u8);
+    value -= max;
+    while value >= 0x80 {
+        block.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    block.push(value as u8);
+}
+
+/// A string literal, never Huffman coded.
+fn encode_string(block: &mut Vec<u8>, string: &[u8]) {
+    encode_integer(block, 0x00, 7, string.len());
+    block.extend_from_slice(string);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<Field> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn integers() {
+        // RFC 7541 appendix C.1
+        let mut block = vec![];
+        encode_integer(&mut block, 0, 5, 10);
+        encode_integer(&mut block, 0, 5, 1337);
+        assert_eq!(block, [0x0a, 0x1f, 0x9a, 0x0a]);
+
+        let mut block = &block[..];
+        assert_eq!(integer(&mut block, 5), Ok(10));
+        assert_eq!(integer(&mut block, 5), Ok(1337));
+        assert!(block.is_empty());
+        assert_eq!(integer(&mut &[0x1f, 0x9a][..], 5), Err(DecodeError));
+    }
+
+    #[test]
+    fn decode_requests() {
+        // RFC 7541 appendix C.3, the second request uses the dynamic table
+        let mut decoder = Decoder::new();
+        let first = [
+            0x82, 0x86, 0x84, 0x41, 0x0f, 0x77, 0x77, 0x77, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70,
+            0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d,
+        ];
+        assert_eq!(
+            decoder.decode(&first),
+            Ok(fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]))
+        );
+        let second = [
+            0x82, 0x86, 0x84, 0xbe, 0x58, 0x08, 0x6e, 0x6f, 0x2d, 0x63, 0x61, 0x63, 0x68, 0x65,
+        ];
+        assert_eq!(
+            decoder.decode(&second),
+            Ok(fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ]))
+        );
+        assert_eq!(decoder.size, 110);
+    }
+
+    #[test]
+    fn decode_huffman() {
+        // RFC 7541 appendix C.4.1
+        let block = [
+            0x82, 0x86, 0x84, 0x41, 0x8c, 0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab,
+            0x90, 0xf4, 0xff,
+        ];
+        assert_eq!(
+            Decoder::new().decode(&block).unwrap()[3],
+            (b":authority".to_vec(), b"www.example.com".to_vec())
+        );
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.decode(&[0x80]), Err(DecodeError));
+        assert_eq!(decoder.decode(&[0xbe]), Err(DecodeError));
+        // a size update after a field
+        assert_eq!(decoder.decode(&[0x82, 0x20]), Err(DecodeError));
+        assert_eq!(decoder.decode(&[0x3f, 0xe2, 0x1f]), Err(DecodeError));
+        assert_eq!(decoder.decode(&[0x00, 0x05, b'a']), Err(DecodeError));
+    }
+
+    #[test]
+    fn eviction() {
+        let mut decoder = Decoder::new();
+        decoder.decode(&[0x20, 0x3f, 0x15]).unwrap();
+        assert_eq!(decoder.max_size, 52);
+        // 32 + 2 bytes each, the second one evicts the first
+        decoder.decode(&[0x40, 0x01, b'a', 0x01, b'1']).unwrap();
+        decoder.decode(&[0x40, 0x01, b'b', 0x01, b'2']).unwrap();
+        assert_eq!(decoder.table, [(b"b".to_vec(), b"2".to_vec())]);
+        assert_eq!(decoder.decode(&[0xbe]), Ok(fields(&[("b", "2")])));
+    }
+
+    #[test]
+    fn encode() {
+        let block = Encoder.encode([(&b":status"[..], &b"200"[..]), (b"x-custom", b"value")]);
+        assert_eq!(
+            Decoder::new().decode(&block),
+            Ok(fields(&[(":status", "200"), ("x-custom", "value")]))
+        );
+        assert_eq!(&block[..5], [0x08, 0x03, b'2', b'0', b'0']);
+    }
+}
@@ -0,0 +1,125 @@
+//! HTTP/2 of RFC 9113, served on connections starting with the client preface,
+//! either right away (prior knowledge, or ALPN `h2` over TLS) or after an
+//! `Upgrade: h2c` request.
+
+mod frame;
+mod hpack;
+mod huffman;
+mod server;
+
+use std::io;
+
+use crate::{
+    base64,
+    bytes::ToBytes,
+    spec::{
+        header,
+        protocol::HttpVersion,
+        request::Request,
+        response::{Response, Status},
+    },
+    BodyLength,
+};
+pub(crate) use frame::PREFACE;
+use frame::{settings_payload, ErrorCode};
+use hpack::DecodeError;
+pub(crate) use server::serve;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Ends the connection with a `GOAWAY`.
+    #[error("HTTP/2 connection error {0:?}")]
+    Connection(ErrorCode),
+    /// Ends one stream with a `RST_STREAM`.
+    #[error("HTTP/2 error {1:?} on stream {0}")]
+    Stream(u32, ErrorCode),
+}
+
+impl From<DecodeError> for Error {
+    fn from(_: DecodeError) -> Self {
+        Error::Connection(ErrorCode::CompressionError)
+    }
+}
+
+/// An HTTP/1.1 request switching to HTTP/2, its response is sent on stream 1.
+#[derive(Debug)]
+pub(crate) struct Upgrade {
+    request: Request,
+    /// The client settings sent in `HTTP2-Settings`.
+    settings: Vec<(u16, u32)>,
+}
+
+impl Upgrade {
+    pub fn new(request: Request, settings: Vec<(u16, u32)>) -> Upgrade {
+        Upgrade { request, settings }
+    }
+
+    /// The interim response sent over HTTP/1.1 before switching.
+    pub fn switching_protocols() -> Vec<u8> {
+        let mut response = Response::new(Status::SwitchingProtocols);
+        response.headers.append(header::CONNECTION, "Upgrade");
+        response.headers.append(header::UPGRADE, "h2c");
+        response.into_bytes()
+    }
+}
+
+/// The client settings of a valid `Upgrade: h2c` request, see RFC 7540 section 3.2.
+///
+/// Requests with a body are served over HTTP/1.1, so the body does not have
+/// to be read before switching.
+pub(crate) fn upgrade_settings(request: &Request) -> Option<Vec<(u16, u32)>> {
+    let has_option = |name, option: &[u8]| {
+        request
+            .headers
+            .get_field_value(name)
+            .is_some_and(|value| value.list().iter().any(|o| o.eq_ignore_ascii_case(option)))
+    };
+    if request.request_line.http_version != (HttpVersion { major: 1, minor: 1 })
+        || request.body_length() != BodyLength::Length(0)
+        || !has_option(header::UPGRADE, b"h2c")
+        || !has_option(header::CONNECTION, b"upgrade")
+        || !has_option(header::CONNECTION, b"http2-settings")
+    {
+        return None;
+    }
+    let mut settings = request.headers.get_all(header::HTTP2_SETTINGS);
+    let payload = base64::decode_url(settings.next()?)?;
+    match settings.next() {
+        Some(_) => None,
+        None => settings_payload(&payload),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parse;
+
+    fn upgrade(request: &str) -> Option<Vec<(u16, u32)>> {
+        upgrade_settings(&Request::convert(request).unwrap())
+    }
+
+    #[test]
+    fn upgrade_request() {
+        assert_eq!(
+            upgrade(
+                "GET / HTTP/1.1\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n"
+            ),
+            Some(vec![(3, 100), (4, 65535)])
+        );
+        // the connection options are missing
+        assert_eq!(
+            upgrade("GET / HTTP/1.1\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABk\r\n\r\n"),
+            None
+        );
+        // a body would have to be read first
+        assert_eq!(
+            upgrade(
+                "POST / HTTP/1.1\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABk\r\nContent-Length: 1\r\n\r\n"
+            ),
+            None
+        );
+    }
+}
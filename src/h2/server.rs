@@ -0,0 +1,1044 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufReader, Read},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    connection::Transport,
+    metrics::METRICS,
+    middleware::Chain,
+    parser::{Limits, StreamParser},
+    request::Handler,
+    respond,
+    spec::{
+        header::{self, HeaderMap},
+        protocol::HttpVersion,
+        request::{Method, Request, RequestLine},
+        response::{Response, Status},
+        uri::RequestURI,
+    },
+    Cli,
+};
+
+use super::{
+    frame::{
+        setting, ErrorCode, Frame, DEFAULT_MAX_FRAME_SIZE, DEFAULT_WINDOW, MAX_FRAME_SIZE_LIMIT,
+        MAX_WINDOW, PREFACE,
+    },
+    hpack::{Decoder, Encoder, Field},
+    Error, Upgrade,
+};
+
+/// Streams a client may have open at once.
+const MAX_CONCURRENT_STREAMS: u32 = 100;
+
+/// Streams a client may reset in a second, more is taken for a rapid reset attack,
+/// CVE-2023-44487, as the handlers of reset streams keep running.
+const MAX_RESETS_PER_SECOND: u32 = MAX_CONCURRENT_STREAMS;
+
+/// Header fields a request or response cannot have in HTTP/2, RFC 9113 section 8.2.2.
+const CONNECTION_SPECIFIC: [&[u8]; 5] = [
+    b"connection",
+    b"keep-alive",
+    b"proxy-connection",
+    b"transfer-encoding",
+    b"upgrade",
+];
+
+/// A request received in full, to be handled on its own thread.
+type Ready = (u32, Request, Vec<u8>);
+
+/// Serves HTTP/2 on `transport`, `reader` starts with the client preface.
+///
+/// Frames are read on this thread, each request is handled on its own thread
+/// once its body was received, and responses are written by whichever thread
+/// has frames to send, under the lock on the sending state.
+pub(crate) fn serve(
+    cli: &Cli,
+    chain: &Chain,
+    transport: &impl Transport,
+    reader: impl Read,
+    upgrade: Option<Upgrade>,
+) -> io::Result<()> {
+    let limits = cli.limits();
+    let sender = &Sender::new(transport);
+    let handling = &AtomicUsize::new(0);
+    let mut connection = Connection::new(sender, handling, limits, transport.peer_addr().ok());
+    let mut reader = BufReader::new(reader);
+
+    thread::scope(|scope| {
+        let dispatch = |(stream, request, body): Ready| {
+            handling.fetch_add(1, Ordering::Relaxed);
+            scope.spawn(move || {
+                let response = respond(cli, chain, request, &mut &body[..]);
+                sender.respond(stream, response);
+                handling.fetch_sub(1, Ordering::Relaxed);
+            });
+        };
+
+        sender.send(&[Frame::Settings {
+            ack: false,
+            settings: vec![
+                (setting::MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS),
+                (setting::MAX_HEADER_LIST_SIZE, limits.header_bytes as u32),
+            ],
+        }])?;
+        if let Some(Upgrade {
+            mut request,
+            settings,
+        }) = upgrade
+        {
+            // acknowledged by the 101 response already
+            if let Err(e) = sender.settings(&settings, false) {
+                connection.close(e);
+                return Ok(());
+            }
+            request.request_line.http_version = HttpVersion { major: 2, minor: 0 };
+            connection.last_stream = 1;
+            sender.open(1);
+            dispatch((1, request, vec![]));
+        }
+
+        match connection.run(&mut reader, dispatch) {
+            // the client closed the connection or it failed
+            Error::Io(_) => {}
+            e => connection.close(e),
+        }
+        Ok(())
+    })
+}
+
+/// State of the frames read from the client.
+struct Connection<'a, T> {
+    sender: &'a Sender<'a, T>,
+    /// Requests whose handler did not return yet, including the ones of reset streams.
+    handling: &'a AtomicUsize,
+    limits: Limits,
+    peer_addr: Option<SocketAddr>,
+    decoder: Decoder,
+    /// Streams whose request is still being received.
+    incoming: BTreeMap<u32, Incoming>,
+    /// Highest stream opened by the client.
+    last_stream: u32,
+    /// A header block continued in `CONTINUATION` frames: stream, block so far and end of stream.
+    continuation: Option<(u32, Vec<u8>, bool)>,
+    /// When the current second of `RST_STREAM` frames started, and how many were received.
+    resets: (Instant, u32),
+}
+
+struct Incoming {
+    /// `None` when the request was already answered, the rest of the body is discarded.
+    request: Option<Request>,
+    body: Vec<u8>,
+}
+
+impl<'a, T: Transport> Connection<'a, T> {
+    fn new(
+        sender: &'a Sender<'a, T>,
+        handling: &'a AtomicUsize,
+        limits: Limits,
+        peer_addr: Option<SocketAddr>,
+    ) -> Self {
+        Connection {
+            sender,
+            handling,
+            limits,
+            peer_addr,
+            decoder: Decoder::new(),
+            incoming: BTreeMap::new(),
+            last_stream: 0,
+            continuation: None,
+            resets: (Instant::now(), 0),
+        }
+    }
+
+    /// Reads frames until the connection ends, returns why it did.
+    fn run(&mut self, reader: &mut impl Read, mut dispatch: impl FnMut(Ready)) -> Error {
+        let mut preface = [0; PREFACE.len()];
+        if let Err(e) = reader.read_exact(&mut preface) {
+            return e.into();
+        }
+        if preface != PREFACE {
+            return Error::Connection(ErrorCode::ProtocolError);
+        }
+
+        let mut first = true;
+        loop {
+            // our SETTINGS_MAX_FRAME_SIZE is always the default
+            let frame = match Frame::read(reader, DEFAULT_MAX_FRAME_SIZE) {
+                Ok(frame) => frame,
+                Err(e) => return e,
+            };
+            // the preface ends with the client settings
+            if first && !matches!(frame, Frame::Settings { ack: false, .. }) {
+                return Error::Connection(ErrorCode::ProtocolError);
+            }
+            first = false;
+
+            match self.receive(frame) {
+                Ok(Some(ready)) => dispatch(ready),
+                Ok(None) => {}
+                Err(Error::Stream(stream, code)) => {
+                    self.incoming.remove(&stream);
+                    if let Err(e) = self.sender.reset(stream, code) {
+                        return e.into();
+                    }
+                }
+                Err(e) => return e,
+            }
+        }
+    }
+
+    /// Ends the connection after a connection error.
+    fn close(&self, error: Error) {
+        if let Error::Connection(code) = error {
+            let _ = self.sender.send(&[Frame::GoAway {
+                last_stream: self.last_stream,
+                code: code as u32,
+            }]);
+        }
+        self.sender.transport.close();
+    }
+
+    fn receive(&mut self, frame: Frame) -> Result<Option<Ready>, Error> {
+        // a header block cannot be interleaved with other frames
+        if let Some((stream, ..)) = self.continuation {
+            if !matches!(frame, Frame::Continuation { stream: s, .. } if s == stream) {
+                return Err(Error::Connection(ErrorCode::ProtocolError));
+            }
+        }
+
+        match frame {
+            Frame::Data {
+                stream,
+                data,
+                padding,
+                end_stream,
+            } => self.data(stream, data, padding, end_stream),
+            Frame::Headers {
+                stream,
+                block,
+                end_stream,
+                end_headers,
+            } => {
+                self.continuation = Some((stream, vec![], end_stream));
+                self.continue_headers(block, end_headers)
+            }
+            Frame::Continuation {
+                block, end_headers, ..
+            } => match self.continuation {
+                Some(_) => self.continue_headers(block, end_headers),
+                None => Err(Error::Connection(ErrorCode::ProtocolError)),
+            },
+            Frame::RstStream { stream, .. } => {
+                if stream > self.last_stream {
+                    return Err(Error::Connection(ErrorCode::ProtocolError));
+                }
+                let (second, resets) = &mut self.resets;
+                if second.elapsed() >= Duration::from_secs(1) {
+                    (*second, *resets) = (Instant::now(), 0);
+                }
+                *resets += 1;
+                if *resets > MAX_RESETS_PER_SECOND {
+                    return Err(Error::Connection(ErrorCode::EnhanceYourCalm));
+                }
+                self.incoming.remove(&stream);
+                self.sender.cancel(stream);
+                Ok(None)
+            }
+            Frame::Settings {
+                ack: false,
+                settings,
+            } => {
+                self.sender.settings(&settings, true)?;
+                Ok(None)
+            }
+            Frame::Ping { ack: false, data } => {
+                self.sender.send(&[Frame::Ping { ack: true, data }])?;
+                Ok(None)
+            }
+            Frame::WindowUpdate { stream, increment } => {
+                self.sender.window_update(stream, increment)?;
+                Ok(None)
+            }
+            // in-flight streams are still answered after a GOAWAY, the client closes the connection
+            Frame::Settings { ack: true, .. }
+            | Frame::Ping { ack: true, .. }
+            | Frame::GoAway { .. }
+            | Frame::Priority { .. }
+            | Frame::Unknown { .. } => Ok(None),
+        }
+    }
+
+    fn data(
+        &mut self,
+        stream: u32,
+        data: Vec<u8>,
+        padding: usize,
+        end_stream: bool,
+    ) -> Result<Option<Ready>, Error> {
+        // the whole payload is given back right away, bodies are bounded by the limits instead
+        let length = (data.len() + padding) as u32;
+        let mut updates = vec![];
+        if length > 0 {
+            updates.push(Frame::WindowUpdate {
+                stream: 0,
+                increment: length,
+            });
+            if !end_stream && self.incoming.contains_key(&stream) {
+                updates.push(Frame::WindowUpdate {
+                    stream,
+                    increment: length,
+                });
+            }
+            self.sender.send(&updates)?;
+        }
+
+        let Some(incoming) = self.incoming.get_mut(&stream) else {
+            return Err(self.not_open(stream));
+        };
+        if incoming.request.is_some() {
+            if incoming.body.len() + data.len() > self.limits.body {
+                incoming.request = None;
+                incoming.body = vec![];
+                self.sender
+                    .respond(stream, Handler::rejection(Status::PayloadTooLarge));
+            } else {
+                incoming.body.extend_from_slice(&data);
+            }
+        }
+        match end_stream {
+            true => self.end_stream(stream),
+            false => Ok(None),
+        }
+    }
+
+    fn continue_headers(
+        &mut self,
+        block: Vec<u8>,
+        end_headers: bool,
+    ) -> Result<Option<Ready>, Error> {
+        let (stream, mut pending, end_stream) =
+            self.continuation.take().expect("in a header block");
+        pending.extend_from_slice(&block);
+        // HPACK never makes a header list smaller than its block, by much
+        if pending.len() > 2 * self.limits.header_bytes + 1024 {
+            return Err(Error::Connection(ErrorCode::EnhanceYourCalm));
+        }
+        match end_headers {
+            true => self.headers(stream, &pending, end_stream),
+            false => {
+                self.continuation = Some((stream, pending, end_stream));
+                Ok(None)
+            }
+        }
+    }
+
+    fn headers(
+        &mut self,
+        stream: u32,
+        block: &[u8],
+        end_stream: bool,
+    ) -> Result<Option<Ready>, Error> {
+        // decoded first, the table is shared by every stream
+        let fields = self.decoder.decode(block)?;
+        if stream % 2 == 0 {
+            return Err(Error::Connection(ErrorCode::ProtocolError));
+        }
+
+        if self.incoming.contains_key(&stream) {
+            // trailers, which are not passed on
+            return match end_stream {
+                true => self.end_stream(stream),
+                false => Err(Error::Stream(stream, ErrorCode::ProtocolError)),
+            };
+        }
+        if stream <= self.last_stream {
+            return Err(Error::Connection(ErrorCode::StreamClosed));
+        }
+        self.last_stream = stream;
+        // a reset stream is closed, but its handler may still be running
+        let busy = self.incoming.len() + self.handling.load(Ordering::Relaxed);
+        if self.sender.open_streams().max(busy) >= MAX_CONCURRENT_STREAMS as usize {
+            return Err(Error::Stream(stream, ErrorCode::RefusedStream));
+        }
+        self.sender.open(stream);
+
+        let size: usize = fields.iter().map(|(n, v)| n.len() + v.len() + 32).sum();
+        let request = if size > self.limits.header_bytes || fields.len() > self.limits.header_count
+        {
+            self.sender.respond(
+                stream,
+                Handler::rejection(Status::RequestHeaderFieldsTooLarge),
+            );
+            None
+        } else {
+            let mut request =
+                request(fields).ok_or(Error::Stream(stream, ErrorCode::ProtocolError))?;
+            request.peer_addr = self.peer_addr;
+            request.received_at = Some(Instant::now());
+            Some(request)
+        };
+        self.incoming.insert(
+            stream,
+            Incoming {
+                request,
+                body: vec![],
+            },
+        );
+        match end_stream {
+            true => self.end_stream(stream),
+            false => Ok(None),
+        }
+    }
+
+    /// The client sent the whole request on `stream`.
+    fn end_stream(&mut self, stream: u32) -> Result<Option<Ready>, Error> {
+        let Some(Incoming {
+            request: Some(mut request),
+            body,
+        }) = self.incoming.remove(&stream)
+        else {
+            return Ok(None);
+        };
+        match request.content_length() {
+            Some(length) if length != body.len() => {
+                return Err(Error::Stream(stream, ErrorCode::ProtocolError))
+            }
+            // routes can rely on it like for HTTP/1.1
+            None if !body.is_empty() => request
+                .headers
+                .insert(header::CONTENT_LENGTH, body.len().to_string()),
+            _ => {}
+        }
+        Ok(Some((stream, request, body)))
+    }
+
+    /// A frame on a stream that is not receiving.
+    fn not_open(&self, stream: u32) -> Error {
+        match stream > self.last_stream {
+            true => Error::Connection(ErrorCode::ProtocolError),
+            false => Error::Stream(stream, ErrorCode::StreamClosed),
+        }
+    }
+}
+
+/// Builds a request from its header fields, `None` when it is malformed.
+fn request(fields: Vec<Field>) -> Option<Request> {
+    let (mut method, mut scheme, mut path, mut authority) = (None, None, None, None);
+    let mut headers = HeaderMap::new();
+    for (name, value) in fields {
+        if value.iter().any(|c| matches!(c, b'\0' | b'\r' | b'\n')) {
+            return None;
+        }
+        if let Some(pseudo) = name.strip_prefix(b":") {
+            let slot = match pseudo {
+                b"method" => &mut method,
+                b"scheme" => &mut scheme,
+                b"path" => &mut path,
+                b"authority" => &mut authority,
+                _ => return None,
+            };
+            // pseudo-headers come first, each one once
+            if !headers.is_empty() || slot.replace(value).is_some() {
+                return None;
+            }
+            continue;
+        }
+        if name.is_empty()
+            || name.iter().any(u8::is_ascii_uppercase)
+            || CONNECTION_SPECIFIC.contains(&name.as_slice())
+            || name == b"te" && value != b"trailers"
+        {
+            return None;
+        }
+        headers.append(name, value);
+    }
+
+    // `CONNECT` has no scheme and path, it is not supported
+    scheme?;
+    let method = match method?.as_slice() {
+        b"GET" => Method::Get,
+        b"POST" => Method::Post,
+        method => Method::Extension(method.to_vec()),
+    };
+    let request_uri = StreamParser::new(path?.as_slice())
+        .parse::<RequestURI>()
+        .ok()?;
+    if let Some(authority) = authority {
+        if !headers.contains_key(header::HOST) {
+            headers.append(header::HOST, authority);
+        }
+    }
+    Some(Request {
+        request_line: RequestLine {
+            method,
+            request_uri,
+            http_version: HttpVersion { major: 2, minor: 0 },
+        },
+        headers,
+        peer_addr: None,
+        received_at: None,
+    })
+}
+
+/// Writes frames for the reading thread and the request threads.
+struct Sender<'a, T> {
+    transport: &'a T,
+    state: Mutex<SendState>,
+}
+
+struct SendState {
+    encoder: Encoder,
+    /// Connection flow control window.
+    window: i64,
+    /// Window of new streams, from the client `SETTINGS_INITIAL_WINDOW_SIZE`.
+    initial_window: i64,
+    max_frame_size: usize,
+    /// Streams opened by the client that are not done sending the response.
+    streams: BTreeMap<u32, Outgoing>,
+}
+
+struct Outgoing {
+    window: i64,
+    /// The response body once handled, and how much of it was sent.
+    body: Option<(Vec<u8>, usize)>,
+}
+
+impl<'a, T: Transport> Sender<'a, T> {
+    fn new(transport: &'a T) -> Self {
+        Sender {
+            transport,
+            state: Mutex::new(SendState {
+                encoder: Encoder,
+                window: DEFAULT_WINDOW,
+                initial_window: DEFAULT_WINDOW,
+                max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+                streams: BTreeMap::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SendState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, out: &[u8]) -> io::Result<()> {
+        if out.is_empty() {
+            return Ok(());
+        }
+        let written = self.transport.send(out);
+        match written {
+            Ok(()) => METRICS.record_sent(out.len()),
+            // also wakes up the reading thread
+            Err(_) => self.transport.close(),
+        }
+        written
+    }
+
+    fn send(&self, frames: &[Frame]) -> io::Result<()> {
+        let _state = self.lock();
+        let mut out = vec![];
+        for frame in frames {
+            frame.encode(&mut out);
+        }
+        self.write(&out)
+    }
+
+    fn open(&self, stream: u32) {
+        let mut state = self.lock();
+        let window = state.initial_window;
+        state
+            .streams
+            .insert(stream, Outgoing { window, body: None });
+    }
+
+    fn open_streams(&self) -> usize {
+        self.lock().streams.len()
+    }
+
+    /// Forgets a stream the client reset.
+    fn cancel(&self, stream: u32) {
+        self.lock().streams.remove(&stream);
+    }
+
+    fn reset(&self, stream: u32, code: ErrorCode) -> io::Result<()> {
+        let mut state = self.lock();
+        state.streams.remove(&stream);
+        let mut out = vec![];
+        Frame::RstStream {
+            stream,
+            code: code as u32,
+        }
+        .encode(&mut out);
+        self.write(&out)
+    }
+
+    /// Sends the head of `response` and as much of its body as the windows allow.
+    fn respond(&self, stream: u32, response: Response) {
+        let mut state = self.lock();
+        if !state.streams.contains_key(&stream) {
+            // reset by the client meanwhile
+            return;
+        }
+
+        let status = response.status().code().to_string();
+        let length = response.body().len().to_string();
+        let mut fields: Vec<(Vec<u8>, &[u8])> = vec![(b":status".to_vec(), status.as_bytes())];
+        for (name, value) in response.headers.iter() {
+            let name = name.to_ascii_lowercase();
+            if !CONNECTION_SPECIFIC.contains(&name.as_slice()) && name != b"content-length" {
+                fields.push((name, value));
+            }
+        }
+        fields.push((b"content-length".to_vec(), length.as_bytes()));
+        let block = state
+            .encoder
+            .encode(fields.iter().map(|(name, value)| (name.as_slice(), *value)));
+
+        let body = response.body.map(|body| body.0).unwrap_or_default();
+        let end_stream = body.is_empty();
+        let mut out = vec![];
+        let mut chunks = block.chunks(state.max_frame_size).peekable();
+        let first = chunks.next().unwrap_or_default().to_vec();
+        Frame::Headers {
+            stream,
+            block: first,
+            end_stream,
+            end_headers: chunks.peek().is_none(),
+        }
+        .encode(&mut out);
+        while let Some(chunk) = chunks.next() {
+            Frame::Continuation {
+                stream,
+                block: chunk.to_vec(),
+                end_headers: chunks.peek().is_none(),
+            }
+            .encode(&mut out);
+        }
+
+        match end_stream {
+            true => {
+                state.streams.remove(&stream);
+            }
+            false => {
+                if let Some(outgoing) = state.streams.get_mut(&stream) {
+                    outgoing.body = Some((body, 0));
+                }
+            }
+        }
+        if self.write(&out).is_ok() {
+            let _ = self.flush(&mut state);
+        }
+    }
+
+    /// Applies client settings, acknowledging them unless they came with an upgrade.
+    fn settings(&self, settings: &[(u16, u32)], ack: bool) -> Result<(), Error> {
+        let mut state = self.lock();
+        for &(id, value) in settings {
+            match id {
+                setting::ENABLE_PUSH if value > 1 => {
+                    return Err(Error::Connection(ErrorCode::ProtocolError))
+                }
+                setting::INITIAL_WINDOW_SIZE => {
+                    let value = value as i64;
+                    if value > MAX_WINDOW {
+                        return Err(Error::Connection(ErrorCode::FlowControlError));
+                    }
+                    // applies to the streams already open too
+                    let delta = value - state.initial_window;
+                    state.initial_window = value;
+                    for outgoing in state.streams.values_mut() {
+                        outgoing.window += delta;
+                        if outgoing.window > MAX_WINDOW {
+                            return Err(Error::Connection(ErrorCode::FlowControlError));
+                        }
+                    }
+                }
+                setting::MAX_FRAME_SIZE => {
+                    if !(DEFAULT_MAX_FRAME_SIZE as u32..=MAX_FRAME_SIZE_LIMIT).contains(&value) {
+                        return Err(Error::Connection(ErrorCode::ProtocolError));
+                    }
+                    state.max_frame_size = value as usize;
+                }
+                // responses are never indexed nor pushed, unknown settings are ignored
+                _ => {}
+            }
+        }
+        if ack {
+            let mut out = vec![];
+            Frame::Settings {
+                ack: true,
+                settings: vec![],
+            }
+            .encode(&mut out);
+            self.write(&out)?;
+        }
+        self.flush(&mut state)?;
+        Ok(())
+    }
+
+    fn window_update(&self, stream: u32, increment: u32) -> Result<(), Error> {
+        if increment == 0 {
+            return Err(match stream {
+                0 => Error::Connection(ErrorCode::ProtocolError),
+                _ => Error::Stream(stream, ErrorCode::ProtocolError),
+            });
+        }
+        let mut state = self.lock();
+        let window = match stream {
+            0 => Some(&mut state.window),
+            _ => state
+                .streams
+                .get_mut(&stream)
+                .map(|outgoing| &mut outgoing.window),
+        };
+        // closed streams can still get updates sent before the client knew
+        let Some(window) = window else {
+            return Ok(());
+        };
+        *window += increment as i64;
+        if *window > MAX_WINDOW {
+            return Err(match stream {
+                0 => Error::Connection(ErrorCode::FlowControlError),
+                _ => Error::Stream(stream, ErrorCode::FlowControlError),
+            });
+        }
+        self.flush(&mut state)?;
+        Ok(())
+    }
+
+    /// Sends response bodies while the windows allow it, a frame of each stream in turn.
+    fn flush(&self, state: &mut SendState) -> io::Result<()> {
+        let SendState {
+            window,
+            max_frame_size,
+            streams,
+            ..
+        } = state;
+        loop {
+            let mut out = vec![];
+            let mut done = vec![];
+            for (&stream, outgoing) in streams.iter_mut() {
+                let Some((body, sent)) = &mut outgoing.body else {
+                    continue;
+                };
+                let length = (body.len() - *sent)
+                    .min(*max_frame_size)
+                    .min(outgoing.window.max(0) as usize)
+                    .min((*window).max(0) as usize);
+                if length == 0 {
+                    continue;
+                }
+                let end_stream = *sent + length == body.len();
+                Frame::Data {
+                    stream,
+                    data: body[*sent..*sent + length].to_vec(),
+                    padding: 0,
+                    end_stream,
+                }
+                .encode(&mut out);
+                *sent += length;
+                outgoing.window -= length as i64;
+                *window -= length as i64;
+                if end_stream {
+                    done.push(stream);
+                }
+            }
+            for stream in done {
+                streams.remove(&stream);
+            }
+            if out.is_empty() {
+                return Ok(());
+            }
+            self.write(&out)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        io::Write,
+        net::{TcpListener, TcpStream},
+    };
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::handle_stream;
+
+    /// Connects to a server handling one connection.
+    fn connect() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let cli = Cli::parse_from(["server"]);
+            let (stream, _) = listener.accept().unwrap();
+            handle_stream(&cli, &Chain::default(), stream).unwrap();
+        });
+        TcpStream::connect(address).unwrap()
+    }
+
+    fn send(stream: &mut TcpStream, frames: &[Frame]) {
+        let mut out = vec![];
+        for frame in frames {
+            frame.encode(&mut out);
+        }
+        stream.write_all(&out).unwrap();
+    }
+
+    fn request_headers(stream: u32, path: &str, end_stream: bool) -> Frame {
+        let method = if end_stream { "GET" } else { "POST" };
+        let fields = [
+            (":method", method),
+            (":scheme", "http"),
+            (":path", path),
+            (":authority", "localhost"),
+            ("content-type", "application/x-www-form-urlencoded"),
+        ];
+        Frame::Headers {
+            stream,
+            block: Encoder.encode(fields.map(|(n, v)| (n.as_bytes(), v.as_bytes()))),
+            end_stream,
+            end_headers: true,
+        }
+    }
+
+    /// Reads frames until `streams` responses ended, returns their headers and bodies.
+    fn responses(
+        stream: &mut TcpStream,
+        mut streams: usize,
+    ) -> HashMap<u32, (Vec<Field>, Vec<u8>)> {
+        let mut decoder = Decoder::new();
+        let mut responses: HashMap<u32, (Vec<Field>, Vec<u8>)> = HashMap::new();
+        while streams > 0 {
+            let (id, end_stream) = match Frame::read(stream, DEFAULT_MAX_FRAME_SIZE).unwrap() {
+                Frame::Headers {
+                    stream,
+                    block,
+                    end_stream,
+                    ..
+                } => {
+                    responses.entry(stream).or_default().0 = decoder.decode(&block).unwrap();
+                    (stream, end_stream)
+                }
+                Frame::Data {
+                    stream,
+                    data,
+                    end_stream,
+                    ..
+                } => {
+                    responses.entry(stream).or_default().1.extend(data);
+                    (stream, end_stream)
+                }
+                _ => continue,
+            };
+            if end_stream {
+                assert!(responses.contains_key(&id));
+                streams -= 1;
+            }
+        }
+        responses
+    }
+
+    fn field<'a>(fields: &'a [Field], name: &str) -> Option<&'a [u8]> {
+        fields
+            .iter()
+            .find(|(n, _)| n == name.as_bytes())
+            .map(|(_, v)| v.as_slice())
+    }
+
+    #[test]
+    fn prior_knowledge() {
+        let mut stream = connect();
+        stream.write_all(PREFACE).unwrap();
+        send(
+            &mut stream,
+            &[
+                Frame::Settings {
+                    ack: false,
+                    settings: vec![],
+                },
+                request_headers(1, "/echo/abc", true),
+                request_headers(3, "/echo", false),
+                Frame::Data {
+                    stream: 3,
+                    data: b"text=hello".to_vec(),
+                    padding: 2,
+                    end_stream: true,
+                },
+            ],
+        );
+
+        let responses = responses(&mut stream, 2);
+        let (headers, body) = &responses[&1];
+        assert_eq!(field(headers, ":status"), Some(&b"200"[..]));
+        assert_eq!(field(headers, "content-type"), Some(&b"text/plain"[..]));
+        assert_eq!(field(headers, "content-length"), Some(&b"3"[..]));
+        assert_eq!(body, b"abc");
+        assert_eq!(responses[&3].1, b"hello");
+    }
+
+    #[test]
+    fn upgrade() {
+        let mut stream = connect();
+        stream
+            .write_all(
+                b"GET /echo/up HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n",
+            )
+            .unwrap();
+        let switching =
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+        let mut head = vec![0; switching.len()];
+        stream.read_exact(&mut head).unwrap();
+        assert_eq!(head, switching);
+
+        stream.write_all(PREFACE).unwrap();
+        send(
+            &mut stream,
+            &[Frame::Settings {
+                ack: false,
+                settings: vec![],
+            }],
+        );
+        assert_eq!(responses(&mut stream, 1)[&1].1, b"up");
+    }
+
+    #[test]
+    fn flow_control() {
+        let mut stream = connect();
+        stream.write_all(PREFACE).unwrap();
+        send(
+            &mut stream,
+            &[
+                Frame::Settings {
+                    ack: false,
+                    settings: vec![(setting::INITIAL_WINDOW_SIZE, 4)],
+                },
+                request_headers(1, "/echo/0123456789", true),
+            ],
+        );
+
+        // the first four bytes fill the window
+        loop {
+            match Frame::read(&mut stream, DEFAULT_MAX_FRAME_SIZE).unwrap() {
+                Frame::Data {
+                    data, end_stream, ..
+                } => {
+                    assert_eq!((data.as_slice(), end_stream), (&b"0123"[..], false));
+                    break;
+                }
+                _ => continue,
+            }
+        }
+        send(
+            &mut stream,
+            &[Frame::WindowUpdate {
+                stream: 1,
+                increment: 100,
+            }],
+        );
+        assert_eq!(responses(&mut stream, 1)[&1].1, b"456789");
+    }
+
+    #[test]
+    fn connection_error() {
+        let mut stream = connect();
+        stream.write_all(PREFACE).unwrap();
+        // a request before the settings
+        send(&mut stream, &[request_headers(1, "/", true)]);
+        loop {
+            match Frame::read(&mut stream, DEFAULT_MAX_FRAME_SIZE).unwrap() {
+                Frame::GoAway { last_stream, code } => {
+                    assert_eq!((last_stream, code), (0, ErrorCode::ProtocolError as u32));
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    #[test]
+    fn rapid_reset() {
+        let mut stream = connect();
+        stream.write_all(PREFACE).unwrap();
+        let mut frames = vec![Frame::Settings {
+            ack: false,
+            settings: vec![],
+        }];
+        for id in (1..).step_by(2).take(MAX_RESETS_PER_SECOND as usize + 1) {
+            frames.push(request_headers(id, "/echo/a", true));
+            frames.push(Frame::RstStream {
+                stream: id,
+                // CANCEL
+                code: 0x8,
+            });
+        }
+        send(&mut stream, &frames);
+        loop {
+            match Frame::read(&mut stream, DEFAULT_MAX_FRAME_SIZE).unwrap() {
+                Frame::GoAway { code, .. } => {
+                    assert_eq!(code, ErrorCode::EnhanceYourCalm as u32);
+                    break;
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<Field> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_bytes().to_vec(), value.as_bytes().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn request_fields() {
+        let request = request(fields(&[
+            (":method", "POST"),
+            (":scheme", "http"),
+            (":path", "/echo?text=a"),
+            (":authority", "localhost:4221"),
+            ("user-agent", "curl"),
+        ]))
+        .unwrap();
+        assert_eq!(request.request_line.method, Method::Post);
+        assert_eq!(request.request_uri().raw_path(), b"/echo");
+        assert_eq!(request.request_uri().raw_query(), Some(&b"text=a"[..]));
+        assert_eq!(
+            request.headers().get(header::HOST),
+            Some(&b"localhost:4221"[..])
+        );
+        assert_eq!(
+            request.headers().get(header::USER_AGENT),
+            Some(&b"curl"[..])
+        );
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let base = [(":method", "GET"), (":scheme", "http"), (":path", "/")];
+        let malformed =
+            |extra: &[(&str, &str)]| request(fields(&[&base[..], extra].concat())).is_none();
+        assert!(!malformed(&[]));
+        assert!(malformed(&[("Host", "a")]));
+        assert!(malformed(&[("connection", "close")]));
+        assert!(malformed(&[("te", "gzip")]));
+        assert!(malformed(&[(":path", "/a")]));
+        assert!(malformed(&[(":status", "200")]));
+        assert!(malformed(&[("a", "b"), (":authority", "a")]));
+        assert!(malformed(&[("a", "b\r\nc: d")]));
+        assert!(request(fields(&[(":method", "GET"), (":path", "/")])).is_none());
+    }
+}
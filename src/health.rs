@@ -0,0 +1,252 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, Weak,
+    },
+};
+
+use crate::{
+    middleware::Middleware,
+    spec::{header, request::Method},
+    IdleConnection, Request, Response, Status,
+};
+
+/// Names of the files the readiness probe writes, `/files` never serves them.
+pub(crate) const PROBE_PREFIX: &str = ".readyz-";
+
+/// Middleware answering liveness and readiness probes with a JSON status.
+///
+/// The server is live as long as it answers. It is ready when the served
+/// directory can be read and written, and until it starts draining.
+///
+/// While draining, every response closes its connection and idle connections
+/// are closed. The listeners are closed as soon as the server drains, so the
+/// readiness probe only reports it on connections that were already open.
+#[derive(Debug)]
+pub struct Health {
+    health_path: String,
+    ready_path: String,
+    directory: Option<PathBuf>,
+    drain: Arc<Drain>,
+}
+
+/// Shared by the probes of every configuration the server was reloaded with.
+#[derive(Debug, Default)]
+struct Drain {
+    draining: AtomicBool,
+    /// Connections that waited for a request since the last drain, some may be gone.
+    idle: Mutex<Vec<Weak<IdleConnection>>>,
+}
+
+impl Health {
+    pub fn new(
+        health_path: impl Into<String>,
+        ready_path: impl Into<String>,
+        directory: Option<PathBuf>,
+    ) -> Health {
+        Health {
+            health_path: health_path.into(),
+            ready_path: ready_path.into(),
+            directory,
+            drain: Arc::default(),
+        }
+    }
+
+    /// Drains along with `previous`, the probes of a reloaded configuration.
+    pub fn draining_with(mut self, previous: &Health) -> Health {
+        self.drain = previous.drain.clone();
+        self
+    }
+
+    /// Reports the server as not ready from now on, while it keeps serving the
+    /// requests it received. Idle connections are closed.
+    pub fn drain(&self) {
+        let mut idle = self.idle();
+        self.drain.draining.store(true, Ordering::Relaxed);
+        for connection in idle.drain(..).filter_map(|connection| connection.upgrade()) {
+            connection.close();
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.drain.draining.load(Ordering::Relaxed)
+    }
+
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Weak<IdleConnection>>> {
+        self.drain
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn ready(&self) -> Response {
+        // without `--directory` there is nothing to check
+        let directory_ok = self.directory.as_deref().map(check_directory);
+        let draining = self.is_draining();
+        let (status, state) = match directory_ok != Some(false) && !draining {
+            true => (Status::OK, "ready"),
+            false => (Status::ServiceUnavailable, "not ready"),
+        };
+        let directory = match directory_ok {
+            Some(true) => "\"ok\"",
+            Some(false) => "\"unavailable\"",
+            None => "null",
+        };
+        json(
+            status,
+            format!(
+                "{{\"status\":\"{state}\",\"checks\":{{\"directory\":{directory},\"draining\":{draining}}}}}"
+            ),
+        )
+    }
+}
+
+impl Middleware for Health {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        if !self.handles(request) {
+            return None;
+        }
+        match request.request_uri().raw_path() == self.health_path.as_bytes() {
+            true => Some(json(Status::OK, "{\"status\":\"ok\"}".into())),
+            false => Some(self.ready()),
+        }
+    }
+
+    fn after(&self, _request: &Request, response: &mut Response) {
+        if self.is_draining() {
+            response.headers_mut().insert(header::CONNECTION, "close");
+        }
+    }
+
+    fn handles(&self, request: &Request) -> bool {
+        let path = request.request_uri().raw_path();
+        request.request_line.method == Method::Get
+            && (path == self.health_path.as_bytes() || path == self.ready_path.as_bytes())
+    }
+
+    fn idle(&self, connection: &Arc<IdleConnection>) {
+        // locked first, so a connection is not missed by a concurrent drain
+        let mut idle = self.idle();
+        if self.is_draining() {
+            connection.close();
+            return;
+        }
+        idle.retain(|connection| connection.strong_count() > 0);
+        if !idle
+            .iter()
+            .any(|idle| Weak::as_ptr(idle) == Arc::as_ptr(connection))
+        {
+            idle.push(Arc::downgrade(connection));
+        }
+    }
+}
+
+fn json(status: Status, body: String) -> Response {
+    let mut response = Response::new(status);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, "application/json");
+    response.set_body(body.into_bytes());
+    response
+}
+
+/// Whether files in `directory` can be listed, created and removed.
+fn check_directory(directory: &Path) -> bool {
+    static PROBES: AtomicU64 = AtomicU64::new(0);
+
+    if fs::read_dir(directory).is_err() {
+        return false;
+    }
+    let probe = directory.join(format!(
+        "{PROBE_PREFIX}{}-{}",
+        process::id(),
+        PROBES.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&probe, b"").is_ok() && fs::remove_file(probe).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parse;
+
+    fn probe(health: &Health, path: &str) -> Option<(Status, String)> {
+        let mut request = Request::convert(&format!("GET {path} HTTP/1.1\r\n\r\n")).unwrap();
+        health.before(&mut request).map(|response| {
+            (
+                response.status(),
+                String::from_utf8(response.body().to_vec()).unwrap(),
+            )
+        })
+    }
+
+    #[test]
+    fn health() {
+        let health = Health::new("/healthz", "/readyz", None);
+        assert_eq!(
+            probe(&health, "/healthz"),
+            Some((Status::OK, "{\"status\":\"ok\"}".into()))
+        );
+        assert_eq!(probe(&health, "/echo/a"), None);
+    }
+
+    #[test]
+    fn ready() {
+        let health = Health::new("/live", "/ready", Some(std::env::temp_dir()));
+        assert_eq!(
+            probe(&health, "/ready"),
+            Some((
+                Status::OK,
+                "{\"status\":\"ready\",\"checks\":{\"directory\":\"ok\",\"draining\":false}}"
+                    .into()
+            ))
+        );
+
+        health.drain();
+        assert_eq!(
+            probe(&health, "/ready"),
+            Some((
+                Status::ServiceUnavailable,
+                "{\"status\":\"not ready\",\"checks\":{\"directory\":\"ok\",\"draining\":true}}"
+                    .into()
+            ))
+        );
+        // still live while draining
+        assert_eq!(probe(&health, "/live").unwrap().0, Status::OK);
+
+        let reloaded = Health::new("/healthz", "/readyz", None).draining_with(&health);
+        assert!(reloaded.is_draining());
+    }
+
+    #[test]
+    fn close_while_draining() {
+        let health = Health::new("/healthz", "/readyz", None);
+        let request = Request::convert("GET /echo/a HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = Response::new(Status::OK);
+        health.after(&request, &mut response);
+        assert_eq!(response.headers().get(header::CONNECTION), None);
+
+        health.drain();
+        health.after(&request, &mut response);
+        assert_eq!(
+            response.headers().get(header::CONNECTION),
+            Some(&b"close"[..])
+        );
+    }
+
+    #[test]
+    fn ready_missing_directory() {
+        let health = Health::new("/healthz", "/readyz", Some("/nonexistent/directory".into()));
+        assert_eq!(
+            probe(&health, "/readyz"),
+            Some((
+                Status::ServiceUnavailable,
+                "{\"status\":\"not ready\",\"checks\":{\"directory\":\"unavailable\",\"draining\":false}}"
+                    .into()
+            ))
+        );
+    }
+}
@@ -1,22 +1,224 @@
+pub mod access_log;
+mod base64;
 pub mod bytes;
+pub mod client;
+#[cfg(feature = "config")]
+pub mod config;
+mod connection;
+pub mod event_stream;
+mod h2;
+pub mod health;
+pub mod metrics;
+pub mod middleware;
 pub mod parser;
+pub mod proxy;
 mod request;
 mod spec;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod websocket;
 
-use std::path::PathBuf;
+use std::{
+    io::{self, Read},
+    path::PathBuf,
+    time::Duration,
+};
 
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+
+use access_log::{AccessLog, LogFormat};
+use bytes::ToBytes;
+pub use connection::{handle_stream, IdleConnection, Transport};
+use health::Health;
+use middleware::{Chain, CloseConnection, Compression, Rejected};
+use parser::{Limit, Limits, ParseError};
+use proxy::{Proxies, ProxyRule};
 use request::Handler;
-pub use spec::request::Request;
+pub use spec::cookie::{Cookies, InvalidCookie, SameSite, SetCookie};
+pub use spec::header::{self, HeaderMap};
+pub use spec::request::{BodyLength, Request};
+pub use spec::response::{Response, Status};
+pub use spec::uri::RequestURI;
 
 #[derive(clap::Parser, Debug, Clone)]
 pub struct Cli {
+    /// Address to accept connections on, repeatable
+    #[arg(long, default_value = "127.0.0.1:4221")]
+    listen: Vec<String>,
     #[arg(long)]
     directory: Option<PathBuf>,
+    /// Maximum request line length in bytes
+    #[arg(long, default_value_t = Limits::default().request_line)]
+    max_request_line: usize,
+    /// Maximum size of all request headers in bytes
+    #[arg(long, default_value_t = Limits::default().header_bytes)]
+    max_header_bytes: usize,
+    /// Maximum number of request headers
+    #[arg(long, default_value_t = Limits::default().header_count)]
+    max_headers: usize,
+    /// Maximum request body size in bytes
+    #[arg(long, default_value_t = Limits::default().body)]
+    max_body: usize,
+    /// Maximum size in bytes of a request body read as JSON
+    #[arg(long, default_value_t = Limits::default().json)]
+    max_json_body: usize,
+    /// Handle pipelined requests without a body concurrently, responses keep their order
+    #[arg(long, overrides_with = "no_concurrent_pipelining")]
+    concurrent_pipelining: bool,
+    /// Handle pipelined requests one at a time, even when the config file says otherwise
+    #[arg(long, overrides_with = "concurrent_pipelining")]
+    no_concurrent_pipelining: bool,
+    /// Serve metrics in the Prometheus text format on /metrics
+    #[arg(long, overrides_with = "no_metrics")]
+    metrics: bool,
+    /// Do not serve metrics, even when the config file enables them
+    #[arg(long, overrides_with = "metrics")]
+    no_metrics: bool,
+    /// Write the access log to this file instead of stdout, it is reopened on SIGHUP
+    #[arg(long)]
+    access_log: Option<PathBuf>,
+    /// Format of the access log lines
+    #[arg(long, value_enum, default_value_t)]
+    access_log_format: LogFormat,
+    /// Never gzip responses, even for clients accepting it
+    #[arg(long, overrides_with = "compression")]
+    no_compression: bool,
+    /// Gzip responses for clients accepting it, even when the config file disables it
+    #[arg(long, overrides_with = "no_compression")]
+    compression: bool,
+    /// Path of the liveness probe
+    #[arg(long, default_value = "/healthz")]
+    health_path: String,
+    /// Path of the readiness probe, not ready while draining or when the directory is unusable
+    #[arg(long, default_value = "/readyz")]
+    ready_path: String,
+    /// Seconds open connections get to end after SIGTERM or SIGINT, no new ones are accepted
+    #[arg(long, default_value_t = 5)]
+    drain_seconds: u64,
+    /// Forward requests whose path starts with PATH to the upstream HOST:PORT, repeatable
+    #[arg(long, value_name = "PATH=HOST:PORT")]
+    proxy: Vec<ProxyRule>,
+    /// Seconds to wait on an upstream to connect, accept the request or send the response
+    #[arg(long, default_value_t = 30)]
+    proxy_timeout: u64,
+    /// PEM certificate chain to serve HTTPS with, repeat it with --tls-key for each name (SNI)
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_key")]
+    tls_cert: Vec<PathBuf>,
+    /// PEM private key of the --tls-cert at the same position
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert")]
+    tls_key: Vec<PathBuf>,
+    /// TOML file with the settings not given as flags, applied again on SIGHUP
+    #[cfg(feature = "config")]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Ids of the arguments given on the command line, they take precedence over the config file.
+    #[arg(skip)]
+    explicit: Vec<String>,
+}
+
+impl Cli {
+    /// Parses the arguments of the process, exiting with the usage on errors.
+    pub fn from_command_line() -> Cli {
+        let matches = Cli::command().get_matches();
+        Cli::from_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Also remembers which arguments were given, unlike `Cli::from_arg_matches`.
+    pub fn from_matches(matches: &ArgMatches) -> Result<Cli, clap::Error> {
+        let mut cli = Cli::from_arg_matches(matches)?;
+        cli.explicit = matches
+            .ids()
+            .filter(|id| matches.value_source(id.as_str()) == Some(ValueSource::CommandLine))
+            .map(|id| id.to_string())
+            .collect();
+        Ok(cli)
+    }
+
+    /// The settings of the `--config` file, overridden by the arguments given on the command line.
+    #[cfg(feature = "config")]
+    pub fn with_config(&self) -> Result<Cli, config::ConfigError> {
+        let mut cli = self.clone();
+        if let Some(path) = &self.config {
+            config::Config::load(path)?.apply(&mut cli)?;
+        }
+        Ok(cli)
+    }
+
+    pub fn listen(&self) -> &[String] {
+        &self.listen
+    }
+
+    /// The built-in middleware, see `Chain::default`.
+    pub fn middleware(&self) -> Chain {
+        let chain = Chain::empty().with(CloseConnection);
+        match self.no_compression {
+            true => chain,
+            false => chain.with(Compression),
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            request_line: self.max_request_line,
+            header_bytes: self.max_header_bytes,
+            header_count: self.max_headers,
+            body: self.max_body,
+            json: self.max_json_body,
+        }
+    }
+
+    pub fn health(&self) -> Health {
+        Health::new(&self.health_path, &self.ready_path, self.directory.clone())
+    }
+
+    pub fn drain_timeout(&self) -> Duration {
+        Duration::from_secs(self.drain_seconds)
+    }
+
+    pub fn proxies(&self) -> Proxies {
+        Proxies {
+            rules: self.proxy.clone(),
+            timeout: Duration::from_secs(self.proxy_timeout),
+        }
+    }
+
+    /// The certificates to serve HTTPS with, `None` to serve plaintext.
+    #[cfg(feature = "tls")]
+    pub fn tls_certificates(&self) -> io::Result<Option<tls::Certificates>> {
+        if self.tls_cert.is_empty() {
+            return Ok(None);
+        }
+        if self.tls_cert.len() != self.tls_key.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "each --tls-cert needs its --tls-key",
+            ));
+        }
+        let files = self
+            .tls_cert
+            .iter()
+            .cloned()
+            .zip(self.tls_key.iter().cloned());
+        tls::Certificates::load(files.collect()).map(Some)
+    }
+
+    pub fn access_log(&self) -> io::Result<AccessLog> {
+        match &self.access_log {
+            Some(path) => AccessLog::file(path, self.access_log_format),
+            None => Ok(AccessLog::stdout(self.access_log_format)),
+        }
+    }
 }
 
 pub enum ServerResponse {
     Continue(Vec<u8>),
     Close(Vec<u8>),
+    /// A `101 Switching Protocols` response, the connection is handed over afterwards.
+    Upgrade(Vec<u8>),
+    /// The head of a response whose body the route writes afterwards, until it closes the connection.
+    Stream(Vec<u8>),
 }
 
 impl ServerResponse {
@@ -24,11 +226,63 @@ impl ServerResponse {
         match self {
             ServerResponse::Continue(data) => data,
             ServerResponse::Close(data) => data,
+            ServerResponse::Upgrade(data) => data,
+            ServerResponse::Stream(data) => data,
         }
     }
 }
 
-pub fn handle_request(cli: Cli, request: Request) -> ServerResponse {
-    let handler = Handler::new(request, cli.directory);
-    handler.process()
+pub fn handle_request(
+    cli: Cli,
+    chain: &Chain,
+    request: Request,
+    body: &mut dyn Read,
+) -> ServerResponse {
+    let limits = cli.limits();
+    let proxies = cli.proxies();
+    let handler = Handler::new(request, body, cli.directory, limits, cli.metrics, proxies);
+    handler.process(chain)
+}
+
+/// Handles a request whose response is framed by the caller, like HTTP/2 streams.
+pub(crate) fn respond(cli: &Cli, chain: &Chain, request: Request, body: &mut dyn Read) -> Response {
+    let handler = Handler::new(
+        request,
+        body,
+        cli.directory.clone(),
+        cli.limits(),
+        cli.metrics,
+        cli.proxies(),
+    );
+    handler.respond(chain)
+}
+
+/// Answers `Expect: 100-continue` before the body is read. `Continue` asks for the interim
+/// `100 Continue` to be sent before handling the request, any other status is the one of
+/// a final response, see [`reject`].
+pub fn handle_expect(cli: &Cli, chain: &Chain, request: &Request) -> Option<Status> {
+    Handler::expect(request, &cli.proxies(), chain)
+}
+
+/// Maps a request that failed to parse to the status of the response sent before closing
+/// the connection. Returns `None` when the connection itself failed and nothing can be sent.
+pub fn handle_parse_error(error: &ParseError) -> Option<Status> {
+    let status = match error {
+        ParseError::Syntax { .. } | ParseError::Incomplete { .. } => Status::BadRequest,
+        ParseError::LimitExceeded(Limit::RequestLine) => Status::URITooLong,
+        ParseError::LimitExceeded(Limit::HeaderBytes | Limit::HeaderCount) => {
+            Status::RequestHeaderFieldsTooLarge
+        }
+        ParseError::LimitExceeded(Limit::Body) => Status::PayloadTooLarge,
+        ParseError::Io(_) => return None,
+    };
+    Some(status)
+}
+
+/// The response to a request rejected before routing, the connection is closed afterwards.
+/// It does not go through the middleware, which only gets to see it, like the access log.
+pub(crate) fn reject(chain: &Chain, request: Rejected, status: Status) -> ServerResponse {
+    let response = Handler::reject(status);
+    chain.rejected(request, &response);
+    ServerResponse::Close(response.into_bytes())
 }
@@ -1,48 +1,267 @@
 use std::{
-    io::Write,
-    net::{TcpListener, TcpStream},
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError, RwLock,
+    },
     thread,
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
-use clap::Parser;
+#[cfg(feature = "tls")]
+use codecrafters_http_server::tls::{Certificates, TlsStream};
 use codecrafters_http_server::{
-    handle_request, parser::StreamParser, Cli, Request, ServerResponse,
+    access_log::AccessLog, handle_stream, health::Health, metrics, middleware::Chain, Cli,
+};
+use signal_hook::{
+    consts::{SIGHUP, SIGINT, SIGTERM},
+    iterator::Signals,
 };
 
-fn main() -> Result<()> {
-    // You can use print statements as follows for debugging, they'll be visible when running tests.
-    println!("Logs from your program will appear here!");
+/// What connections are served with, replaced as a whole when the config is reloaded.
+struct Server {
+    cli: Cli,
+    chain: Chain,
+    access_log: Arc<AccessLog>,
+    health: Arc<Health>,
+    #[cfg(feature = "tls")]
+    certificates: Option<Arc<Certificates>>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<Arc<rustls::ServerConfig>>,
+}
 
-    let cli = Cli::parse();
+impl Server {
+    /// `previous` is the server being reloaded, its draining state carries over.
+    fn new(cli: Cli, previous: Option<&Server>) -> io::Result<Server> {
+        let access_log = Arc::new(cli.access_log()?);
+        let health = match previous {
+            Some(previous) => cli.health().draining_with(&previous.health),
+            None => cli.health(),
+        };
+        let health = Arc::new(health);
+        let chain = cli
+            .middleware()
+            .wrap(access_log.clone())
+            .with(health.clone());
+        #[cfg(feature = "tls")]
+        let certificates = cli.tls_certificates()?.map(Arc::new);
+        #[cfg(feature = "tls")]
+        let tls_config = certificates
+            .as_ref()
+            .map(|certificates| certificates.server_config())
+            .transpose()?;
+        Ok(Server {
+            cli,
+            chain,
+            access_log,
+            health,
+            #[cfg(feature = "tls")]
+            certificates,
+            #[cfg(feature = "tls")]
+            tls_config,
+        })
+    }
+}
+
+type Current = Arc<RwLock<Arc<Server>>>;
+
+/// The accept loop of each address, stopped by setting its flag and
+/// connecting to the local address to wake it.
+struct Listeners {
+    current: Current,
+    running: HashMap<String, (Arc<AtomicBool>, SocketAddr)>,
+}
 
-    // Uncomment this block to pass the first stage
+impl Listeners {
+    /// Listens on the addresses of the current server and stops listening on
+    /// the others, on all of them once draining. Nothing changes unless every
+    /// new address could be bound.
+    fn update(&mut self) -> io::Result<()> {
+        let server = self
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let addresses = match server.health.is_draining() {
+            true => &[],
+            false => server.cli.listen(),
+        };
+        let mut bound = vec![];
+        for address in addresses {
+            if !self.running.contains_key(address) {
+                let listener = TcpListener::bind(address)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{address}: {e}")))?;
+                bound.push((address.clone(), listener));
+            }
+        }
+        for (address, listener) in bound {
+            let local = listener.local_addr()?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let current = self.current.clone();
+            let stopped = stop.clone();
+            thread::spawn(move || accept(listener, &stopped, &current));
+            self.running.insert(address, (stop, local));
+        }
+        self.running.retain(|address, (stop, local)| {
+            let keep = addresses.contains(address);
+            if !keep {
+                stop.store(true, Ordering::Relaxed);
+                wake(*local);
+            }
+            keep
+        });
+        Ok(())
+    }
+}
 
-    let listener = TcpListener::bind("127.0.0.1:4221").unwrap();
+/// Unblocks the accept loop listening on `address` with a connection it drops.
+fn wake(address: SocketAddr) {
+    let mut address = address;
+    if address.ip().is_unspecified() {
+        address.set_ip(match address.ip() {
+            IpAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+            IpAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+        });
+    }
+    if let Err(e) = TcpStream::connect_timeout(&address, Duration::from_secs(1)) {
+        println!("{}", e);
+    }
+}
 
-    for stream in listener.incoming() {
-        let stream = stream.unwrap();
-        let cli = cli.clone();
+/// Serves the connections of `listener` with the current server until `stop` is set.
+fn accept(listener: TcpListener, stop: &AtomicBool, current: &Current) {
+    // failures such as running out of file descriptors tend to repeat at once
+    const BACKOFF: Duration = Duration::from_millis(10);
+    const MAX_BACKOFF: Duration = Duration::from_secs(1);
+    let mut backoff = BACKOFF;
+    loop {
+        let accepted = listener.accept();
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let stream = match accepted {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                println!("{}", e);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        };
+        backoff = BACKOFF;
+        // the connection keeps this configuration until it ends, even across reloads
+        let server = current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let active = metrics::connection_opened();
         thread::spawn(move || {
-            handle_stream(cli, stream).unwrap();
+            #[cfg(feature = "tls")]
+            let served = match server.tls_config.clone() {
+                Some(config) => TlsStream::new(stream, config)
+                    .and_then(|stream| handle_stream(&server.cli, &server.chain, stream)),
+                None => handle_stream(&server.cli, &server.chain, stream),
+            };
+            #[cfg(not(feature = "tls"))]
+            let served = handle_stream(&server.cli, &server.chain, stream);
+            // a failed handshake or a connection reset by the client only ends this connection
+            if let Err(e) = served {
+                println!("{}", e);
+            }
+            drop(active);
         });
     }
+}
 
+/// Reads the config file again and applies it to new connections, if it is
+/// valid. The TLS certificates are loaded again with it.
+#[cfg(feature = "config")]
+fn reload(arguments: &Cli, listeners: &mut Listeners) -> Result<()> {
+    let cli = arguments.with_config()?;
+    let previous = listeners
+        .current
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    let server = Arc::new(Server::new(cli, Some(&previous))?);
+    *listeners
+        .current
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = server;
+    if let Err(e) = listeners.update() {
+        // the new addresses could not be bound, the previous server goes on
+        *listeners
+            .current
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = previous;
+        return Err(e.into());
+    }
     Ok(())
 }
 
-fn handle_stream(cli: Cli, mut stream: TcpStream) -> Result<()> {
-    loop {
-        let mut parser = StreamParser::new(&stream);
-        match parser.parse::<Request>() {
-            Ok(request) => {
-                let resp = handle_request(cli.clone(), request);
-                stream.write_all(resp.data())?;
-                if let ServerResponse::Close(_) = resp {
-                    break Ok(());
+fn main() -> Result<()> {
+    // You can use print statements as follows for debugging, they'll be visible when running tests.
+    println!("Logs from your program will appear here!");
+
+    let arguments = Cli::from_command_line();
+    #[cfg(feature = "config")]
+    let cli = arguments.with_config()?;
+    #[cfg(not(feature = "config"))]
+    let cli = arguments;
+
+    let drain_timeout = cli.drain_timeout();
+    let mut listeners = Listeners {
+        current: Arc::new(RwLock::new(Arc::new(Server::new(cli, None)?))),
+        running: HashMap::new(),
+    };
+    listeners.update()?;
+
+    let mut signals = Signals::new([SIGHUP, SIGINT, SIGTERM])?;
+    for signal in signals.forever() {
+        let server = listeners
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        match signal {
+            SIGHUP => {
+                // connections accepted before a reload keep logging to this one
+                if let Err(e) = server.access_log.reopen() {
+                    println!("{}", e);
+                }
+                #[cfg(feature = "tls")]
+                if let Some(certificates) = &server.certificates {
+                    if let Err(e) = certificates.reload() {
+                        println!("{}", e);
+                    }
                 }
+                #[cfg(feature = "config")]
+                if let Err(e) = reload(&arguments, &mut listeners) {
+                    println!("config not reloaded: {}", e);
+                }
+            }
+            // a second signal does not wait for the draining to end
+            _ if server.health.is_draining() => process::exit(0),
+            _ => {
+                // no new connections, the open ones get until the timeout to end
+                server.health.drain();
+                if let Err(e) = listeners.update() {
+                    println!("{}", e);
+                }
+                thread::spawn(move || {
+                    let deadline = Instant::now() + drain_timeout;
+                    while metrics::active_connections() > 0 && Instant::now() < deadline {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    process::exit(0);
+                });
             }
-            Err(e) => println!("{}", e),
         }
     }
+
+    Ok(())
 }
@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Metrics of the whole process, exposed on `/metrics` when enabled.
+pub(crate) static METRICS: Metrics = Metrics::new();
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Not cumulative, each count is only for its own bucket.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug)]
+pub(crate) struct Metrics {
+    /// Keyed by route, method and status code.
+    requests: Mutex<BTreeMap<(&'static str, &'static str, u16), u64>>,
+    latency: Mutex<BTreeMap<&'static str, Histogram>>,
+    received_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+    active_connections: AtomicU64,
+    gzip_saved_bytes: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Metrics {
+        Metrics {
+            requests: Mutex::new(BTreeMap::new()),
+            latency: Mutex::new(BTreeMap::new()),
+            received_bytes: AtomicU64::new(0),
+            sent_bytes: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            gzip_saved_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_request(
+        &self,
+        route: &'static str,
+        method: &[u8],
+        status: u16,
+        latency: Duration,
+    ) {
+        // any method is accepted, a label per method would let clients grow the metrics
+        let method = match method {
+            b"GET" => "GET",
+            b"HEAD" => "HEAD",
+            b"POST" => "POST",
+            b"PUT" => "PUT",
+            b"DELETE" => "DELETE",
+            b"PATCH" => "PATCH",
+            b"OPTIONS" => "OPTIONS",
+            _ => "OTHER",
+        };
+        *lock(&self.requests)
+            .entry((route, method, status))
+            .or_default() += 1;
+
+        let seconds = latency.as_secs_f64();
+        let mut histograms = lock(&self.latency);
+        let histogram = histograms.entry(route).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|&le| seconds <= le) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.sent_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_gzip(&self, original: usize, compressed: usize) {
+        let saved = original.saturating_sub(compressed) as u64;
+        self.gzip_saved_bytes.fetch_add(saved, Ordering::Relaxed);
+    }
+
+    /// The text exposition format of Prometheus.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "http_requests_total",
+            "counter",
+            "Requests handled.",
+        );
+        for ((route, method, status), count) in lock(&self.requests).iter() {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{route=\"{route}\",method=\"{}\",status=\"{status}\"}} {count}",
+                escape(method)
+            );
+        }
+
+        header(
+            &mut out,
+            "http_request_duration_seconds",
+            "histogram",
+            "Time from reading the request head to having the response.",
+        );
+        for (route, histogram) in lock(&self.latency).iter() {
+            let mut cumulative = 0;
+            for (le, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}\n\
+                 http_request_duration_seconds_sum{{route=\"{route}\"}} {}\n\
+                 http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                histogram.count, histogram.sum, histogram.count
+            );
+        }
+
+        for (name, kind, help, value) in [
+            (
+                "http_received_bytes_total",
+                "counter",
+                "Bytes read from clients.",
+                &self.received_bytes,
+            ),
+            (
+                "http_sent_bytes_total",
+                "counter",
+                "Bytes written to clients.",
+                &self.sent_bytes,
+            ),
+            (
+                "http_active_connections",
+                "gauge",
+                "Connections currently open.",
+                &self.active_connections,
+            ),
+            (
+                "http_gzip_saved_bytes_total",
+                "counter",
+                "Bytes saved by compressing response bodies.",
+                &self.gzip_saved_bytes,
+            ),
+        ] {
+            header(&mut out, name, kind, help);
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+        out
+    }
+}
+
+/// Counts a connection as active until dropped.
+#[derive(Debug)]
+pub struct ActiveConnection(());
+
+/// Marks an accepted connection as active, for as long as the returned value lives.
+pub fn connection_opened() -> ActiveConnection {
+    METRICS.active_connections.fetch_add(1, Ordering::Relaxed);
+    ActiveConnection(())
+}
+
+/// The number of connections currently active.
+pub fn active_connections() -> u64 {
+    METRICS.active_connections.load(Ordering::Relaxed)
+}
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        METRICS.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts the bytes read from `R` as received.
+pub(crate) struct CountReceived<R>(pub R);
+
+impl<R: Read> Read for CountReceived<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.0.read(buf)?;
+        METRICS
+            .received_bytes
+            .fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    // counters are still usable after a panic elsewhere
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::new();
+        metrics.record_request("echo", b"GET", 200, Duration::from_millis(20));
+        metrics.record_request("echo", b"GET", 200, Duration::from_secs(20));
+        metrics.record_request("unknown", b"POST", 404, Duration::ZERO);
+        metrics.record_request("unknown", b"BREW", 404, Duration::ZERO);
+        metrics.record_request("unknown", b"COFFEE", 404, Duration::ZERO);
+        metrics.record_sent(10);
+        metrics.record_gzip(100, 30);
+
+        let rendered = metrics.render();
+        for line in [
+            "# TYPE http_requests_total counter",
+            "http_requests_total{route=\"echo\",method=\"GET\",status=\"200\"} 2",
+            "http_requests_total{route=\"unknown\",method=\"POST\",status=\"404\"} 1",
+            "http_requests_total{route=\"unknown\",method=\"OTHER\",status=\"404\"} 2",
+            "# TYPE http_request_duration_seconds histogram",
+            "http_request_duration_seconds_bucket{route=\"echo\",le=\"0.01\"} 0",
+            "http_request_duration_seconds_bucket{route=\"echo\",le=\"0.025\"} 1",
+            "http_request_duration_seconds_bucket{route=\"echo\",le=\"10\"} 1",
+            "http_request_duration_seconds_bucket{route=\"echo\",le=\"+Inf\"} 2",
+            "http_request_duration_seconds_sum{route=\"echo\"} 20.02",
+            "http_request_duration_seconds_count{route=\"echo\"} 2",
+            "http_sent_bytes_total 10",
+            "http_gzip_saved_bytes_total 70",
+            "# TYPE http_active_connections gauge",
+        ] {
+            assert!(rendered.lines().any(|l| l == line), "missing {line:?}");
+        }
+    }
+
+    #[test]
+    fn escape_label() {
+        assert_eq!(escape("a\"b\\c\n"), "a\\\"b\\\\c\\n");
+    }
+}
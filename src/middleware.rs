@@ -0,0 +1,333 @@
+use std::{io::Write, net::SocketAddr, sync::Arc};
+
+use flate2::write::GzEncoder;
+
+use crate::{
+    event_stream::is_event_stream,
+    metrics::METRICS,
+    spec::{header, message::parameters},
+    IdleConnection, Request, Response, Status,
+};
+
+/// Code run around routing, to inspect or change requests and responses.
+///
+/// `before` is called in the order middleware was added and `after` in the
+/// reverse order, so the first middleware sees the request first and the
+/// response last.
+pub trait Middleware: Send + Sync {
+    /// Runs before routing. Returning a response skips the route and the
+    /// middleware added after this one, their `after` is not called either.
+    fn before(&self, _request: &mut Request) -> Option<Response> {
+        None
+    }
+
+    /// Runs on the response, whether it came from a route or from a `before`.
+    fn after(&self, _request: &Request, _response: &mut Response) {}
+
+    /// Whether `before` answers `request` itself, so it is not refused for lacking
+    /// a route, like when it asks for `100 Continue`.
+    fn handles(&self, _request: &Request) -> bool {
+        false
+    }
+
+    /// Runs whenever an HTTP/1 connection starts waiting for its next request.
+    fn idle(&self, _connection: &Arc<IdleConnection>) {}
+
+    /// Runs on the response to a request rejected before `before` could be called,
+    /// like a malformed one, the response is sent as it is.
+    fn rejected(&self, _request: Rejected, _response: &Response) {}
+}
+
+/// A request answered by the server itself, without going through the chain.
+#[derive(Debug, Clone, Copy)]
+pub enum Rejected<'a> {
+    /// Parsed, but not handled, like one with an unsupported `Expect`.
+    Request(&'a Request),
+    /// Could not be parsed, only the address of the client is known.
+    Unparsed(Option<SocketAddr>),
+}
+
+/// Lets the caller keep a handle on middleware it added to a chain.
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    fn before(&self, request: &mut Request) -> Option<Response> {
+        (**self).before(request)
+    }
+
+    fn after(&self, request: &Request, response: &mut Response) {
+        (**self).after(request, response)
+    }
+
+    fn handles(&self, request: &Request) -> bool {
+        (**self).handles(request)
+    }
+
+    fn idle(&self, connection: &Arc<IdleConnection>) {
+        (**self).idle(connection)
+    }
+
+    fn rejected(&self, request: Rejected, response: &Response) {
+        (**self).rejected(request, response)
+    }
+}
+
+/// The middleware wrapped around every request, in order.
+#[derive(Clone)]
+pub struct Chain(Vec<Arc<dyn Middleware>>);
+
+impl Chain {
+    /// A chain without any middleware, not even the built-in ones.
+    pub fn empty() -> Chain {
+        Chain(vec![])
+    }
+
+    /// Adds `middleware` inside of the ones already added.
+    pub fn with(mut self, middleware: impl Middleware + 'static) -> Chain {
+        self.0.push(Arc::new(middleware));
+        self
+    }
+
+    /// Adds `middleware` outside of the ones already added.
+    pub fn wrap(mut self, middleware: impl Middleware + 'static) -> Chain {
+        self.0.insert(0, Arc::new(middleware));
+        self
+    }
+
+    /// Runs the `before` of each middleware until one answers the request,
+    /// returns that response and how many middleware ran.
+    pub(crate) fn before(&self, request: &mut Request) -> (Option<Response>, usize) {
+        for (i, middleware) in self.0.iter().enumerate() {
+            if let Some(response) = middleware.before(request) {
+                return (Some(response), i + 1);
+            }
+        }
+        (None, self.0.len())
+    }
+
+    /// Runs the `after` of the first `ran` middleware, innermost first.
+    pub(crate) fn after(&self, ran: usize, request: &Request, response: &mut Response) {
+        for middleware in self.0[..ran].iter().rev() {
+            middleware.after(request, response);
+        }
+    }
+
+    /// Whether one of the middleware answers `request` itself.
+    pub(crate) fn handles(&self, request: &Request) -> bool {
+        self.0.iter().any(|middleware| middleware.handles(request))
+    }
+
+    /// Runs the `idle` of every middleware.
+    pub(crate) fn idle(&self, connection: &Arc<IdleConnection>) {
+        for middleware in &self.0 {
+            middleware.idle(connection);
+        }
+    }
+
+    /// Runs the `rejected` of every middleware, innermost first.
+    pub(crate) fn rejected(&self, request: Rejected, response: &Response) {
+        for middleware in self.0.iter().rev() {
+            middleware.rejected(request, response);
+        }
+    }
+}
+
+impl Default for Chain {
+    /// The built-in middleware: `Connection: close` handling, then compression.
+    fn default() -> Chain {
+        Chain::empty().with(CloseConnection).with(Compression)
+    }
+}
+
+/// Answers `Connection: close` in kind, the connection is closed after the response.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CloseConnection;
+
+impl Middleware for CloseConnection {
+    fn after(&self, request: &Request, response: &mut Response) {
+        if let Some(connection) = request.headers.get_field_value(header::CONNECTION) {
+            if connection
+                .list()
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"close"))
+            {
+                response.headers.insert(header::CONNECTION, "close");
+            }
+        }
+    }
+}
+
+/// Gzips the response body when the client accepts it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Compression;
+
+impl Middleware for Compression {
+    fn after(&self, request: &Request, response: &mut Response) {
+        // the body is written after the head, gzip would hold events back
+        if response.status() == Status::SwitchingProtocols || is_event_stream(response) {
+            return;
+        }
+        // caches must tell the compressed and the plain response apart
+        let varies = response.headers.get_all(header::VARY).any(|vary| {
+            vary.split(|&c| c == b',')
+                .any(|name| name.trim_ascii().eq_ignore_ascii_case(b"Accept-Encoding"))
+        });
+        if !varies {
+            response
+                .headers
+                .append(header::VARY, header::ACCEPT_ENCODING);
+        }
+        // already encoded, like a response relayed from an upstream
+        if response.headers.contains_key(header::CONTENT_ENCODING) {
+            return;
+        }
+        let Some(accept_encoding) = request.headers.get_field_value(header::ACCEPT_ENCODING) else {
+            return;
+        };
+        let gzip = accept_encoding
+            .list()
+            .into_iter()
+            .map(parameters)
+            // `q=0` marks a coding as not acceptable
+            .filter(|(_, params)| !params.iter().any(|(name, q)| name == b"q" && is_zero(q)))
+            .any(|(coding, _)| coding.eq_ignore_ascii_case(b"gzip"));
+        if !gzip {
+            return;
+        }
+
+        // there is nothing to encode without a body
+        let Some(body) = response.body.as_mut().filter(|body| !body.0.is_empty()) else {
+            return;
+        };
+        let mut e = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        e.write_all(&body.0).expect("failed to encode using gzip");
+        let compressed = e.finish().expect("failed to encode gzip");
+        METRICS.record_gzip(body.0.len(), compressed.len());
+        body.0 = compressed;
+        response.headers.insert(header::CONTENT_ENCODING, "gzip");
+    }
+}
+
+fn is_zero(q: &[u8]) -> bool {
+    std::str::from_utf8(q)
+        .ok()
+        .and_then(|q| q.parse::<f32>().ok())
+        .is_some_and(|q| q == 0.0)
+}
+
+#[cfg(test)]
+mod test {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    use super::*;
+    use crate::{parser::Parse, Status};
+
+    fn run(chain: &Chain, request: &str) -> Response {
+        let mut request = Request::convert(request).unwrap();
+        let (response, ran) = chain.before(&mut request);
+        let mut response = response.unwrap_or_else(|| {
+            let mut response = Response::new(Status::OK);
+            response.set_body(b"body".to_vec());
+            response
+        });
+        chain.after(ran, &request, &mut response);
+        response
+    }
+
+    #[test]
+    fn close_connection() {
+        let chain = Chain::default();
+        let response = run(
+            &chain,
+            "GET / HTTP/1.1\r\nConnection: keep-alive, Close\r\n\r\n",
+        );
+        assert_eq!(
+            response.headers().get(header::CONNECTION),
+            Some(&b"close"[..])
+        );
+        let response = run(&chain, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.headers().get(header::CONNECTION), None);
+    }
+
+    #[test]
+    fn compression() {
+        let chain = Chain::default();
+        let response = run(
+            &chain,
+            "GET / HTTP/1.1\r\nAccept-Encoding: br, gzip\r\n\r\n",
+        );
+        assert_eq!(
+            response.headers().get(header::CONTENT_ENCODING),
+            Some(&b"gzip"[..])
+        );
+        let mut body = vec![];
+        GzDecoder::new(response.body())
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"body");
+
+        let response = run(
+            &chain,
+            "GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0\r\n\r\n",
+        );
+        assert_eq!(response.headers().get(header::CONTENT_ENCODING), None);
+        assert_eq!(response.body(), b"body");
+        assert_eq!(
+            response.headers().get(header::VARY),
+            Some(&b"Accept-Encoding"[..])
+        );
+    }
+
+    #[test]
+    fn compression_without_body() {
+        let chain = Chain::empty().with(Compression);
+        let mut request =
+            Request::convert("GET / HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n").unwrap();
+        let mut response = Response::new(Status::NotFound);
+        chain.after(chain.before(&mut request).1, &request, &mut response);
+        assert_eq!(response.headers().get(header::CONTENT_ENCODING), None);
+        assert_eq!(
+            response.headers().get(header::VARY),
+            Some(&b"Accept-Encoding"[..])
+        );
+    }
+
+    struct Deny;
+
+    impl Middleware for Deny {
+        fn before(&self, request: &mut Request) -> Option<Response> {
+            match request.headers().contains_key("Authorization") {
+                true => None,
+                false => Some(Response::new(Status::Unauthorized)),
+            }
+        }
+    }
+
+    struct Tag(&'static str);
+
+    impl Middleware for Tag {
+        fn after(&self, _request: &Request, response: &mut Response) {
+            response.headers_mut().append("X-Tag", self.0);
+        }
+    }
+
+    #[test]
+    fn short_circuit() {
+        let chain = Chain::empty()
+            .with(Tag("outer"))
+            .with(Deny)
+            .with(Tag("inner"));
+        let response = run(&chain, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.headers().get_all("X-Tag").collect::<Vec<_>>(),
+            vec![&b"outer"[..]]
+        );
+
+        let response = run(&chain, "GET / HTTP/1.1\r\nAuthorization: yes\r\n\r\n");
+        assert_eq!(response.status(), Status::OK);
+        assert_eq!(
+            response.headers().get_all("X-Tag").collect::<Vec<_>>(),
+            vec![&b"inner"[..], b"outer"]
+        );
+    }
+}
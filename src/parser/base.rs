@@ -1,10 +1,9 @@
 use std::{
-    io::{BufReader, ErrorKind, Read},
+    io::{self, BufReader, ErrorKind, Read},
     thread,
     time::Duration,
 };
 
-use anyhow::{bail, Result};
 use winnow::{
     ascii::Caseless,
     error::ErrMode,
@@ -12,6 +11,11 @@ use winnow::{
     ModalResult, Partial,
 };
 
+use super::{
+    error::{Limit, ParseError},
+    limits::Limits,
+};
+
 pub trait Convertible<'i>:
     Stream<Slice = &'i [u8]>
     + Compare<&'static str>
@@ -43,7 +47,12 @@ pub trait Parse {
         I: Convertible<'i>,
         I::Token: AsChar;
 
-    fn convert(b: &str) -> Result<Self>
+    /// Checks the input received so far against `limits`, before reading any more of it.
+    fn check_limits(_input: &[u8], _limits: &Limits) -> Result<(), Limit> {
+        Ok(())
+    }
+
+    fn convert(b: &str) -> Result<Self, ParseError>
     where
         Self: std::marker::Sized,
         Self: std::fmt::Debug,
@@ -54,25 +63,35 @@ pub trait Parse {
 
 #[derive(Debug)]
 pub struct StreamParser<R: Read> {
-    reader: BufReader<R>,
+    pub(super) reader: BufReader<R>,
+    pub(super) limits: Limits,
     pub buffer: Vec<u8>,
 }
 
 impl<R: Read> StreamParser<R> {
     pub fn new(reader: R) -> StreamParser<R> {
+        StreamParser::with_limits(reader, Limits::default())
+    }
+
+    pub fn with_limits(reader: R, limits: Limits) -> StreamParser<R> {
         StreamParser {
             reader: BufReader::new(reader),
+            limits,
             buffer: vec![],
         }
     }
 
-    pub fn parse<T>(&mut self) -> Result<T>
+    pub fn parse<T>(&mut self) -> Result<T, ParseError>
     where
         T: Parse + std::fmt::Debug,
     {
         let mut buffer = [0; 4096];
 
         loop {
+            if let Err(limit) = T::check_limits(&self.buffer, &self.limits) {
+                break Err(ParseError::LimitExceeded(limit));
+            }
+
             let mut partial = Partial::new(self.buffer.as_slice());
             let start = partial.checkpoint();
             match T::parse(&mut partial) {
@@ -89,10 +108,8 @@ impl<R: Read> StreamParser<R> {
                             thread::sleep(Duration::from_millis(100));
                             continue;
                         }
-                        Err(e) => {
-                            dbg!(e);
-                            0
-                        }
+                        Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => break Err(ParseError::Io(e)),
                     };
                     if n == 0 {
                         break self.parse_complete();
@@ -100,12 +117,15 @@ impl<R: Read> StreamParser<R> {
                     self.buffer.extend_from_slice(&buffer[..n]);
                 }
 
-                Err(e) => bail!("error {e}"),
+                Err(ErrMode::Backtrack(e) | ErrMode::Cut(e)) => {
+                    let offset = partial.offset_from(&start);
+                    break Err(ParseError::from_context(e, offset));
+                }
             }
         }
     }
 
-    pub fn parse_complete<T>(&mut self) -> Result<T>
+    pub fn parse_complete<T>(&mut self) -> Result<T, ParseError>
     where
         T: Parse + std::fmt::Debug,
     {
@@ -117,7 +137,55 @@ impl<R: Read> StreamParser<R> {
                 self.buffer = self.buffer.split_off(consumed);
                 Ok(out)
             }
-            Err(e) => bail!("unexpected eof, `{}`", e),
+            // a line that was received in full and still failed, more input would not help
+            Err(ErrMode::Backtrack(e) | ErrMode::Cut(e)) if buffer.contains(&b'\n') => {
+                Err(ParseError::from_context(e, buffer.offset_from(&start)))
+            }
+            Err(_) => Err(ParseError::Incomplete {
+                buffered: self.buffer.len(),
+            }),
+        }
+    }
+
+    /// Whether the input starts with `prefix`, reading no more than needed to tell.
+    pub fn starts_with(&mut self, prefix: &[u8]) -> io::Result<bool> {
+        loop {
+            let compared = self.buffer.len().min(prefix.len());
+            if self.buffer[..compared] != prefix[..compared] {
+                return Ok(false);
+            }
+            if compared == prefix.len() {
+                return Ok(true);
+            }
+            if self.fill_buffer()? == 0 {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Whether input was read but not parsed yet, like a pipelined request.
+    pub fn is_buffered(&self) -> bool {
+        !self.buffer.is_empty() || !self.reader.buffer().is_empty()
+    }
+
+    /// The input read but not parsed yet, and the reader for the rest.
+    pub fn into_parts(mut self) -> (Vec<u8>, R) {
+        self.buffer.extend_from_slice(self.reader.buffer());
+        (self.buffer, self.reader.into_inner())
+    }
+
+    /// Reads more input into the buffer, returns the number of bytes added, 0 at the end.
+    pub(super) fn fill_buffer(&mut self) -> io::Result<usize> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.reader.read(&mut buffer) {
+                Ok(n) => {
+                    self.buffer.extend_from_slice(&buffer[..n]);
+                    break Ok(n);
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e),
+            }
         }
     }
 
@@ -0,0 +1,296 @@
+use std::{
+    io::{self, Read},
+    str,
+};
+
+use winnow::{
+    ascii::{crlf, hex_digit1},
+    combinator::terminated,
+    stream::{AsChar, FindSlice},
+    token::take_till,
+    Parser,
+};
+
+use crate::spec::request::BodyLength;
+
+use super::{
+    base::{Parse, StreamParser},
+    error::{Limit, ParseError},
+    limits::Limits,
+    message::FieldSection,
+};
+
+/// A message body, read from whatever the parser has buffered and then from
+/// the underlying reader.
+#[derive(Debug)]
+pub struct Body<'a, R: Read> {
+    parser: &'a mut StreamParser<R>,
+    framing: Framing,
+    chunked_total: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Framing {
+    Length(usize),
+    ChunkSize,
+    ChunkData(usize),
+    ChunkEnd,
+    /// Read so far of a body ending with the connection.
+    UntilClose(usize),
+    Done,
+}
+
+/// `chunk-size [ chunk-ext ] CRLF`, extensions are ignored.
+#[derive(Debug, PartialEq, Eq)]
+struct ChunkSize(usize);
+
+/// The CRLF closing a chunk's data.
+#[derive(Debug, PartialEq, Eq)]
+struct ChunkEnd;
+
+impl<R: Read> StreamParser<R> {
+    /// Streams the next message body, anything after it stays available
+    /// for the next `parse`.
+    pub fn body(&mut self, length: BodyLength) -> Body<'_, R> {
+        Body {
+            parser: self,
+            framing: match length {
+                BodyLength::Length(length) => Framing::Length(length),
+                BodyLength::Chunked => Framing::ChunkSize,
+                BodyLength::UntilClose => Framing::UntilClose(0),
+            },
+            chunked_total: 0,
+        }
+    }
+}
+
+impl<R: Read> Body<'_, R> {
+    /// Reads and drops whatever the handler did not consume, so the
+    /// connection is positioned at the start of the next request.
+    pub fn discard(&mut self) -> io::Result<()> {
+        io::copy(self, &mut io::sink()).map(|_| ())
+    }
+
+    fn read_raw(&mut self, buf: &mut [u8], max: usize) -> io::Result<usize> {
+        let max = buf.len().min(max);
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let n = match self.parser.buffer.is_empty() {
+            false => {
+                let n = max.min(self.parser.buffer.len());
+                buf[..n].copy_from_slice(&self.parser.buffer[..n]);
+                self.parser.buffer.drain(..n);
+                n
+            }
+            true => self.parser.reader.read(&mut buf[..max])?,
+        };
+        match n {
+            0 => Err(io::ErrorKind::UnexpectedEof.into()),
+            n => Ok(n),
+        }
+    }
+}
+
+impl<R: Read> Read for Body<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.framing {
+                Framing::Length(remaining) => {
+                    let n = self.read_raw(buf, remaining)?;
+                    self.framing = Framing::Length(remaining - n);
+                    return Ok(n);
+                }
+                Framing::ChunkSize => {
+                    let ChunkSize(size) = self.parser.parse()?;
+                    self.chunked_total = self.chunked_total.saturating_add(size);
+                    if self.chunked_total > self.parser.limits.body {
+                        return Err(ParseError::LimitExceeded(Limit::Body).into());
+                    }
+                    self.framing = match size {
+                        0 => {
+                            // trailer fields are not interpreted
+                            self.parser.parse::<FieldSection>()?;
+                            Framing::Done
+                        }
+                        size => Framing::ChunkData(size),
+                    };
+                }
+                Framing::ChunkData(remaining) => {
+                    let n = self.read_raw(buf, remaining)?;
+                    self.framing = match remaining - n {
+                        0 => Framing::ChunkEnd,
+                        remaining => Framing::ChunkData(remaining),
+                    };
+                    return Ok(n);
+                }
+                Framing::ChunkEnd => {
+                    self.parser.parse::<ChunkEnd>()?;
+                    self.framing = Framing::ChunkSize;
+                }
+                Framing::UntilClose(total) => {
+                    let n = match self.read_raw(buf, usize::MAX) {
+                        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => 0,
+                        read => read?,
+                    };
+                    if n == 0 {
+                        self.framing = Framing::Done;
+                        return Ok(0);
+                    }
+                    if total + n > self.parser.limits.body {
+                        return Err(ParseError::LimitExceeded(Limit::Body).into());
+                    }
+                    self.framing = Framing::UntilClose(total + n);
+                    return Ok(n);
+                }
+                Framing::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+impl Parse for ChunkSize {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        let size = terminated(
+            hex_digit1.try_map(|size: &[u8]| {
+                usize::from_str_radix(str::from_utf8(size).expect("hex digits are ascii"), 16)
+            }),
+            (
+                take_till(0.., |c: I::Token| "\r\n".contains(c.as_char())),
+                crlf,
+            ),
+        )
+        .parse_next(input)?;
+        Ok(ChunkSize(size))
+    }
+
+    fn check_limits(input: &[u8], limits: &Limits) -> Result<(), Limit> {
+        match input.find_slice("\r\n") {
+            None if input.len() > limits.header_bytes => Err(Limit::HeaderBytes),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Parse for ChunkEnd {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        crlf.parse_next(input)?;
+        Ok(ChunkEnd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test_parse_ok, Request};
+
+    test_parse_ok!(chunk_size, b"1a\r\n", ChunkSize(26), b"");
+    test_parse_ok!(
+        chunk_size_ext,
+        b"A;name=value\r\nabc",
+        ChunkSize(10),
+        b"abc"
+    );
+
+    #[test]
+    fn body_from_buffer_and_reader() {
+        let input: &[u8] = b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789GET";
+        let mut p = StreamParser::new(input);
+        let request: Request = p.parse().unwrap();
+        assert_eq!(request.body_length(), BodyLength::Length(10));
+
+        let mut body = vec![];
+        p.body(request.body_length())
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"0123456789");
+        assert_eq!(p.complete_buffer(), b"GET");
+    }
+
+    #[test]
+    fn body_discard() {
+        let input: &[u8] = b"0123456789GET";
+        let mut p = StreamParser::new(input);
+        let mut body = p.body(BodyLength::Length(10));
+        let mut start = [0; 4];
+        body.read_exact(&mut start).unwrap();
+        assert_eq!(&start, b"0123");
+        body.discard().unwrap();
+        assert_eq!(p.complete_buffer(), b"GET");
+    }
+
+    #[test]
+    fn body_unexpected_eof() {
+        let input: &[u8] = b"01234";
+        let mut p = StreamParser::new(input);
+        assert!(p.body(BodyLength::Length(10)).discard().is_err());
+
+        // a truncated body is not mistaken for a complete one
+        let mut p = StreamParser::new(input);
+        let mut body = vec![];
+        assert_eq!(
+            p.body(BodyLength::Length(10))
+                .read_to_end(&mut body)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn body_chunked() {
+        let input: &[u8] = b"4\r\nWiki\r\n7;ext\r\npedia i\r\n0\r\nExpires: never\r\n\r\nGET";
+        let mut p = StreamParser::new(input);
+        let mut body = vec![];
+        p.body(BodyLength::Chunked).read_to_end(&mut body).unwrap();
+        assert_eq!(body, b"Wikipedia i");
+        assert_eq!(p.complete_buffer(), b"GET");
+    }
+
+    #[test]
+    fn body_chunked_invalid() {
+        let input: &[u8] = b"4\r\nWikiX\r\n0\r\n\r\n";
+        let mut p = StreamParser::new(input);
+        assert!(p.body(BodyLength::Chunked).discard().is_err());
+    }
+
+    #[test]
+    fn body_chunked_over_limit() {
+        let input: &[u8] = b"4\r\nWiki\r\n4\r\npedi\r\n0\r\n\r\n";
+        let limits = Limits {
+            body: 6,
+            ..Limits::default()
+        };
+        let mut p = StreamParser::with_limits(input, limits);
+        assert!(p.body(BodyLength::Chunked).discard().is_err());
+    }
+
+    #[test]
+    fn body_until_close() {
+        let input: &[u8] = b"no length\r\n";
+        let mut p = StreamParser::new(input);
+        let mut body = vec![];
+        p.body(BodyLength::UntilClose)
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"no length\r\n");
+
+        let limits = Limits {
+            body: 4,
+            ..Limits::default()
+        };
+        let mut p = StreamParser::with_limits(input, limits);
+        assert!(p.body(BodyLength::UntilClose).discard().is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fmt, io};
+
+use winnow::error::{ContextError, StrContext};
+
+pub(super) const REQUEST_LINE: StrContext = StrContext::Label("request line");
+pub(super) const STATUS_LINE: StrContext = StrContext::Label("status line");
+pub(super) const HEADER: StrContext = StrContext::Label("header");
+pub(super) const FRAMING: StrContext = StrContext::Label("framing");
+pub(super) const BODY_TOO_LARGE: StrContext = StrContext::Label("body too large");
+
+/// The part of the message being parsed when a syntax error occurred.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Context {
+    RequestLine,
+    StatusLine,
+    Header,
+    /// Conflicting or invalid `Content-Length` and `Transfer-Encoding`.
+    Framing,
+}
+
+/// A configured size limit that a message went over.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    RequestLine,
+    HeaderBytes,
+    HeaderCount,
+    Body,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The stream ended before a complete message was read.
+    #[error("unexpected eof with {buffered} bytes buffered")]
+    Incomplete { buffered: usize },
+    /// The input is not valid, `offset` is relative to the start of the message.
+    #[error("syntax error at byte {offset}{}", context.map(|c| format!(" in {c}")).unwrap_or_default())]
+    Syntax {
+        offset: usize,
+        context: Option<Context>,
+    },
+    #[error("{0} limit exceeded")]
+    LimitExceeded(Limit),
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Context::RequestLine => write!(f, "request line"),
+            Context::StatusLine => write!(f, "status line"),
+            Context::Header => write!(f, "header"),
+            Context::Framing => write!(f, "message framing"),
+        }
+    }
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::RequestLine => write!(f, "request line length"),
+            Limit::HeaderBytes => write!(f, "header size"),
+            Limit::HeaderCount => write!(f, "header count"),
+            Limit::Body => write!(f, "body size"),
+        }
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> Self {
+        match error {
+            ParseError::Io(e) => e,
+            ParseError::Incomplete { .. } => io::ErrorKind::UnexpectedEof.into(),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+impl ParseError {
+    /// The limit that a read went over, when `error` is a `LimitExceeded` turned into
+    /// an `io::Error`, like the ones of a streamed [`Body`](super::Body).
+    pub fn exceeded_limit(error: &io::Error) -> Option<Limit> {
+        match error.get_ref()?.downcast_ref::<ParseError>()? {
+            ParseError::LimitExceeded(limit) => Some(*limit),
+            _ => None,
+        }
+    }
+
+    pub(super) fn from_context(error: ContextError, offset: usize) -> ParseError {
+        // contexts are pushed while unwinding, the outermost one is the last
+        match error.context().last() {
+            Some(context) if context == &BODY_TOO_LARGE => ParseError::LimitExceeded(Limit::Body),
+            Some(context) if context == &FRAMING => ParseError::Syntax {
+                offset,
+                context: Some(Context::Framing),
+            },
+            Some(context) if context == &HEADER => ParseError::Syntax {
+                offset,
+                context: Some(Context::Header),
+            },
+            Some(context) if context == &REQUEST_LINE => ParseError::Syntax {
+                offset,
+                context: Some(Context::RequestLine),
+            },
+            Some(context) if context == &STATUS_LINE => ParseError::Syntax {
+                offset,
+                context: Some(Context::StatusLine),
+            },
+            _ => ParseError::Syntax {
+                offset,
+                context: None,
+            },
+        }
+    }
+}
@@ -0,0 +1,26 @@
+/// Upper bounds on the size of a request, checked while it is being read.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Limits {
+    /// Maximum length of the request line, without the trailing CRLF.
+    pub request_line: usize,
+    /// Maximum size of all header lines combined.
+    pub header_bytes: usize,
+    /// Maximum number of header lines.
+    pub header_count: usize,
+    /// Maximum `Content-Length` of a body.
+    pub body: usize,
+    /// Maximum size of a body read as JSON, which is held in memory to be deserialized.
+    pub json: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            request_line: 8 * 1024,
+            header_bytes: 8 * 1024,
+            header_count: 100,
+            body: 16 * 1024 * 1024,
+            json: 1024 * 1024,
+        }
+    }
+}
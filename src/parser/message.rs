@@ -1,18 +1,27 @@
+use std::str;
+
 use winnow::{
-    ascii::{space0, space1},
-    combinator::{alt, fail, peek, preceded, separated, seq},
-    error::ContextError,
-    stream::AsChar,
-    token::{rest, take_till, take_until, take_while},
+    ascii::crlf,
+    combinator::{fail, opt, peek, repeat, seq, terminated},
+    error::{ContextError, StrContext},
+    stream::{AsChar, FindSlice},
+    token::{take_till, take_while},
     Parser,
 };
 
 use crate::{
-    parser::util::is_space,
-    spec::message::{FieldContent, FieldName, FieldValue, MessageBody, MessageHeader},
+    parser::util::{is_tchar, Lws},
+    spec::{
+        header::{self, HeaderMap},
+        message::{FieldName, FieldValue, MessageBody, MessageHeader},
+    },
 };
 
-use super::base::Parse;
+use super::{
+    base::Parse,
+    error::{Limit, BODY_TOO_LARGE, FRAMING, HEADER},
+    limits::Limits,
+};
 
 impl Parse for FieldName {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
@@ -21,31 +30,16 @@ impl Parse for FieldName {
         I: super::base::Convertible<'i>,
         I::Token: winnow::stream::AsChar,
     {
-        let field_name = preceded(
-            space0,
-            take_until(.., ':')
-                .map(|field_name: &[u8]| FieldName(field_name.trim_ascii_end().to_vec())),
-        )
-        .parse_next(input)?;
+        // whitespace between the name and the colon is rejected, RFC 9112 section 5.1,
+        // and so is whitespace before the name, where it would hide the field
+        let field_name = take_while(1.., is_tchar)
+            .map(|field_name: &[u8]| FieldName(field_name.to_vec()))
+            .parse_next(input)?;
 
         Ok(field_name)
     }
 }
 
-impl Parse for FieldContent {
-    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
-    where
-        Self: std::marker::Sized,
-        I: super::base::Convertible<'i>,
-        I::Token: winnow::stream::AsChar,
-    {
-        let field_content = alt((take_till(0.., is_space), rest))
-            .map(|field_content: &[u8]| FieldContent(field_content.to_vec()))
-            .parse_next(input)?;
-        Ok(field_content)
-    }
-}
-
 impl Parse for FieldValue {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
     where
@@ -53,15 +47,25 @@ impl Parse for FieldValue {
         I: super::base::Convertible<'i>,
         I::Token: AsChar,
     {
-        let field_contents = separated(0.., FieldContent::parse, space1);
-        let field_value = preceded(space0, field_contents)
-            .map(|contents: Vec<FieldContent>| {
-                contents.into_iter().filter(|c| !c.0.is_empty()).collect()
-            })
-            .map(FieldValue)
-            .parse_next(input)?;
+        let mut field_value = vec![];
+        loop {
+            let field_content =
+                take_till(0.., |c: I::Token| "\r\n".contains(c.as_char())).parse_next(input)?;
+            field_value.extend_from_slice(field_content);
+
+            // obs-fold, a line break followed by whitespace, is replaced with a single space
+            let mut obs_fold = opt(peek((
+                "\r\n",
+                take_while(1, |c: I::Token| " \t".contains(c.as_char())),
+            )));
+            if obs_fold.parse_next(input)?.is_none() {
+                break;
+            }
+            Lws::parse(input)?;
+            field_value.push(b' ');
+        }
 
-        Ok(field_value)
+        Ok(FieldValue(field_value.trim_ascii().to_vec()))
     }
 }
 
@@ -72,7 +76,6 @@ impl Parse for MessageHeader {
         I: super::base::Convertible<'i>,
         I::Token: AsChar,
     {
-        let _ = space0(input)?;
         if peek::<I, &'i [u8], ContextError, &str>("\r\n")
             .parse_next(input)
             .is_ok()
@@ -99,6 +102,96 @@ impl Parse for MessageHeader {
     }
 }
 
+/// Header fields up to the empty line closing them, like trailers or the head of a multipart part.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct FieldSection(pub HeaderMap);
+
+impl Parse for FieldSection {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: AsChar,
+    {
+        let headers = terminated(
+            repeat(0.., terminated(MessageHeader::parse, crlf)),
+            crlf.context(HEADER),
+        )
+        .map(|headers: Vec<MessageHeader>| FieldSection(headers.into()))
+        .parse_next(input)?;
+        Ok(headers)
+    }
+
+    fn check_limits(input: &[u8], limits: &Limits) -> Result<(), Limit> {
+        match input.find_slice("\r\n\r\n") {
+            None if input.len() > limits.header_bytes => Err(Limit::HeaderBytes),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks the start line and header lines of a message received so far against
+/// `limits`, returns the length of the head once it is complete.
+pub(super) fn check_head_limits(input: &[u8], limits: &Limits) -> Result<Option<usize>, Limit> {
+    let Some(start_line) = input.find_slice("\r\n") else {
+        return match input.len() > limits.request_line {
+            true => Err(Limit::RequestLine),
+            false => Ok(None),
+        };
+    };
+    if start_line.start > limits.request_line {
+        return Err(Limit::RequestLine);
+    }
+
+    // count header fields until the empty line closing the head
+    let headers = &input[start_line.end..];
+    let mut header_bytes = 0;
+    let mut header_count = 0;
+    loop {
+        let Some(line) = (&headers[header_bytes..]).find_slice("\r\n") else {
+            return match headers.len() > limits.header_bytes {
+                true => Err(Limit::HeaderBytes),
+                false => Ok(None),
+            };
+        };
+        if line.start == 0 {
+            return Ok(Some(start_line.end + header_bytes + 2));
+        }
+        // obs-fold continuation lines belong to the field above
+        if !matches!(headers[header_bytes], b' ' | b'\t') {
+            header_count += 1;
+        }
+        header_bytes += line.end;
+        if header_bytes > limits.header_bytes {
+            return Err(Limit::HeaderBytes);
+        }
+        if header_count > limits.header_count {
+            return Err(Limit::HeaderCount);
+        }
+    }
+}
+
+/// Rejects `Content-Length` values that are not numbers or disagree with each other.
+pub(super) fn check_content_length(headers: &HeaderMap) -> Result<(), StrContext> {
+    let mut content_length = None;
+    for value in headers.get_all(header::CONTENT_LENGTH) {
+        for element in value.split(|&c| c == b',').map(<[u8]>::trim_ascii) {
+            if element.is_empty() || !element.iter().all(u8::is_ascii_digit) {
+                return Err(FRAMING);
+            }
+            let length = str::from_utf8(element)
+                .expect("digits are ascii")
+                .parse::<usize>()
+                .map_err(|_| BODY_TOO_LARGE)?;
+            match content_length {
+                Some(content_length) if content_length != length => return Err(FRAMING),
+                _ => content_length = Some(length),
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Parse for MessageBody {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
     where
@@ -125,63 +218,62 @@ mod test {
         FieldName(b"Content-Type".to_vec()),
         b": 3\r\n"
     );
-    test_parse_ok!(
+    test_parse_error!(
         field_name_leading,
+        FieldName,
         b" Content-Type: 3\r\n",
-        FieldName(b"Content-Type".to_vec()),
-        b": 3\r\n"
+        b" Content-Type: 3\r\n"
     );
     test_parse_ok!(
         field_name_trailing,
         b"Content-Type \t: 3\r\n",
         FieldName(b"Content-Type".to_vec()),
-        b": 3\r\n"
-    );
-
-    test_parse_ok!(field_content, b"ab", FieldContent(b"ab".to_vec()), b"");
-    test_parse_ok!(
-        field_content_trailing,
-        b"ab ",
-        FieldContent(b"ab".to_vec()),
-        b" "
+        b" \t: 3\r\n"
     );
+    test_parse_error!(field_name_empty, FieldName, b": 3\r\n", b": 3\r\n");
 
     test_parse_ok!(
         field_value,
         b"ab  \tcd",
-        FieldValue(vec![
-            FieldContent(b"ab".to_vec()),
-            FieldContent(b"cd".to_vec())
-        ]),
+        FieldValue(b"ab  \tcd".to_vec()),
         b""
     );
     test_parse_ok!(
         field_value_crlf,
         b"ab  \tcd\r\n",
-        FieldValue(vec![
-            FieldContent(b"ab".to_vec()),
-            FieldContent(b"cd".to_vec())
-        ]),
+        FieldValue(b"ab  \tcd".to_vec()),
         b"\r\n"
     );
     test_parse_ok!(
         field_value_leading,
         b" \tab  \tcd",
-        FieldValue(vec![
-            FieldContent(b"ab".to_vec()),
-            FieldContent(b"cd".to_vec())
-        ]),
+        FieldValue(b"ab  \tcd".to_vec()),
         b""
     );
     test_parse_ok!(
         field_value_trailing,
         b" \tab  \tcd   \r\n",
-        FieldValue(vec![
-            FieldContent(b"ab".to_vec()),
-            FieldContent(b"cd".to_vec())
-        ]),
+        FieldValue(b"ab  \tcd".to_vec()),
         b"\r\n"
     );
+    test_parse_ok!(
+        field_value_quoted,
+        b"\"a  b\"; c=\"d\te\"\r\n",
+        FieldValue(b"\"a  b\"; c=\"d\te\"".to_vec()),
+        b"\r\n"
+    );
+    test_parse_ok!(
+        field_value_obs_fold,
+        b"ab\r\n \tcd\r\n\r\n",
+        FieldValue(b"ab cd".to_vec()),
+        b"\r\n\r\n"
+    );
+    test_parse_ok!(
+        field_value_bare_cr,
+        b"ab\rcd\r\n",
+        FieldValue(b"ab".to_vec()),
+        b"\rcd\r\n"
+    );
     test_parse_ok!(field_value_empty, b"", FieldValue(vec![]), b"");
     test_parse_ok!(field_value_only_space, b"   \t", FieldValue(vec![]), b"");
     test_parse_ok!(field_value_only_crlf, b" \r\n", FieldValue(vec![]), b"\r\n");
@@ -191,26 +283,24 @@ mod test {
         b"Content-Length: 3 4 5\r\n",
         MessageHeader {
             field_name: FieldName(b"Content-Length".to_vec()),
-            field_value: Some(FieldValue(vec![
-                FieldContent(b"3".to_vec()),
-                FieldContent(b"4".to_vec()),
-                FieldContent(b"5".to_vec()),
-            ]))
+            field_value: Some(FieldValue(b"3 4 5".to_vec()))
         },
         b"\r\n"
     );
-    test_parse_ok!(
+    test_parse_error!(
         message_header_leading,
+        MessageHeader,
         b"   Content-Length: 3 4 5\r\n",
+        b"   Content-Length: 3 4 5\r\n"
+    );
+    test_parse_ok!(
+        message_header_obs_fold,
+        b"X-Folded: a\r\n\tb\r\n\r\n",
         MessageHeader {
-            field_name: FieldName(b"Content-Length".to_vec()),
-            field_value: Some(FieldValue(vec![
-                FieldContent(b"3".to_vec()),
-                FieldContent(b"4".to_vec()),
-                FieldContent(b"5".to_vec()),
-            ]))
+            field_name: FieldName(b"X-Folded".to_vec()),
+            field_value: Some(FieldValue(b"a b".to_vec()))
         },
-        b"\r\n"
+        b"\r\n\r\n"
     );
     test_parse_ok!(
         message_header_empty_value,
@@ -222,6 +312,37 @@ mod test {
         b"\r\n"
     );
     test_parse_error!(message_header_empty_header, MessageHeader, b"\r\n", b"\r\n");
+    test_parse_error!(
+        message_header_space_before_colon,
+        MessageHeader,
+        b"Content-Length : 3\r\n",
+        b"Content-Length : 3\r\n"
+    );
+
+    test_parse_ok!(
+        field_section,
+        b"Expires: never\r\nX-A: 1\r\n\r\nGET",
+        FieldSection(
+            vec![
+                MessageHeader {
+                    field_name: FieldName(b"Expires".to_vec()),
+                    field_value: Some(FieldValue(b"never".to_vec())),
+                },
+                MessageHeader {
+                    field_name: FieldName(b"X-A".to_vec()),
+                    field_value: Some(FieldValue(b"1".to_vec())),
+                },
+            ]
+            .into()
+        ),
+        b"GET"
+    );
+    test_parse_ok!(
+        field_section_empty,
+        b"\r\nGET",
+        FieldSection(HeaderMap::new()),
+        b"GET"
+    );
 
     test_parse_ok!(
         body,
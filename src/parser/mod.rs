@@ -1,7 +1,18 @@
 mod base;
+mod body;
+mod error;
+mod limits;
 mod message;
+mod multipart;
 mod protocol;
 pub mod request;
+pub mod response;
+mod uri;
 mod util;
 
 pub use base::{Parse, StreamParser};
+pub use body::Body;
+pub use error::{Context, Limit, ParseError};
+pub use limits::Limits;
+pub use multipart::{form_data_boundary, Multipart, Part};
+pub(crate) use util::is_tchar;
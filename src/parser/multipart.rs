@@ -0,0 +1,300 @@
+use std::io::{self, Read};
+
+use winnow::{
+    ascii::{crlf, space0},
+    combinator::alt,
+    stream::FindSlice,
+    Parser,
+};
+
+use crate::spec::{
+    header::{self, HeaderMap},
+    message::parameters,
+};
+
+use super::{
+    base::{Parse, StreamParser},
+    error::ParseError,
+    limits::Limits,
+    message::FieldSection,
+};
+
+/// A `multipart/form-data` body (RFC 7578), parts are streamed one after the other.
+#[derive(Debug)]
+pub struct Multipart<R: Read> {
+    parser: StreamParser<R>,
+    /// `CRLF--boundary`, what ends the data of a part.
+    delimiter: Vec<u8>,
+    state: State,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    /// In the preamble or the data of a part.
+    Data,
+    /// Right after a delimiter.
+    Delimiter,
+    Done,
+}
+
+/// What follows a delimiter: `--` after the last part, otherwise the line ends.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum DelimiterEnd {
+    Part,
+    Close,
+}
+
+/// A part of a multipart body, reading it yields the part's data.
+#[derive(Debug)]
+pub struct Part<'a, R: Read> {
+    multipart: &'a mut Multipart<R>,
+    headers: HeaderMap,
+}
+
+impl<R: Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &[u8], limits: Limits) -> Multipart<R> {
+        let mut parser = StreamParser::with_limits(reader, limits);
+        // the first delimiter has no line break before it, adding one lets every
+        // delimiter be found the same way
+        parser.buffer.extend_from_slice(b"\r\n");
+        Multipart {
+            parser,
+            delimiter: [&b"\r\n--"[..], boundary].concat(),
+            state: State::Data,
+        }
+    }
+
+    /// The next part, what is left of the previous one is skipped.
+    pub fn next_part(&mut self) -> Result<Option<Part<'_, R>>, ParseError> {
+        if self.state == State::Data {
+            // the preamble or the unread data of the previous part
+            io::copy(&mut ReadData(self), &mut io::sink())?;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        match self.parser.parse::<DelimiterEnd>()? {
+            DelimiterEnd::Close => {
+                // the epilogue is ignored
+                self.state = State::Done;
+                Ok(None)
+            }
+            DelimiterEnd::Part => {
+                let FieldSection(headers) = self.parser.parse()?;
+                self.state = State::Data;
+                Ok(Some(Part {
+                    multipart: self,
+                    headers,
+                }))
+            }
+        }
+    }
+
+    fn read_data(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.state != State::Data || buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let buffer = self.parser.buffer.as_slice();
+            let available = match buffer.find_slice(self.delimiter.as_slice()) {
+                Some(delimiter) if delimiter.start == 0 => {
+                    self.parser.buffer.drain(..delimiter.end);
+                    self.state = State::Delimiter;
+                    return Ok(0);
+                }
+                Some(delimiter) => delimiter.start,
+                // the end of the buffer could be the start of a delimiter
+                None => buffer.len().saturating_sub(self.delimiter.len() - 1),
+            };
+            if available > 0 {
+                let n = available.min(buf.len());
+                buf[..n].copy_from_slice(&buffer[..n]);
+                self.parser.buffer.drain(..n);
+                return Ok(n);
+            }
+            if self.parser.fill_buffer()? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+/// The boundary of a `multipart/form-data` content type.
+pub fn form_data_boundary(content_type: &[u8]) -> Option<Vec<u8>> {
+    let (media_type, parameters) = parameters(content_type);
+    if !media_type.eq_ignore_ascii_case(b"multipart/form-data") {
+        return None;
+    }
+    parameters
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(b"boundary"))
+        .map(|(_, boundary)| boundary)
+        .filter(|boundary| !boundary.is_empty())
+}
+
+struct ReadData<'a, R: Read>(&'a mut Multipart<R>);
+
+impl<R: Read> Read for ReadData<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read_data(buf)
+    }
+}
+
+impl<R: Read> Part<'_, R> {
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The form field name from `Content-Disposition`.
+    pub fn name(&self) -> Option<Vec<u8>> {
+        self.disposition_parameter(b"name")
+    }
+
+    /// The file name from `Content-Disposition`, only set for file uploads.
+    pub fn filename(&self) -> Option<Vec<u8>> {
+        self.disposition_parameter(b"filename")
+    }
+
+    fn disposition_parameter(&self, name: &[u8]) -> Option<Vec<u8>> {
+        let disposition = self.headers.get(header::CONTENT_DISPOSITION)?;
+        let (_, parameters) = parameters(disposition);
+        parameters
+            .into_iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+impl<R: Read> Read for Part<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.multipart.read_data(buf)
+    }
+}
+
+impl Parse for DelimiterEnd {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        // transport padding may follow the boundary
+        let end = alt((
+            "--".value(DelimiterEnd::Close),
+            (space0, crlf).value(DelimiterEnd::Part),
+        ))
+        .parse_next(input)?;
+        Ok(end)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_parse_ok;
+
+    test_parse_ok!(delimiter_close, b"--\r\n", DelimiterEnd::Close, b"\r\n");
+    test_parse_ok!(delimiter_part, b" \t\r\nA", DelimiterEnd::Part, b"A");
+
+    const BODY: &[u8] = b"preamble\r
+--abc\r
+Content-Disposition: form-data; name=\"text\"\r
+\r
+hello\r
+--abc\r
+Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r
+Content-Type: text/plain\r
+\r
+line 1\r
+--abd\r
+\r
+--abc--\r
+epilogue";
+
+    /// Name, file name and data of a part.
+    type Field = (Option<Vec<u8>>, Option<Vec<u8>>, Vec<u8>);
+
+    fn parts(body: &[u8]) -> Result<Vec<Field>, ParseError> {
+        let mut multipart = Multipart::new(body, b"abc", Limits::default());
+        let mut parts = vec![];
+        while let Some(mut part) = multipart.next_part()? {
+            let mut data = vec![];
+            part.read_to_end(&mut data)?;
+            parts.push((part.name(), part.filename(), data));
+        }
+        Ok(parts)
+    }
+
+    #[test]
+    fn multipart() {
+        assert_eq!(
+            parts(BODY).unwrap(),
+            vec![
+                (Some(b"text".to_vec()), None, b"hello".to_vec()),
+                (
+                    Some(b"file".to_vec()),
+                    Some(b"a.txt".to_vec()),
+                    b"line 1\r\n--abd\r\n".to_vec()
+                ),
+            ]
+        );
+    }
+
+    /// Hands out the input one byte at a time.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&c, rest)), Some(b)) => {
+                    *b = c;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn multipart_split_delimiter() {
+        // delimiters arriving over several reads are still found
+        let mut multipart = Multipart::new(Trickle(BODY), b"abc", Limits::default());
+        multipart.next_part().unwrap();
+        let mut part = multipart.next_part().unwrap().unwrap();
+        let mut data = vec![];
+        part.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"line 1\r\n--abd\r\n");
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn multipart_unread_parts_skipped() {
+        let mut multipart = Multipart::new(BODY, b"abc", Limits::default());
+        assert!(multipart.next_part().unwrap().is_some());
+        let part = multipart.next_part().unwrap().unwrap();
+        assert_eq!(
+            part.headers().get(header::CONTENT_TYPE),
+            Some(&b"text/plain"[..])
+        );
+        assert!(multipart.next_part().unwrap().is_none());
+        assert!(multipart.next_part().unwrap().is_none());
+    }
+
+    #[test]
+    fn multipart_unexpected_eof() {
+        assert!(parts(b"--abc\r\n\r\nno closing delimiter").is_err());
+    }
+
+    #[test]
+    fn boundary() {
+        assert_eq!(
+            form_data_boundary(b"multipart/form-data; boundary=\"a b\""),
+            Some(b"a b".to_vec())
+        );
+        assert_eq!(form_data_boundary(b"text/plain; boundary=abc"), None);
+        assert_eq!(form_data_boundary(b"multipart/form-data"), None);
+    }
+}
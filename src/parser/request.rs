@@ -1,19 +1,24 @@
-use std::str;
-
 use winnow::{
     ascii::{alpha1, crlf, space0, Caseless},
-    combinator::{alt, empty, repeat, seq, terminated},
-    token::{take, take_till},
+    combinator::{alt, empty, fail, repeat, seq, terminated},
+    error::StrContext,
     Parser,
 };
 
 use crate::spec::{
-    message::{MessageBody, MessageHeader},
+    header::{self, HeaderMap},
+    message::MessageHeader,
     protocol::HttpVersion,
-    request::{Method, Request, RequestLine, RequestURI},
+    request::{Method, Request, RequestLine},
+    uri::RequestURI,
 };
 
-use super::{base::Parse, util::is_space};
+use super::{
+    base::Parse,
+    error::{Limit, FRAMING, HEADER, REQUEST_LINE},
+    limits::Limits,
+    message::{check_content_length, check_head_limits},
+};
 
 impl Parse for Method {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
@@ -32,20 +37,6 @@ impl Parse for Method {
     }
 }
 
-impl Parse for RequestURI {
-    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
-    where
-        Self: std::marker::Sized,
-        I: super::base::Convertible<'i>,
-        I::Token: winnow::stream::AsChar,
-    {
-        let uri = take_till(1.., is_space)
-            .map(|uri: &[u8]| RequestURI(uri.to_vec()))
-            .parse_next(input)?;
-        Ok(uri)
-    }
-}
-
 impl Parse for RequestLine {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
     where
@@ -76,37 +67,76 @@ impl Parse for Request {
         I: super::base::Convertible<'i>,
         I::Token: winnow::stream::AsChar,
     {
-        let mut request: Request = seq! {
+        // only the head is parsed, the body is streamed by the caller
+        let request = seq! {
             Request {
-                request_line: RequestLine::parse,
-                headers: repeat(0.., terminated(MessageHeader::parse, crlf)),
-                _: crlf,
-                body: empty.map(|_| None),
+                request_line: RequestLine::parse.context(REQUEST_LINE),
+                headers: repeat(0.., terminated(MessageHeader::parse, crlf))
+                    .map(|headers: Vec<MessageHeader>| HeaderMap::from(headers)),
+                _: crlf.context(HEADER),
+                // filled in by the connection
+                peer_addr: empty.value(None),
+                received_at: empty.value(None),
             }
         }
         .parse_next(input)?;
 
-        // whether we should read body
-        request.body = match request.find_value(b"Content-Length") {
-            Some(content_length) => {
-                if let Ok(Ok(value)) = str::from_utf8(&content_length).map(|s| s.parse::<u32>()) {
-                    let body = take(value).parse_next(input)?;
-                    Some(MessageBody(body.to_vec()))
-                } else {
-                    None
-                }
-            }
-            None => None,
-        };
+        if let Err(context) = check_framing(&request) {
+            fail.context(context).parse_next(input)?
+        }
 
         Ok(request)
     }
+
+    fn check_limits(input: &[u8], limits: &Limits) -> Result<(), Limit> {
+        // the head is complete, so the declared body size is known before the body is read
+        let Some(head) = check_head_limits(input, limits)? else {
+            return Ok(());
+        };
+        let Ok(request) = Request::parse(&mut &input[..head]) else {
+            return Ok(());
+        };
+        match request.content_length() {
+            Some(content_length) if content_length > limits.body => Err(Limit::Body),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Rejects requests whose body length is ambiguous, following RFC 9112 section 6.3,
+/// as a proxy in front of us could frame them differently.
+fn check_framing(request: &Request) -> Result<(), StrContext> {
+    if request.headers.contains_key(header::TRANSFER_ENCODING) {
+        if request.headers.contains_key(header::CONTENT_LENGTH) {
+            return Err(FRAMING);
+        }
+        if request.request_line.http_version < (HttpVersion { major: 1, minor: 1 }) {
+            return Err(FRAMING);
+        }
+        // chunked is the only transfer coding supported, it must be applied exactly once
+        let codings: Vec<&[u8]> = request
+            .headers
+            .headers(header::TRANSFER_ENCODING)
+            .filter_map(|header| header.field_value.as_ref())
+            .flat_map(|value| value.list())
+            .collect();
+        return match codings.as_slice() {
+            [coding] if coding.eq_ignore_ascii_case(b"chunked") => Ok(()),
+            _ => Err(FRAMING),
+        };
+    }
+
+    check_content_length(&request.headers)
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        spec::message::{FieldContent, FieldName, FieldValue},
+        parser::{Context, Limit, ParseError, StreamParser},
+        spec::{
+            message::{FieldName, FieldValue},
+            request::BodyLength,
+        },
         test_parse_ok,
     };
 
@@ -131,31 +161,15 @@ mod test {
         b" "
     );
 
-    test_parse_ok!(
-        request_uri,
-        b"http://localhost",
-        RequestURI(b"http://localhost".to_vec()),
-        b""
-    );
-    test_parse_ok!(
-        request_uri_trailing,
-        b"http://localhost ",
-        RequestURI(b"http://localhost".to_vec()),
-        b" "
-    );
-    test_parse_ok!(
-        request_uri_crlf,
-        b"http://localhost\r\n",
-        RequestURI(b"http://localhost".to_vec()),
-        b"\r\n"
-    );
-
     test_parse_ok!(
         request_line,
         b"GET /user-agent HTTP/1.1\r\n",
         RequestLine {
             method: Method::Get,
-            request_uri: RequestURI(b"/user-agent".to_vec()),
+            request_uri: RequestURI::Origin {
+                path: b"/user-agent".to_vec(),
+                query: None,
+            },
             http_version: HttpVersion { major: 1, minor: 1 }
         },
         b""
@@ -165,7 +179,10 @@ mod test {
         b"  \tGET /user-agent HTTP/1.1\r\n",
         RequestLine {
             method: Method::Get,
-            request_uri: RequestURI(b"/user-agent".to_vec()),
+            request_uri: RequestURI::Origin {
+                path: b"/user-agent".to_vec(),
+                query: None,
+            },
             http_version: HttpVersion { major: 1, minor: 1 }
         },
         b""
@@ -175,7 +192,10 @@ mod test {
         b"GET /user-agent HTTP/1.1  \t\r\n",
         RequestLine {
             method: Method::Get,
-            request_uri: RequestURI(b"/user-agent".to_vec()),
+            request_uri: RequestURI::Origin {
+                path: b"/user-agent".to_vec(),
+                query: None,
+            },
             http_version: HttpVersion { major: 1, minor: 1 }
         },
         b""
@@ -192,24 +212,29 @@ Accept: */*\r
         Request {
             request_line: RequestLine {
                 method: Method::Get,
-                request_uri: RequestURI(b"/user-agent".to_vec()),
+                request_uri: RequestURI::Origin {
+                    path: b"/user-agent".to_vec(),
+                    query: None,
+                },
                 http_version: HttpVersion { major: 2, minor: 0 },
             },
             headers: vec![
                 MessageHeader {
                     field_name: FieldName(b"Host".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"localhost:4221".to_vec())])),
+                    field_value: Some(FieldValue(b"localhost:4221".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"User-Agent".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"foobar/1.2.3".to_vec())])),
+                    field_value: Some(FieldValue(b"foobar/1.2.3".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"Accept".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"*/*".to_vec())])),
+                    field_value: Some(FieldValue(b"*/*".to_vec())),
                 },
-            ],
-            body: None,
+            ]
+            .into(),
+            peer_addr: None,
+            received_at: None,
         },
         b""
     );
@@ -221,11 +246,15 @@ Accept: */*\r
         Request {
             request_line: RequestLine {
                 method: Method::Get,
-                request_uri: RequestURI(b"/user-agent".to_vec()),
+                request_uri: RequestURI::Origin {
+                    path: b"/user-agent".to_vec(),
+                    query: None,
+                },
                 http_version: HttpVersion { major: 2, minor: 0 },
             },
-            headers: vec![],
-            body: None,
+            headers: HeaderMap::new(),
+            peer_addr: None,
+            received_at: None,
         },
         b""
     );
@@ -241,30 +270,35 @@ Content-Length: 10\r
         Request {
             request_line: RequestLine {
                 method: Method::Get,
-                request_uri: RequestURI(b"/user-agent".to_vec()),
+                request_uri: RequestURI::Origin {
+                    path: b"/user-agent".to_vec(),
+                    query: None,
+                },
                 http_version: HttpVersion { major: 2, minor: 0 },
             },
             headers: vec![
                 MessageHeader {
                     field_name: FieldName(b"Host".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"localhost:4221".to_vec())])),
+                    field_value: Some(FieldValue(b"localhost:4221".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"User-Agent".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"foobar/1.2.3".to_vec())])),
+                    field_value: Some(FieldValue(b"foobar/1.2.3".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"Accept".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"*/*".to_vec())])),
+                    field_value: Some(FieldValue(b"*/*".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"Content-Length".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"10".to_vec())])),
+                    field_value: Some(FieldValue(b"10".to_vec())),
                 },
-            ],
-            body: Some(MessageBody(b"0123456789".into())),
+            ]
+            .into(),
+            peer_addr: None,
+            received_at: None,
         },
-        b""
+        b"0123456789"
     );
     test_parse_ok!(
         request_no_header_and_body,
@@ -274,11 +308,15 @@ Content-Length: 10\r
         Request {
             request_line: RequestLine {
                 method: Method::Get,
-                request_uri: RequestURI(b"/user-agent".to_vec()),
+                request_uri: RequestURI::Origin {
+                    path: b"/user-agent".to_vec(),
+                    query: None,
+                },
                 http_version: HttpVersion { major: 2, minor: 0 },
             },
-            headers: vec![],
-            body: None,
+            headers: HeaderMap::new(),
+            peer_addr: None,
+            received_at: None,
         },
         b""
     );
@@ -293,25 +331,278 @@ Accept: */*\r
         Request {
             request_line: RequestLine {
                 method: Method::Get,
-                request_uri: RequestURI(b"/user-agent".to_vec()),
+                request_uri: RequestURI::Origin {
+                    path: b"/user-agent".to_vec(),
+                    query: None,
+                },
                 http_version: HttpVersion { major: 2, minor: 0 },
             },
             headers: vec![
                 MessageHeader {
                     field_name: FieldName(b"Host".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"localhost:4221".to_vec())])),
+                    field_value: Some(FieldValue(b"localhost:4221".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"User-Agent".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"foobar/1.2.3".to_vec())])),
+                    field_value: Some(FieldValue(b"foobar/1.2.3".to_vec())),
                 },
                 MessageHeader {
                     field_name: FieldName(b"Accept".to_vec()),
-                    field_value: Some(FieldValue(vec![FieldContent(b"*/*".to_vec())])),
+                    field_value: Some(FieldValue(b"*/*".to_vec())),
                 },
-            ],
-            body: None,
+            ]
+            .into(),
+            peer_addr: None,
+            received_at: None,
         },
         b"0123456789"
     );
+
+    #[test]
+    fn request_malformed_request_line() {
+        let mut p = StreamParser::new(&b"GET /user-agent garbage\r\n\r\n"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::Syntax {
+                offset: 16,
+                context: Some(Context::RequestLine)
+            })
+        ));
+    }
+
+    #[test]
+    fn request_bad_header() {
+        let mut p = StreamParser::new(&b"GET / HTTP/1.1\r\ngarbage\r\nHost: a\r\n\r\n"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::Syntax {
+                offset: 16,
+                context: Some(Context::Header)
+            })
+        ));
+    }
+
+    #[test]
+    fn request_body_too_large() {
+        let mut p =
+            StreamParser::new(&b"POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::LimitExceeded(Limit::Body))
+        ));
+    }
+
+    #[test]
+    fn request_unexpected_eof() {
+        let mut p = StreamParser::new(&b"POST / HTTP/1.1\r\nContent-Length: 10\r\n"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::Incomplete { buffered: 37 })
+        ));
+    }
+
+    #[test]
+    fn request_complete_syntax_error() {
+        let parse_complete = |input: &[u8]| {
+            let mut p = StreamParser::new(std::io::empty());
+            p.buffer = input.to_vec();
+            p.parse_complete::<Request>()
+        };
+        assert!(matches!(
+            parse_complete(b"GET / HTTP/1.1\r\nHost x\r\n"),
+            Err(ParseError::Syntax {
+                offset: 16,
+                context: Some(Context::Header)
+            })
+        ));
+        assert!(matches!(
+            parse_complete(b"GET / HTTP/1.1\r\nHost"),
+            Err(ParseError::Incomplete { buffered: 20 })
+        ));
+    }
+
+    fn parse_with_limits(input: &[u8], limits: Limits) -> Result<Request, ParseError> {
+        StreamParser::with_limits(input, limits).parse::<Request>()
+    }
+
+    #[test]
+    fn request_line_too_long() {
+        let limits = Limits {
+            request_line: 16,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            parse_with_limits(b"GET /user-agent HTTP/1.1\r\n\r\n", limits),
+            Err(ParseError::LimitExceeded(Limit::RequestLine))
+        ));
+        // rejected before the end of the line arrives
+        assert!(matches!(
+            parse_with_limits(b"GET /aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", limits),
+            Err(ParseError::LimitExceeded(Limit::RequestLine))
+        ));
+    }
+
+    #[test]
+    fn request_header_bytes_too_large() {
+        let limits = Limits {
+            header_bytes: 20,
+            ..Limits::default()
+        };
+        assert!(parse_with_limits(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n", limits).is_ok());
+        assert!(matches!(
+            parse_with_limits(b"GET / HTTP/1.1\r\nHost: a\r\nAccept: */*\r\n\r\n", limits),
+            Err(ParseError::LimitExceeded(Limit::HeaderBytes))
+        ));
+        assert!(matches!(
+            parse_with_limits(
+                b"GET / HTTP/1.1\r\nUser-Agent: aaaaaaaaaaaaaaaaaaaa",
+                limits
+            ),
+            Err(ParseError::LimitExceeded(Limit::HeaderBytes))
+        ));
+    }
+
+    #[test]
+    fn request_header_count_too_large() {
+        let limits = Limits {
+            header_count: 1,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            parse_with_limits(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n", limits),
+            Err(ParseError::LimitExceeded(Limit::HeaderCount))
+        ));
+        // a folded field counts once
+        assert!(parse_with_limits(b"GET / HTTP/1.1\r\nA: 1\r\n 2\r\n\r\n", limits).is_ok());
+    }
+
+    #[test]
+    fn request_body_over_limit() {
+        let limits = Limits {
+            body: 4,
+            ..Limits::default()
+        };
+        assert!(
+            parse_with_limits(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\n0123", limits).is_ok()
+        );
+        // rejected without waiting for the body
+        assert!(matches!(
+            parse_with_limits(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n", limits),
+            Err(ParseError::LimitExceeded(Limit::Body))
+        ));
+    }
+
+    #[test]
+    fn request_lowercase_headers() {
+        let mut p = StreamParser::new(
+            &b"POST / HTTP/1.1\r\ncontent-length: 10\r\naccept-encoding: gzip\r\n\r\n"[..],
+        );
+        let request = p.parse::<Request>().unwrap();
+        assert_eq!(request.content_length(), Some(10));
+        assert_eq!(
+            request.headers().get(header::ACCEPT_ENCODING),
+            Some(&b"gzip"[..])
+        );
+    }
+
+    fn framing_error(head: &[u8]) -> bool {
+        matches!(
+            StreamParser::new(head).parse::<Request>(),
+            Err(ParseError::Syntax {
+                context: Some(Context::Framing),
+                ..
+            })
+        )
+    }
+
+    #[test]
+    fn request_content_length_and_transfer_encoding() {
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n"
+        ));
+    }
+
+    #[test]
+    fn request_transfer_encoding_not_chunked() {
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked, gzip\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.0\r\nTransfer-Encoding: chunked\r\n\r\n"
+        ));
+
+        let mut p =
+            StreamParser::new(&b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n"[..]);
+        let request = p.parse::<Request>().unwrap();
+        assert_eq!(request.body_length(), BodyLength::Chunked);
+    }
+
+    #[test]
+    fn request_duplicate_content_length() {
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: 10\r\nContent-Length: 11\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: 10, 11\r\n\r\n"
+        ));
+
+        // identical values are the same length
+        let mut p = StreamParser::new(
+            &b"POST / HTTP/1.1\r\nContent-Length: 10, 10\r\nContent-Length: 10\r\n\r\n"[..],
+        );
+        let request = p.parse::<Request>().unwrap();
+        assert_eq!(request.body_length(), BodyLength::Length(10));
+    }
+
+    #[test]
+    fn request_invalid_content_length() {
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: +10\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: 0x10\r\n\r\n"
+        ));
+        assert!(framing_error(
+            b"POST / HTTP/1.1\r\nContent-Length: 10,\r\n\r\n"
+        ));
+        assert!(framing_error(b"POST / HTTP/1.1\r\nContent-Length:\r\n\r\n"));
+    }
+
+    #[test]
+    fn request_whitespace_before_first_header() {
+        let mut p =
+            StreamParser::new(&b"GET / HTTP/1.1\r\n Transfer-Encoding: chunked\r\n\r\n"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::Syntax {
+                offset: 16,
+                context: Some(Context::Header)
+            })
+        ));
+    }
+
+    #[test]
+    fn request_space_before_colon() {
+        let mut p =
+            StreamParser::new(&b"POST / HTTP/1.1\r\nContent-Length : 10\r\n\r\n0123456789"[..]);
+        assert!(matches!(
+            p.parse::<Request>(),
+            Err(ParseError::Syntax {
+                offset: 17,
+                context: Some(Context::Header)
+            })
+        ));
+    }
 }
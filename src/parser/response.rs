@@ -1,2 +1,215 @@
-#[derive(Debug)]
-pub(super) struct StatusLine {}
+use std::str;
+
+use winnow::{
+    ascii::{crlf, digit1},
+    combinator::{empty, fail, opt, preceded, repeat, seq, terminated},
+    stream::AsChar,
+    token::take_till,
+    Parser,
+};
+
+use crate::spec::{
+    header::{self, HeaderMap},
+    message::MessageHeader,
+    protocol::HttpVersion,
+    response::{Response, Status, StatusLine},
+};
+
+use super::{
+    base::Parse,
+    error::{Limit, HEADER, STATUS_LINE},
+    limits::Limits,
+    message::{check_content_length, check_head_limits},
+};
+
+impl Parse for Status {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        let status = digit1
+            .verify(|code: &[u8]| code.len() == 3)
+            .map(|code| str::from_utf8(code).expect("digits are ascii"))
+            .try_map(str::parse::<u16>)
+            .verify(|code| *code >= 100)
+            .map(Status::from_code)
+            .parse_next(input)?;
+        Ok(status)
+    }
+}
+
+impl Parse for StatusLine {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        // the reason phrase is only informative, the one of `Status` is sent on
+        let status_line = seq! {StatusLine {
+            http_version: HttpVersion::parse,
+            _: ' ',
+            status: Status::parse,
+            _: opt(preceded(' ', take_till(0.., |c: I::Token| "\r\n".contains(c.as_char())))),
+            _: "\r\n",
+        }}
+        .parse_next(input)?;
+        Ok(status_line)
+    }
+}
+
+impl Parse for Response {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        // only the head is parsed, the body is streamed by the caller
+        let response = seq! {
+            Response {
+                status_line: StatusLine::parse.context(STATUS_LINE),
+                headers: repeat(0.., terminated(MessageHeader::parse, crlf))
+                    .map(|headers: Vec<MessageHeader>| HeaderMap::from(headers)),
+                _: crlf.context(HEADER),
+                body: empty.map(|_| None),
+            }
+        }
+        .parse_next(input)?;
+
+        // `Transfer-Encoding` takes precedence, RFC 9112 section 6.3
+        if !response.headers.contains_key(header::TRANSFER_ENCODING) {
+            if let Err(context) = check_content_length(&response.headers) {
+                fail.context(context).parse_next(input)?
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn check_limits(input: &[u8], limits: &Limits) -> Result<(), Limit> {
+        let Some(head) = check_head_limits(input, limits)? else {
+            return Ok(());
+        };
+        let Ok(response) = Response::parse(&mut &input[..head]) else {
+            return Ok(());
+        };
+        match response.headers.content_length() {
+            Some(content_length) if content_length > limits.body => Err(Limit::Body),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        parser::{Context, Limit, ParseError, StreamParser},
+        spec::{
+            request::{BodyLength, Method},
+            response::Response,
+        },
+        test_parse_error, test_parse_ok,
+    };
+
+    use super::*;
+
+    test_parse_ok!(status, b"404 ", Status::NotFound, b" ");
+    test_parse_ok!(status_other, b"299", Status::Other(299), b"");
+    test_parse_error!(status_short, Status, b"20 ", b"20 ");
+    test_parse_error!(status_informational, Status, b"099", b"099");
+
+    test_parse_ok!(
+        status_line,
+        b"HTTP/1.1 502 Bad Gateway\r\n",
+        StatusLine {
+            http_version: HttpVersion { major: 1, minor: 1 },
+            status: Status::BadGateway,
+        },
+        b""
+    );
+    test_parse_ok!(
+        status_line_no_reason,
+        b"HTTP/1.0 200\r\n",
+        StatusLine {
+            http_version: HttpVersion { major: 1, minor: 0 },
+            status: Status::OK,
+        },
+        b""
+    );
+
+    fn parse(input: &str) -> Result<Response, ParseError> {
+        StreamParser::new(input.as_bytes()).parse()
+    }
+
+    #[test]
+    fn response_head() {
+        let response =
+            parse("HTTP/1.1 201 Created\r\nLocation: /a\r\nContent-Length: 2\r\n\r\nok").unwrap();
+        assert_eq!(response.status(), Status::Created);
+        assert_eq!(response.headers().get("location"), Some(&b"/a"[..]));
+        assert_eq!(response.body(), b"");
+        assert_eq!(response.body_length(&Method::Get), BodyLength::Length(2));
+    }
+
+    #[test]
+    fn response_body_length() {
+        let body_length = |input: &str, method: Method| parse(input).unwrap().body_length(&method);
+        let head = Method::Extension(b"HEAD".to_vec());
+        assert_eq!(
+            body_length("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", head),
+            BodyLength::Length(0)
+        );
+        assert_eq!(
+            body_length("HTTP/1.1 304 Not Modified\r\n\r\n", Method::Get),
+            BodyLength::Length(0)
+        );
+        assert_eq!(
+            body_length(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\nContent-Length: 5\r\n\r\n",
+                Method::Get
+            ),
+            BodyLength::Chunked
+        );
+        assert_eq!(
+            body_length(
+                "HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip\r\n\r\n",
+                Method::Get
+            ),
+            BodyLength::UntilClose
+        );
+        assert_eq!(
+            body_length("HTTP/1.0 200 OK\r\n\r\n", Method::Post),
+            BodyLength::UntilClose
+        );
+    }
+
+    #[test]
+    fn response_invalid() {
+        assert!(matches!(
+            parse("HTTP/1.1 OK\r\n\r\n"),
+            Err(ParseError::Syntax {
+                context: Some(Context::StatusLine),
+                ..
+            })
+        ));
+        assert!(matches!(
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 1, 2\r\n\r\n"),
+            Err(ParseError::Syntax {
+                context: Some(Context::Framing),
+                ..
+            })
+        ));
+        let limits = Limits {
+            body: 1,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            StreamParser::with_limits(&b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n"[..], limits)
+                .parse::<Response>(),
+            Err(ParseError::LimitExceeded(Limit::Body))
+        ));
+    }
+}
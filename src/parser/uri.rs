@@ -0,0 +1,146 @@
+use winnow::{
+    combinator::{alt, eof, opt, preceded, terminated},
+    stream::AsChar,
+    token::{rest, take_till, take_while},
+    Parser,
+};
+
+use crate::spec::uri::RequestURI;
+
+use super::base::Parse;
+
+impl Parse for RequestURI {
+    fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
+    where
+        Self: std::marker::Sized,
+        I: super::base::Convertible<'i>,
+        I::Token: winnow::stream::AsChar,
+    {
+        let uri = take_while(1.., |c: I::Token| c.as_char().is_ascii_graphic())
+            .and_then(request_target)
+            .verify(RequestURI::is_valid)
+            .parse_next(input)?;
+        Ok(uri)
+    }
+}
+
+/// The whole request-target, it was already split from the request line.
+fn request_target(input: &mut &[u8]) -> winnow::ModalResult<RequestURI> {
+    terminated(
+        alt((
+            "*".value(RequestURI::Asterisk),
+            origin_form,
+            absolute_form,
+            take_till(1.., ['/', '?'])
+                .map(|authority: &[u8]| RequestURI::Authority(authority.to_vec())),
+        )),
+        eof,
+    )
+    .parse_next(input)
+}
+
+fn path(input: &mut &[u8]) -> winnow::ModalResult<Vec<u8>> {
+    ('/', take_till(0.., '?'))
+        .take()
+        .map(<[u8]>::to_vec)
+        .parse_next(input)
+}
+
+fn query(input: &mut &[u8]) -> winnow::ModalResult<Option<Vec<u8>>> {
+    opt(preceded('?', rest.map(<[u8]>::to_vec))).parse_next(input)
+}
+
+fn origin_form(input: &mut &[u8]) -> winnow::ModalResult<RequestURI> {
+    let (path, query) = (path, query).parse_next(input)?;
+    Ok(RequestURI::Origin { path, query })
+}
+
+fn absolute_form(input: &mut &[u8]) -> winnow::ModalResult<RequestURI> {
+    // scheme = ALPHA *( ALPHA / DIGIT / "+" / "-" / "." )
+    let scheme = (
+        take_while(1, AsChar::is_alpha),
+        take_while(0.., |c: u8| {
+            c.is_ascii_alphanumeric() || b"+-.".contains(&c)
+        }),
+    )
+        .take()
+        .parse_next(input)?;
+    let (_, authority, path, query) = (
+        "://",
+        take_till(0.., ['/', '?']),
+        opt(path).map(Option::unwrap_or_default),
+        query,
+    )
+        .parse_next(input)?;
+    Ok(RequestURI::Absolute {
+        scheme: scheme.to_vec(),
+        authority: authority.to_vec(),
+        path,
+        query,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test_parse_error, test_parse_ok};
+
+    test_parse_ok!(
+        origin,
+        b"/echo/a%20b?x=1 ",
+        RequestURI::Origin {
+            path: b"/echo/a%20b".to_vec(),
+            query: Some(b"x=1".to_vec())
+        },
+        b" "
+    );
+    test_parse_ok!(
+        origin_empty_query,
+        b"/?\r\n",
+        RequestURI::Origin {
+            path: b"/".to_vec(),
+            query: Some(vec![])
+        },
+        b"\r\n"
+    );
+    test_parse_ok!(
+        absolute,
+        b"http://localhost:4221/files/a?b ",
+        RequestURI::Absolute {
+            scheme: b"http".to_vec(),
+            authority: b"localhost:4221".to_vec(),
+            path: b"/files/a".to_vec(),
+            query: Some(b"b".to_vec())
+        },
+        b" "
+    );
+    test_parse_ok!(
+        absolute_no_path,
+        b"http://localhost ",
+        RequestURI::Absolute {
+            scheme: b"http".to_vec(),
+            authority: b"localhost".to_vec(),
+            path: vec![],
+            query: None
+        },
+        b" "
+    );
+    test_parse_ok!(
+        authority,
+        b"localhost:4221 ",
+        RequestURI::Authority(b"localhost:4221".to_vec()),
+        b" "
+    );
+    test_parse_ok!(asterisk, b"* ", RequestURI::Asterisk, b" ");
+    test_parse_error!(invalid_escape, RequestURI, b"/a%2 ", b"/a%2 ");
+    test_parse_error!(authority_with_path, RequestURI, b"host/a ", b"host/a ");
+    test_parse_ok!(
+        non_ascii,
+        b"/\xc3\xa9 ",
+        RequestURI::Origin {
+            path: b"/".to_vec(),
+            query: None
+        },
+        b"\xc3\xa9 "
+    );
+}
@@ -2,12 +2,14 @@ use winnow::{ascii::space0, combinator::opt, stream::AsChar, Parser};
 
 use super::base::Parse;
 
-pub(super) fn is_space<T: AsChar>(c: T) -> bool {
-    " \t\r\n".contains(c.as_char())
+/// `tchar` from RFC 9110 section 5.6.2, the characters allowed in a token.
+pub(crate) fn is_tchar<T: AsChar>(c: T) -> bool {
+    let c = c.as_char();
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
 }
 
 #[derive(Debug, PartialEq, Eq)]
-struct Lws(Vec<u8>);
+pub(super) struct Lws(Vec<u8>);
 impl Parse for Lws {
     fn parse<'i, I>(input: &mut I) -> winnow::ModalResult<Self>
     where
@@ -0,0 +1,146 @@
+//! Forwarding requests to upstream servers, for the paths given with `--proxy`.
+//!
+//! Upstream connections are kept open between requests in a pool shared by
+//! every client connection, see [`crate::client`].
+
+use std::{str::FromStr, time::Duration};
+
+use crate::{
+    client::Pool,
+    spec::{
+        header::{self, HeaderMap},
+        uri::RequestURI,
+    },
+};
+
+/// Headers only meaningful for a single connection, never forwarded, RFC 9110 section 7.6.1.
+const HOP_BY_HOP: [&str; 8] = [
+    header::CONNECTION,
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    header::TRANSFER_ENCODING,
+];
+
+/// Connections to every upstream.
+pub(crate) static UPSTREAMS: Pool = Pool::new();
+
+/// Requests whose path starts with `prefix` are forwarded to `upstream`, `PATH=HOST:PORT`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ProxyRule {
+    prefix: Vec<Vec<u8>>,
+    upstream: String,
+}
+
+impl FromStr for ProxyRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let (path, upstream) = rule.split_once('=').ok_or("expected PATH=HOST:PORT")?;
+        let Some(path) = path.strip_prefix('/') else {
+            return Err(format!("the path {path:?} does not start with /"));
+        };
+        match upstream.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => return Err(format!("the upstream {upstream:?} is not HOST:PORT")),
+        }
+        Ok(ProxyRule {
+            // `/api` and `/api/` both match `/api/...`
+            prefix: path
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(|segment| segment.as_bytes().to_vec())
+                .collect(),
+            upstream: upstream.to_string(),
+        })
+    }
+}
+
+impl ProxyRule {
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+}
+
+/// The proxy rules and the timeout of every upstream operation.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Proxies {
+    pub(crate) rules: Vec<ProxyRule>,
+    pub(crate) timeout: Duration,
+}
+
+impl Proxies {
+    /// The rule with the longest prefix matching the path of `request_uri`.
+    pub(crate) fn find(&self, request_uri: &RequestURI) -> Option<&ProxyRule> {
+        let segments = request_uri.path_segments();
+        self.rules
+            .iter()
+            .filter(|rule| segments.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+    }
+}
+
+/// Removes the hop-by-hop headers, including the ones listed in `Connection`.
+pub(crate) fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<Vec<u8>> = headers
+        .get_all(header::CONNECTION)
+        .flat_map(|value| value.split(|&c| c == b','))
+        .map(|option| option.trim_ascii().to_vec())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn uri(path: &[u8]) -> RequestURI {
+        RequestURI::Origin {
+            path: path.to_vec(),
+            query: None,
+        }
+    }
+
+    #[test]
+    fn rules() {
+        let proxies = Proxies {
+            rules: vec![
+                "/=127.0.0.1:1".parse().unwrap(),
+                "/api/=127.0.0.1:2".parse().unwrap(),
+                "/api/v2=localhost:3".parse().unwrap(),
+            ],
+            timeout: Duration::from_secs(1),
+        };
+        let upstream = |path| proxies.find(&uri(path)).map(ProxyRule::upstream);
+        assert_eq!(upstream(b"/api"), Some("127.0.0.1:2"));
+        assert_eq!(upstream(b"/api/v2/users?id=1"), Some("localhost:3"));
+        assert_eq!(upstream(b"/apis"), Some("127.0.0.1:1"));
+
+        assert!("api=127.0.0.1:1".parse::<ProxyRule>().is_err());
+        assert!("/api=127.0.0.1".parse::<ProxyRule>().is_err());
+        assert!("/api".parse::<ProxyRule>().is_err());
+    }
+
+    #[test]
+    fn hop_by_hop() {
+        let mut headers = HeaderMap::new();
+        headers.append("Connection", "keep-alive, X-Private");
+        headers.append("X-Private", "1");
+        headers.append("Keep-Alive", "timeout=5");
+        headers.append("Transfer-Encoding", "chunked");
+        headers.append("Accept", "*/*");
+        strip_hop_by_hop(&mut headers);
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![(&b"Accept"[..], &b"*/*"[..])]
+        );
+    }
+}
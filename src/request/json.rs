@@ -0,0 +1,214 @@
+use std::io::{self, Read};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::spec::{
+    header,
+    message::parameters,
+    response::{Response, Status},
+};
+
+use super::{AdditionalBody, AdditionalHeader, Request};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum JsonError {
+    #[error("expected an application/json body")]
+    UnsupportedMediaType,
+    #[error("the JSON body is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("invalid JSON body: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl JsonError {
+    pub fn status(&self) -> Status {
+        match self {
+            JsonError::UnsupportedMediaType => Status::UnsupportedMediaType,
+            JsonError::TooLarge(_) => Status::PayloadTooLarge,
+            JsonError::Invalid(_) | JsonError::Io(_) => Status::BadRequest,
+        }
+    }
+
+    /// A problem details response describing the error.
+    pub fn into_response(self) -> Response {
+        Response::problem(self.status(), Some(self.to_string()))
+    }
+}
+
+impl Request<'_> {
+    /// Whether the body is declared as `application/json` or a `+json` media type.
+    pub fn is_json(&self) -> bool {
+        self.inner
+            .headers
+            .get(header::CONTENT_TYPE)
+            .map(|content_type| parameters(content_type).0.to_ascii_lowercase())
+            .is_some_and(|media_type| {
+                media_type == b"application/json"
+                    || (media_type.starts_with(b"application/") && media_type.ends_with(b"+json"))
+            })
+    }
+
+    /// Deserializes a JSON body, which is held in memory up to the `json` limit.
+    pub fn json<T: DeserializeOwned>(&mut self) -> Result<T, JsonError> {
+        if !self.is_json() {
+            return Err(JsonError::UnsupportedMediaType);
+        }
+        let limit = self.limits.json;
+        let mut body = vec![];
+        // one byte more than the limit tells a body at the limit from a larger one
+        self.body
+            .by_ref()
+            .take(limit as u64 + 1)
+            .read_to_end(&mut body)?;
+        if body.len() > limit {
+            return Err(JsonError::TooLarge(limit));
+        }
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+impl Response {
+    /// A `200 OK` response with `value` as its JSON body.
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Response {
+        let body = serde_json::to_vec(value).expect("values are serializable to JSON");
+        Response::with_body(Status::OK, "application/json", body)
+    }
+
+    /// A problem details response (RFC 9457) for `status`.
+    pub(crate) fn problem(status: Status, detail: Option<String>) -> Response {
+        let mut problem = serde_json::json!({
+            "type": "about:blank",
+            "title": status.reason_phrase(),
+            "status": status.code(),
+        });
+        if let Some(detail) = detail {
+            problem["detail"] = detail.into();
+        }
+        let body = serde_json::to_vec(&problem).expect("problem details are valid JSON");
+        Response::with_body(status, "application/problem+json", body)
+    }
+
+    fn with_body(status: Status, content_type: &str, body: Vec<u8>) -> Response {
+        let mut response = Response::new(status);
+        response.headers.insert(header::CONTENT_TYPE, content_type);
+        response.set_body(body);
+        response
+    }
+
+    /// Splits the response into what `HandleRequest::handle` returns.
+    pub(crate) fn into_parts(self) -> (Option<Status>, AdditionalHeader, AdditionalBody) {
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    String::from_utf8_lossy(name).into_owned(),
+                    String::from_utf8_lossy(value).into_owned(),
+                )
+            })
+            .collect();
+        let body = self.body.map(|body| body.0).unwrap_or_default();
+        (Some(self.status_line.status), headers, body)
+    }
+}
+
+/// Gives an error response without a body a problem details one.
+pub(super) fn problem_details(response: &mut Response) {
+    let status = &response.status_line.status;
+    if status.code() < 400 || response.body.is_some() {
+        return;
+    }
+    let problem = Response::problem(*status, None);
+    response.headers.insert(
+        header::CONTENT_TYPE,
+        problem
+            .headers
+            .get(header::CONTENT_TYPE)
+            .unwrap_or_default(),
+    );
+    response.body = problem.body;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parse;
+    use crate::{
+        bytes::ToBytes, parser::Limits, proxy::Proxies, request::Body,
+        spec::request::Request as RawRequest,
+    };
+
+    fn json(
+        content_type: &str,
+        body: &[u8],
+        limits: Limits,
+    ) -> Result<serde_json::Value, JsonError> {
+        let request = RawRequest::convert(&format!(
+            "POST / HTTP/1.1\r\nContent-Type: {content_type}\r\n\r\n"
+        ))
+        .unwrap();
+        let mut body = body;
+        let mut request = Request {
+            inner: request,
+            body: Body::new(&mut body),
+            cli_directory: None,
+            limits,
+            metrics: false,
+            proxies: Proxies::default(),
+        };
+        request.json()
+    }
+
+    #[test]
+    fn request_json() {
+        let value = json(
+            "application/json; charset=utf-8",
+            br#"{"a": [1, 2]}"#,
+            Limits::default(),
+        );
+        assert_eq!(value.unwrap(), serde_json::json!({"a": [1, 2]}));
+        assert!(json("application/vnd.api+json", b"null", Limits::default()).is_ok());
+    }
+
+    #[test]
+    fn request_json_errors() {
+        assert!(matches!(
+            json("text/plain", b"{}", Limits::default()),
+            Err(JsonError::UnsupportedMediaType)
+        ));
+        assert!(matches!(
+            json("application/json", b"{", Limits::default()),
+            Err(JsonError::Invalid(_))
+        ));
+        let limits = Limits {
+            json: 4,
+            ..Limits::default()
+        };
+        assert!(json("application/json", b"1234", limits).is_ok());
+        assert!(matches!(
+            json("application/json", b"12345", limits),
+            Err(JsonError::TooLarge(4))
+        ));
+    }
+
+    #[test]
+    fn response_json() {
+        assert_eq!(
+            Response::json(&[1, 2]).into_bytes(),
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n[1,2]"
+        );
+    }
+
+    #[test]
+    fn problem() {
+        assert_eq!(
+            Response::problem(Status::NotFound, Some("no such file".into())).into_bytes(),
+            "HTTP/1.1 404 Not Found\r\n\
+             Content-Type: application/problem+json\r\n\r\n\
+             {\"detail\":\"no such file\",\"status\":404,\"title\":\"Not Found\",\"type\":\"about:blank\"}"
+                .as_bytes()
+        );
+    }
+}
@@ -1,88 +1,317 @@
+#[cfg(feature = "json")]
+mod json;
+mod params;
 mod routes;
 
-use flate2::{write::GzEncoder, Compression};
-use std::{io::Write, path::PathBuf};
+use std::{
+    io::{self, Read},
+    path::PathBuf,
+    time::Instant,
+};
 
 use crate::{
     bytes::ToBytes,
+    event_stream::{is_event_stream, EventStream},
+    metrics::METRICS,
+    middleware::Chain,
+    parser::{form_data_boundary, Limit, Limits, Multipart, ParseError},
+    proxy::Proxies,
     spec::{
-        message::{FieldContent, FieldName, FieldValue, MessageBody, MessageHeader},
+        header,
+        message::{parameters, MessageBody},
+        protocol::HttpVersion,
         request::{Method, Request as RawRequest},
-        response::{Response, Status, StatusLine},
+        response::{InterimResponse, Response, Status, StatusLine},
+        uri::RequestURI,
     },
+    websocket::WebSocket,
     ServerResponse,
 };
+pub(crate) use params::Params;
 pub(crate) use routes::Route;
 
 pub(super) type AdditionalHeader = Vec<(String, String)>;
 pub(super) type AdditionalBody = Vec<u8>;
 
-#[derive(Debug, PartialEq, Eq)]
-enum Encoding {
-    Gzip,
-    Invalid,
-}
-
-#[derive(Debug, PartialEq, Eq)]
-pub(super) struct Request {
+pub(super) struct Request<'a> {
     inner: RawRequest,
+    body: Body<'a>,
     cli_directory: Option<PathBuf>,
-    encoding: Vec<Encoding>,
+    limits: Limits,
+    metrics: bool,
+    proxies: Proxies,
 }
 
 pub(super) trait HandleRequest {
-    fn handle(&self, request: &Request) -> (Option<Status>, AdditionalHeader, AdditionalBody);
+    fn handle(&self, request: &mut Request) -> (Option<Status>, AdditionalHeader, AdditionalBody);
+}
+
+/// A route serving WebSocket connections once it accepted their handshake.
+pub(super) trait HandleWebSocket {
+    fn serve(&self, socket: &mut WebSocket) -> io::Result<()>;
+}
+
+/// A route writing the events of a `text/event-stream` response it answered with.
+pub(super) trait HandleEventStream {
+    fn stream(&self, query: &Params, events: &mut EventStream) -> io::Result<()>;
+}
+
+/// The body of a request, which remembers whether reading it went over the body limit.
+pub(super) struct Body<'a> {
+    reader: &'a mut dyn Read,
+    too_large: bool,
+}
+
+impl<'a> Body<'a> {
+    pub fn new(reader: &'a mut dyn Read) -> Body<'a> {
+        Body {
+            reader,
+            too_large: false,
+        }
+    }
 }
 
-pub(crate) struct Handler {
-    request: Request,
+impl Read for Body<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf);
+        if let Err(e) = &read {
+            self.too_large |= ParseError::exceeded_limit(e) == Some(Limit::Body);
+        }
+        read
+    }
+}
+
+pub(crate) struct Handler<'a> {
+    request: Request<'a>,
     response: Response,
 }
 
-impl Request {
+impl Request<'_> {
     pub fn method(&self) -> &Method {
         &self.inner.request_line.method
     }
+
+    /// Parameters of the query string, empty without one.
+    pub fn query(&self) -> Params {
+        self.inner
+            .request_uri()
+            .raw_query()
+            .map(Params::parse)
+            .unwrap_or_default()
+    }
+
+    /// The first value of the query parameter `name`.
+    pub fn query_param(&self, name: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+        self.query().get(name).map(<[u8]>::to_vec)
+    }
+
+    /// Reads an `application/x-www-form-urlencoded` body, `None` for other content types.
+    pub fn form(&mut self) -> io::Result<Option<Params>> {
+        let Some(content_type) = self.inner.headers.get(header::CONTENT_TYPE) else {
+            return Ok(None);
+        };
+        if !parameters(content_type)
+            .0
+            .eq_ignore_ascii_case(b"application/x-www-form-urlencoded")
+        {
+            return Ok(None);
+        }
+        // the body size is bounded by the parser limits
+        let mut body = vec![];
+        self.body.read_to_end(&mut body)?;
+        Ok(Some(Params::parse(&body)))
+    }
+
+    /// Streams a `multipart/form-data` body, `None` for other content types.
+    pub fn multipart(&mut self) -> Option<Multipart<&mut dyn Read>> {
+        let content_type = self.inner.headers.get(header::CONTENT_TYPE)?;
+        let boundary = form_data_boundary(content_type)?;
+        Some(Multipart::new(
+            &mut self.body as &mut dyn Read,
+            &boundary,
+            self.limits,
+        ))
+    }
 }
 
-impl Handler {
-    pub fn new(request: RawRequest, cli_directory: Option<PathBuf>) -> Handler {
+impl<'a> Handler<'a> {
+    pub fn new(
+        request: RawRequest,
+        body: &'a mut dyn Read,
+        cli_directory: Option<PathBuf>,
+        limits: Limits,
+        metrics: bool,
+        proxies: Proxies,
+    ) -> Handler<'a> {
         Handler {
-            response: Response {
-                status_line: StatusLine {
-                    http_version: request.request_line.http_version,
-                    status: Status::NotFound,
-                },
-                headers: vec![],
-                body: None,
-            },
+            response: Response::new(Status::NotFound),
             request: Request {
                 inner: request,
+                body: Body::new(body),
                 cli_directory,
-                encoding: vec![],
+                limits,
+                metrics,
+                proxies,
             },
         }
     }
 
-    pub fn process(mut self) -> ServerResponse {
-        let route = Route::from(&self.request.inner.request_line.request_uri);
+    /// Answers an `Expect` header before the body is read: `Continue` when the
+    /// request can be served, otherwise the status of a final response.
+    pub fn expect(request: &RawRequest, proxies: &Proxies, chain: &Chain) -> Option<Status> {
+        // HTTP/1.0 clients do not know about interim responses
+        if request.request_line.http_version < (HttpVersion { major: 1, minor: 1 }) {
+            return None;
+        }
+        let expect = request.headers.get(header::EXPECT)?;
+        if !expect.eq_ignore_ascii_case(b"100-continue") {
+            return Some(Status::ExpectationFailed);
+        }
+        // the body size was already checked against the limits by the parser
+        if Route::resolve(&request.request_line.request_uri, proxies) == Route::Unknown
+            && !chain.handles(request)
+        {
+            return Some(Status::NotFound);
+        }
+        Some(Status::Continue)
+    }
+
+    /// The `100 Continue` interim response, sent before the body is read.
+    pub fn interim(request: &RawRequest) -> ServerResponse {
+        let response = InterimResponse {
+            status_line: StatusLine {
+                http_version: request.request_line.http_version,
+                status: Status::Continue,
+            },
+        };
+        ServerResponse::Continue(response.into_bytes())
+    }
 
-        if let Some(accept_encoding) = self.request.inner.find_value(b"Accept-Encoding") {
-            self.request.encoding = accept_encoding
-                .split(|u| u == &b',')
-                .map(|v| match v.trim_ascii() {
-                    b"gzip" => Encoding::Gzip,
-                    _ => Encoding::Invalid,
-                })
-                .collect();
+    /// Builds the response to a request that is not processed, framed for the
+    /// connection to be closed afterwards.
+    pub fn reject(status: Status) -> Response {
+        let mut response = Handler::rejection(status);
+        let content_length = response.body.as_ref().map_or(0, |body| body.0.len());
+        response
+            .headers
+            .append(header::CONTENT_LENGTH, content_length.to_string());
+        response.headers.append(header::CONNECTION, "close");
+        response
+    }
+
+    /// The response to a request that is not processed, with its problem details.
+    pub fn rejection(status: Status) -> Response {
+        #[cfg_attr(not(feature = "json"), allow(unused_mut))]
+        let mut response = Response::new(status);
+        #[cfg(feature = "json")]
+        json::problem_details(&mut response);
+        response
+    }
+
+    /// Whether the route of `request` may take the connection over with its response.
+    pub fn may_switch(request: &RawRequest, proxies: &Proxies) -> bool {
+        Route::resolve(&request.request_line.request_uri, proxies).takes_connection()
+    }
+
+    /// Serves a connection switched to WebSocket by the route of `request_uri`.
+    pub fn websocket(request_uri: &RequestURI, mut socket: WebSocket) -> io::Result<()> {
+        Route::from(request_uri).serve(&mut socket)
+    }
+
+    /// Writes the events of a `text/event-stream` response of the route of `request_uri`.
+    pub fn event_stream(request_uri: &RequestURI, mut events: EventStream) -> io::Result<()> {
+        let query = request_uri
+            .raw_query()
+            .map(Params::parse)
+            .unwrap_or_default();
+        Route::from(request_uri).stream(&query, &mut events)
+    }
+
+    /// Runs the middleware and the route, the response is not framed yet.
+    pub fn respond(mut self, chain: &Chain) -> Response {
+        let received_at = self.request.inner.received_at.unwrap_or_else(Instant::now);
+        let route = self.resolve();
+
+        let (response, ran) = chain.before(&mut self.request.inner);
+        match response {
+            Some(response) => self.response = response,
+            None => self.route(&route),
         }
 
-        let (status, headers, body) = route.handle(&self.request);
+        #[cfg(feature = "json")]
+        json::problem_details(&mut self.response);
+
+        chain.after(ran, &self.request.inner, &mut self.response);
+        METRICS.record_request(
+            route.name(),
+            self.request.method().as_bytes(),
+            self.response.status().code(),
+            received_at.elapsed(),
+        );
+        self.response.status_line.http_version = self.request.inner.request_line.http_version;
+        self.response
+    }
+
+    /// Handles the request, the response is framed for HTTP/1.1.
+    pub fn process(self, chain: &Chain) -> ServerResponse {
+        let takes_connection = self.resolve().takes_connection();
+        let mut response = self.respond(chain);
+        // the connection belongs to the new protocol, there is no body to frame
+        if response.status() == Status::SwitchingProtocols {
+            return ServerResponse::Upgrade(response.into_bytes());
+        }
+        // the events are written by the route afterwards, until the connection is closed
+        if takes_connection && is_event_stream(&response) {
+            response.headers.insert(header::CONNECTION, "close");
+            return ServerResponse::Stream(response.into_bytes());
+        }
+
+        // always framed, so pipelined responses can be told apart
+        let content_length = response.body().len();
+        // those never have a body, a length would describe the representation
+        if !matches!(response.status().code(), 204 | 304) {
+            response
+                .headers
+                .insert(header::CONTENT_LENGTH, content_length.to_string());
+        }
+
+        let close = response
+            .headers
+            .get_field_value(header::CONNECTION)
+            .is_some_and(|connection| {
+                connection
+                    .list()
+                    .iter()
+                    .any(|option| option.eq_ignore_ascii_case(b"close"))
+            });
+        match close {
+            true => ServerResponse::Close(response.into_bytes()),
+            false => ServerResponse::Continue(response.into_bytes()),
+        }
+    }
+
+    fn resolve(&self) -> Route {
+        Route::resolve(
+            &self.request.inner.request_line.request_uri,
+            &self.request.proxies,
+        )
+    }
+
+    fn route(&mut self, route: &impl HandleRequest) {
+        let (status, headers, body) = route.handle(&mut self.request);
+        // only a body streamed without a length goes over the limit while it is read, the
+        // rest of it cannot be skipped
+        if self.request.body.too_large {
+            self.response.status_line.status = Status::PayloadTooLarge;
+            self.response.headers.append(header::CONNECTION, "close");
+            return;
+        }
         if let Some(status) = status {
             self.response.status_line.status = status;
         }
+        // appended, so a route can send a header like `Set-Cookie` more than once
         for (header, content) in headers {
-            self.add_response_header(&header, &content);
+            self.response.headers.append(header, content);
         }
         if !body.is_empty() {
             match self.response.body.as_mut() {
@@ -90,44 +319,185 @@ impl Handler {
                 None => self.response.body = Some(MessageBody(body)),
             }
         }
+    }
+}
 
-        if self.request.encoding.contains(&Encoding::Gzip) {
-            self.add_response_header("Content-Encoding", "gzip");
-            if let Some(MessageBody(data)) = self.response.body.as_ref() {
-                let mut e = GzEncoder::new(Vec::new(), Compression::default());
-                e.write_all(data).expect("failed to encode using gzip");
-                let body = e.finish().expect("failed to encode gzip");
-                self.response.body = Some(MessageBody(body));
-            }
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{health::Health, parser::Parse, SetCookie};
 
-        if let Some(body) = self.response.body.as_ref() {
-            self.add_response_header("Content-Length", &body.0.len().to_string());
-        }
+    fn expect(request: &str) -> Option<Status> {
+        let request = RawRequest::convert(request).unwrap();
+        Handler::expect(&request, &Proxies::default(), &Chain::default())
+    }
+
+    #[test]
+    fn accept_encoding_q_zero() {
+        let compressed = |accept_encoding: &str| {
+            process(
+                &format!("GET /echo/abc HTTP/1.1\r\nAccept-Encoding: {accept_encoding}\r\n\r\n"),
+                b"",
+            )
+            .windows(24)
+            .any(|line| line == b"Content-Encoding: gzip\r\n")
+        };
+        assert!(compressed("br, GZIP;q=0.5"));
+        assert!(!compressed("gzip;q=0, br"));
+    }
+
+    #[test]
+    fn connection_list() {
+        let response = process(
+            "GET / HTTP/1.1\r\nConnection: keep-alive, Close\r\n\r\n",
+            b"",
+        );
+        assert!(response
+            .windows(19)
+            .any(|line| line == b"Connection: close\r\n"));
+    }
 
-        if let Some(close) = self.request.inner.find_value(b"Connection") {
-            if close == b"close" {
-                self.add_response_header("Connection", "close");
-                return ServerResponse::Close(self.response.into_bytes());
+    #[test]
+    fn expect_continue() {
+        let request = "POST /files/a HTTP/1.1\r\nExpect: 100-continue\r\n\r\n";
+        assert_eq!(expect(request), Some(Status::Continue));
+        assert_eq!(
+            Handler::interim(&RawRequest::convert(request).unwrap()).data(),
+            b"HTTP/1.1 100 Continue\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn expect_none() {
+        assert_eq!(expect("POST /files/a HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(
+            expect("POST /files/a HTTP/1.0\r\nExpect: 100-continue\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn expect_unknown_route() {
+        assert_eq!(
+            expect("POST /unknown HTTP/1.1\r\nExpect: 100-continue\r\n\r\n"),
+            Some(Status::NotFound)
+        );
+    }
+
+    #[test]
+    fn expect_middleware_path() {
+        let request = "GET /healthz HTTP/1.1\r\nExpect: 100-continue\r\n\r\n";
+        let request = RawRequest::convert(request).unwrap();
+        let chain = Chain::default().with(Health::new("/healthz", "/readyz", None));
+        assert_eq!(
+            Handler::expect(&request, &Proxies::default(), &chain),
+            Some(Status::Continue)
+        );
+        assert_eq!(
+            Handler::expect(&request, &Proxies::default(), &Chain::default()),
+            Some(Status::NotFound)
+        );
+    }
+
+    #[test]
+    fn expect_unsupported() {
+        assert_eq!(
+            expect("POST /files/a HTTP/1.1\r\nExpect: something\r\n\r\n"),
+            Some(Status::ExpectationFailed)
+        );
+    }
+
+    fn process_with(request: &str, body: &[u8], metrics: bool) -> Vec<u8> {
+        let request = RawRequest::convert(request).unwrap();
+        let mut body = body;
+        Handler::new(
+            request,
+            &mut body,
+            None,
+            Limits::default(),
+            metrics,
+            Proxies::default(),
+        )
+        .process(&Chain::default())
+        .data()
+        .to_vec()
+    }
+
+    fn process(request: &str, body: &[u8]) -> Vec<u8> {
+        process_with(request, body, false)
+    }
+
+    #[test]
+    fn echo_query() {
+        assert!(
+            process("GET /echo?text=a+b%21&text=c HTTP/1.1\r\n\r\n", b"")
+                .ends_with(b"\r\n\r\na b!")
+        );
+    }
+
+    #[test]
+    fn echo_form() {
+        assert!(process(
+            "POST /echo HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\n",
+            b"text=hello+world&other=1"
+        )
+        .ends_with(b"\r\n\r\nhello world"));
+        assert!(process(
+            "POST /echo HTTP/1.1\r\nContent-Type: text/plain\r\n\r\n",
+            b"text=a"
+        )
+        .starts_with(b"HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn set_cookie_headers() {
+        struct Login;
+
+        impl HandleRequest for Login {
+            fn handle(
+                &self,
+                _: &mut Request,
+            ) -> (Option<Status>, AdditionalHeader, AdditionalBody) {
+                let cookies = [
+                    SetCookie::new("a", "1").unwrap(),
+                    SetCookie::new("b", "2").unwrap().http_only(),
+                ];
+                let headers = cookies
+                    .iter()
+                    .map(|cookie| (header::SET_COOKIE.into(), cookie.to_string()))
+                    .collect();
+                (Some(Status::OK), headers, vec![])
             }
         }
-        ServerResponse::Continue(self.response.into_bytes())
+
+        let request = RawRequest::convert("GET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut body = io::empty();
+        let mut handler = Handler::new(
+            request,
+            &mut body,
+            None,
+            Limits::default(),
+            false,
+            Proxies::default(),
+        );
+        handler.route(&Login);
+        let response = handler.response.into_bytes();
+        let expected = b"Set-Cookie: a=1\r\nSet-Cookie: b=2; HttpOnly\r\n";
+        assert!(response
+            .windows(expected.len())
+            .any(|lines| lines == expected));
     }
 
-    fn add_response_header(&mut self, header: &str, content: &str) {
-        let header = MessageHeader {
-            field_name: FieldName(header.as_bytes().into()),
-            field_value: Some(FieldValue(vec![FieldContent(content.as_bytes().into())])),
-        };
-        // replace the old one
-        match self
-            .response
-            .headers
-            .iter()
-            .position(|h| h.field_name == header.field_name)
-        {
-            Some(existing_idx) => self.response.headers[existing_idx] = header,
-            None => self.response.headers.push(header),
-        }
+    #[test]
+    fn metrics() {
+        let request = "GET /metrics HTTP/1.1\r\n\r\n";
+        assert!(process(request, b"").starts_with(b"HTTP/1.1 404 Not Found\r\n"));
+
+        process("GET /echo/a HTTP/1.1\r\n\r\n", b"");
+        let response = String::from_utf8(process_with(request, b"", true)).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(
+            response.contains("http_requests_total{route=\"echo\",method=\"GET\",status=\"200\"}")
+        );
     }
 }
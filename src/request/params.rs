@@ -0,0 +1,50 @@
+use crate::spec::uri::form_urlencoded;
+
+/// Decoded `name=value` pairs of a query string or a form body, names may repeat.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub(crate) struct Params(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl Params {
+    /// Decodes `application/x-www-form-urlencoded` data, `+` is a space.
+    pub fn parse(input: &[u8]) -> Params {
+        Params(form_urlencoded(input).collect())
+    }
+
+    /// The first value of `name`.
+    pub fn get(&self, name: impl AsRef<[u8]>) -> Option<&[u8]> {
+        self.get_all(name).next()
+    }
+
+    /// Every value of `name`, in order.
+    pub fn get_all(&self, name: impl AsRef<[u8]>) -> impl Iterator<Item = &[u8]> {
+        self.0
+            .iter()
+            .filter(move |(n, _)| n == name.as_ref())
+            .map(|(_, value)| value.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn repeated() {
+        let params = Params::parse(b"tag=a&q=x+y&tag=b%26c");
+        assert_eq!(params.get("q"), Some(&b"x y"[..]));
+        assert_eq!(params.get("tag"), Some(&b"a"[..]));
+        assert_eq!(
+            params.get_all("tag").collect::<Vec<_>>(),
+            vec![&b"a"[..], b"b&c"]
+        );
+        assert_eq!(params.get("missing"), None);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(Params::parse(b""), Params::default());
+        let params = Params::parse(b"flag&=v");
+        assert_eq!(params.get("flag"), Some(&b""[..]));
+        assert_eq!(params.get(""), Some(&b"v"[..]));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     request::HandleRequest,
-    spec::{request::Method, response::Status},
+    spec::{header, request::Method, response::Status},
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -11,19 +11,40 @@ pub(crate) struct Echo {
 impl HandleRequest for Echo {
     fn handle(
         &self,
-        request: &crate::request::Request,
+        request: &mut crate::request::Request,
     ) -> (
         Option<crate::spec::response::Status>,
         crate::request::AdditionalHeader,
         crate::request::AdditionalBody,
     ) {
-        match request.method() {
-            Method::Get => (
+        // without a path, the text comes from `?text=` or a form field
+        let text = match request.method() {
+            Method::Get if self.command.is_empty() => {
+                Some(request.query_param("text").unwrap_or_default())
+            }
+            Method::Get => Some(self.command.to_vec()),
+            #[cfg(feature = "json")]
+            Method::Post if request.is_json() => {
+                // JSON is echoed back as parsed
+                return match request.json::<serde_json::Value>() {
+                    Ok(value) => crate::spec::response::Response::json(&value).into_parts(),
+                    Err(e) => e.into_response().into_parts(),
+                };
+            }
+            Method::Post => match request.form() {
+                Ok(Some(form)) => form.get("text").map(<[u8]>::to_vec),
+                Ok(None) => None,
+                Err(_) => return (Some(Status::BadRequest), vec![], vec![]),
+            },
+            _ => return (None, vec![], vec![]),
+        };
+        match text {
+            Some(text) => (
                 Some(Status::OK),
-                vec![("Content-Type".into(), "text/plain".into())],
-                self.command.to_vec(),
+                vec![(header::CONTENT_TYPE.into(), "text/plain".into())],
+                text,
             ),
-            _ => (None, vec![], vec![]),
+            None => (Some(Status::BadRequest), vec![], vec![]),
         }
     }
 }
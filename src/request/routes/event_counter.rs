@@ -0,0 +1,77 @@
+use std::{io, time::Duration};
+
+use crate::{
+    event_stream::{self, Event, EventStream},
+    request::{HandleEventStream, HandleRequest, Params},
+    spec::{header, protocol::HttpVersion, request::Method, response::Status},
+};
+
+/// Counts up in `tick` events, resuming after the `Last-Event-ID` of a reconnecting client.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct EventCounter;
+
+/// The `count` of events, unlimited by default, and the `interval` between them in milliseconds.
+fn settings(query: &Params) -> Option<(Option<u64>, Duration)> {
+    let number = |name| -> Option<Option<u64>> {
+        match query.get(name) {
+            Some(value) => std::str::from_utf8(value).ok()?.parse().ok().map(Some),
+            None => Some(None),
+        }
+    };
+    let interval = number("interval")?.unwrap_or(1000);
+    Some((number("count")?, Duration::from_millis(interval)))
+}
+
+impl HandleRequest for EventCounter {
+    fn handle(
+        &self,
+        request: &mut crate::request::Request,
+    ) -> (
+        Option<crate::spec::response::Status>,
+        crate::request::AdditionalHeader,
+        crate::request::AdditionalBody,
+    ) {
+        if request.method() != &Method::Get {
+            return (None, vec![], vec![]);
+        }
+        // HTTP/2 responses are sent whole, the stream would never end
+        if request.inner.request_line.http_version >= (HttpVersion { major: 2, minor: 0 }) {
+            return (Some(Status::HTTPVersionNotSupported), vec![], vec![]);
+        }
+        match settings(&request.query()) {
+            Some(_) => (
+                Some(Status::OK),
+                vec![
+                    (
+                        header::CONTENT_TYPE.into(),
+                        event_stream::CONTENT_TYPE.into(),
+                    ),
+                    (header::CACHE_CONTROL.into(), "no-cache".into()),
+                ],
+                vec![],
+            ),
+            None => (Some(Status::BadRequest), vec![], vec![]),
+        }
+    }
+}
+
+impl HandleEventStream for EventCounter {
+    fn stream(&self, query: &Params, events: &mut EventStream) -> io::Result<()> {
+        let Some((count, interval)) = settings(query) else {
+            return Ok(());
+        };
+        // an id that was not sent by this route starts over
+        let start = events
+            .last_event_id()
+            .and_then(|id| id.parse::<u64>().ok())
+            .map_or(0, |id| id + 1);
+        for n in start..count.unwrap_or(u64::MAX) {
+            if n > start {
+                events.pause(interval)?;
+            }
+            let n = n.to_string();
+            events.send(Event::new(&n).event("tick").id(&n))?;
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,17 @@
 use std::{
     fs,
-    io::{Read, Write},
+    io::{self, Read},
+    path::{Path, PathBuf},
 };
 
 use crate::{
+    parser::Multipart,
     request::HandleRequest,
-    spec::{request::Method, response::Status},
+    spec::{header, request::Method, response::Status},
 };
 
+use super::is_filename;
+
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct Files {
     pub filename: Vec<u8>,
@@ -16,13 +20,14 @@ pub(crate) struct Files {
 impl HandleRequest for Files {
     fn handle(
         &self,
-        request: &crate::request::Request,
+        request: &mut crate::request::Request,
     ) -> (
         Option<crate::spec::response::Status>,
         crate::request::AdditionalHeader,
         crate::request::AdditionalBody,
     ) {
         match request.method() {
+            Method::Get if self.filename.is_empty() => (Some(Status::NotFound), vec![], vec![]),
             Method::Get => {
                 let directory = request
                     .cli_directory
@@ -38,7 +43,10 @@ impl HandleRequest for Files {
 
                     (
                         Some(Status::OK),
-                        vec![("Content-Type".into(), "application/octet-stream".into())],
+                        vec![(
+                            header::CONTENT_TYPE.into(),
+                            "application/octet-stream".into(),
+                        )],
                         content,
                     )
                 } else {
@@ -48,21 +56,52 @@ impl HandleRequest for Files {
             Method::Post => {
                 let directory = request
                     .cli_directory
-                    .as_ref()
+                    .clone()
                     .expect("directory must be passed");
+                if let Some(multipart) = request.multipart() {
+                    let mut saved = vec![];
+                    if save_parts(&directory, multipart, &mut saved).is_ok() {
+                        return (Some(Status::Created), vec![], vec![]);
+                    }
+                    // nothing is kept if one of the files fails
+                    for path in saved {
+                        if fs::remove_file(&path).is_err() {
+                            return (Some(Status::InternalServerError), vec![], vec![]);
+                        }
+                    }
+                    return (Some(Status::BadRequest), vec![], vec![]);
+                }
+                if self.filename.is_empty() {
+                    return (Some(Status::NotFound), vec![], vec![]);
+                }
                 if let Ok(filename) = String::from_utf8(self.filename.clone()) {
-                    let file = directory.join(filename);
-                    let mut file = fs::File::options()
-                        .create_new(true)
-                        .truncate(true)
-                        .write(true)
-                        .open(file)
-                        .expect("cannot open / create file");
-                    match &request.inner.body {
-                        Some(body) => file.write_all(&body.0).expect("cannot write file"),
-                        None => file.write_all(b"").expect("cannot write file"),
+                    let path = directory.join(filename);
+                    let file = fs::File::options().create_new(true).write(true).open(&path);
+                    let mut file = match file {
+                        Ok(file) => file,
+                        // uploads never replace a file
+                        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                            return (Some(Status::Conflict), vec![], vec![]);
+                        }
+                        Err(_) => return (Some(Status::InternalServerError), vec![], vec![]),
+                    };
+                    // the body is streamed, so uploads never sit in memory as a whole
+                    match io::copy(&mut request.body, &mut file) {
+                        Ok(_) => (Some(Status::Created), vec![], vec![]),
+                        Err(_) => {
+                            drop(file);
+                            // the upload failed either way, a partial file left behind
+                            // does not change the answer
+                            let _ = fs::remove_file(&path);
+                            // the rest of the body cannot be skipped, one going over the
+                            // limit is answered with 413 by the handler
+                            (
+                                Some(Status::InternalServerError),
+                                vec![(header::CONNECTION.into(), "close".into())],
+                                vec![],
+                            )
+                        }
                     }
-                    (Some(Status::Created), vec![], vec![])
                 } else {
                     (Some(Status::NotFound), vec![], vec![])
                 }
@@ -71,3 +110,33 @@ impl HandleRequest for Files {
         }
     }
 }
+
+/// Saves every file of a form upload under its own name, each one is added to `saved`
+/// as soon as it is created, so the caller can remove them if one fails.
+fn save_parts(
+    directory: &Path,
+    mut multipart: Multipart<&mut dyn Read>,
+    saved: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    while let Some(mut part) = multipart.next_part()? {
+        // other form fields are skipped
+        let Some(filename) = part.filename() else {
+            continue;
+        };
+        let filename = String::from_utf8(filename)
+            .ok()
+            .filter(|filename| is_filename(filename.as_bytes()))
+            .ok_or(io::ErrorKind::InvalidInput)?;
+        let path = directory.join(filename);
+        let mut file = fs::File::options()
+            .create_new(true)
+            .write(true)
+            .open(&path)?;
+        saved.push(path);
+        io::copy(&mut part, &mut file)?;
+    }
+    match saved.is_empty() {
+        true => Err(io::ErrorKind::InvalidInput.into()),
+        false => Ok(()),
+    }
+}
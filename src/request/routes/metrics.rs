@@ -0,0 +1,35 @@
+use crate::{
+    metrics::METRICS,
+    request::HandleRequest,
+    spec::{header, request::Method, response::Status},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Metrics;
+
+impl HandleRequest for Metrics {
+    fn handle(
+        &self,
+        request: &mut crate::request::Request,
+    ) -> (
+        Option<crate::spec::response::Status>,
+        crate::request::AdditionalHeader,
+        crate::request::AdditionalBody,
+    ) {
+        // opt-in, the route does not exist otherwise
+        if !request.metrics {
+            return (None, vec![], vec![]);
+        }
+        match request.method() {
+            Method::Get => (
+                Some(Status::OK),
+                vec![(
+                    header::CONTENT_TYPE.into(),
+                    "text/plain; version=0.0.4; charset=utf-8".into(),
+                )],
+                METRICS.render().into_bytes(),
+            ),
+            _ => (None, vec![], vec![]),
+        }
+    }
+}
@@ -1,15 +1,31 @@
 mod echo;
+mod event_counter;
 mod files;
+mod metrics;
+mod proxy;
 mod root;
 mod user_agent;
+mod websocket_echo;
 
-use crate::spec::{request::RequestURI, response::Status};
+use std::io;
+
+use crate::{
+    event_stream::EventStream,
+    health::PROBE_PREFIX,
+    proxy::Proxies,
+    spec::{response::Status, uri::RequestURI},
+    websocket::WebSocket,
+};
 use echo::Echo;
+use event_counter::EventCounter;
 use files::Files;
+use metrics::Metrics;
+use proxy::Proxy;
 use root::Root;
 use user_agent::UserAgent;
+use websocket_echo::WebSocketEcho;
 
-use super::HandleRequest;
+use super::{HandleEventStream, HandleRequest, HandleWebSocket, Params};
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) enum Route {
@@ -17,13 +33,51 @@ pub(crate) enum Route {
     UserAgent(UserAgent),
     Files(Files),
     Root(Root),
+    Metrics(Metrics),
+    WebSocketEcho(WebSocketEcho),
+    EventCounter(EventCounter),
+    Proxy(Proxy),
     Unknown,
 }
 
+impl Route {
+    /// Label of the route in metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Route::Echo(_) => "echo",
+            Route::UserAgent(_) => "user_agent",
+            Route::Files(_) => "files",
+            Route::Root(_) => "root",
+            Route::Metrics(_) => "metrics",
+            Route::WebSocketEcho(_) => "websocket_echo",
+            Route::EventCounter(_) => "event_counter",
+            Route::Proxy(_) => "proxy",
+            Route::Unknown => "unknown",
+        }
+    }
+
+    /// Whether the route may go on with the connection after its response head,
+    /// as a WebSocket or an event stream.
+    pub fn takes_connection(&self) -> bool {
+        matches!(self, Route::WebSocketEcho(_) | Route::EventCounter(_))
+    }
+
+    /// The route of `request_uri`, proxied paths take precedence over the built-in routes.
+    pub fn resolve(request_uri: &RequestURI, proxies: &Proxies) -> Route {
+        match proxies.find(request_uri) {
+            Some(rule) => Route::Proxy(Proxy {
+                upstream: rule.upstream().to_string(),
+                timeout: proxies.timeout,
+            }),
+            None => Route::from(request_uri),
+        }
+    }
+}
+
 impl HandleRequest for Route {
     fn handle(
         &self,
-        request: &crate::request::Request,
+        request: &mut crate::request::Request,
     ) -> (
         Option<crate::spec::response::Status>,
         super::AdditionalHeader,
@@ -34,42 +88,93 @@ impl HandleRequest for Route {
             Route::UserAgent(user_agent) => user_agent.handle(request),
             Route::Files(files) => files.handle(request),
             Route::Root(root) => root.handle(request),
+            Route::Metrics(metrics) => metrics.handle(request),
+            Route::WebSocketEcho(echo) => echo.handle(request),
+            Route::EventCounter(counter) => counter.handle(request),
+            Route::Proxy(proxy) => proxy.handle(request),
             Route::Unknown => (Some(Status::NotFound), vec![], vec![]),
         }
     }
 }
 
+impl HandleWebSocket for Route {
+    fn serve(&self, socket: &mut WebSocket) -> io::Result<()> {
+        match self {
+            Route::WebSocketEcho(echo) => echo.serve(socket),
+            // only reached after a handshake accepted by one of the routes above
+            _ => Ok(()),
+        }
+    }
+}
+
+impl HandleEventStream for Route {
+    fn stream(&self, query: &Params, events: &mut EventStream) -> io::Result<()> {
+        match self {
+            Route::EventCounter(counter) => counter.stream(query, events),
+            // only reached after one of the routes above answered with an event stream
+            _ => Ok(()),
+        }
+    }
+}
+
 impl From<&RequestURI> for Route {
     fn from(value: &RequestURI) -> Self {
-        let mut components = value.0.split(|v| v == &b'/').skip(1);
-        match components.next() {
-            Some(b"") => Route::Root(Root),
-            Some(b"echo") => Route::Echo(Echo {
-                command: components.flatten().cloned().collect(),
+        // routes match on the decoded path, the query is left to the handlers
+        let segments = value.path_segments();
+        match segments
+            .iter()
+            .map(Vec::as_slice)
+            .collect::<Vec<_>>()
+            .as_slice()
+        {
+            [b""] => Route::Root(Root),
+            [b"echo", command @ ..] => Route::Echo(Echo {
+                command: command.join(&b'/'),
             }),
-            Some(b"user-agent") => Route::UserAgent(UserAgent),
-            Some(b"files") => Route::Files(Files {
-                filename: components.flatten().cloned().collect(),
+            [b"user-agent"] => Route::UserAgent(UserAgent),
+            [b"metrics"] => Route::Metrics(Metrics),
+            [b"ws", b"echo"] => Route::WebSocketEcho(WebSocketEcho),
+            [b"events", b"counter"] => Route::EventCounter(EventCounter),
+            // form uploads name their files in the body
+            [b"files"] | [b"files", b""] => Route::Files(Files { filename: vec![] }),
+            // a single segment, so the file cannot be outside of the directory
+            [b"files", filename] if is_filename(filename) => Route::Files(Files {
+                filename: filename.to_vec(),
             }),
             _ => Route::Unknown,
         }
     }
 }
 
+/// A name for a file directly inside the served directory, other than a readiness probe.
+pub(super) fn is_filename(segment: &[u8]) -> bool {
+    !matches!(segment, b"" | b"." | b"..")
+        && !segment.iter().any(|c| b"/\\\0".contains(c))
+        && !segment.starts_with(PROBE_PREFIX.as_bytes())
+}
+
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
     use super::*;
-    use crate::spec::request::RequestURI;
+
+    fn uri(path: &[u8]) -> RequestURI {
+        RequestURI::Origin {
+            path: path.to_vec(),
+            query: None,
+        }
+    }
 
     #[test]
     fn root() {
-        assert_eq!(Route::from(&RequestURI(b"/".into())), Route::Root(Root));
+        assert_eq!(Route::from(&uri(b"/")), Route::Root(Root));
     }
 
     #[test]
     fn echo() {
         assert_eq!(
-            Route::from(&RequestURI(b"/echo/something".into())),
+            Route::from(&uri(b"/echo/something")),
             Route::Echo(Echo {
                 command: b"something".into()
             })
@@ -79,7 +184,7 @@ mod test {
     #[test]
     fn user_agent() {
         assert_eq!(
-            Route::from(&RequestURI(b"/user-agent".into())),
+            Route::from(&uri(b"/user-agent")),
             Route::UserAgent(UserAgent),
         );
     }
@@ -87,18 +192,81 @@ mod test {
     #[test]
     fn files() {
         assert_eq!(
-            Route::from(&RequestURI(b"/files/foo".into())),
+            Route::from(&uri(b"/files/foo")),
             Route::Files(Files {
                 filename: b"foo".into()
             }),
         );
     }
 
+    #[test]
+    fn metrics() {
+        assert_eq!(Route::from(&uri(b"/metrics")), Route::Metrics(Metrics));
+    }
+
+    #[test]
+    fn websocket_echo() {
+        assert_eq!(
+            Route::from(&uri(b"/ws/echo")),
+            Route::WebSocketEcho(WebSocketEcho)
+        );
+    }
+
+    #[test]
+    fn event_counter() {
+        assert_eq!(
+            Route::from(&uri(b"/events/counter")),
+            Route::EventCounter(EventCounter)
+        );
+    }
+
+    #[test]
+    fn proxied() {
+        let proxies = Proxies {
+            rules: vec!["/echo=127.0.0.1:8080".parse().unwrap()],
+            timeout: Duration::from_secs(1),
+        };
+        assert_eq!(
+            Route::resolve(&uri(b"/echo/a"), &proxies),
+            Route::Proxy(Proxy {
+                upstream: "127.0.0.1:8080".into(),
+                timeout: Duration::from_secs(1),
+            })
+        );
+        assert_eq!(
+            Route::resolve(&uri(b"/user-agent"), &proxies),
+            Route::UserAgent(UserAgent)
+        );
+    }
+
     #[test]
     fn unknown() {
+        assert_eq!(Route::from(&uri(b"/something")), Route::Unknown);
+    }
+
+    #[test]
+    fn echo_decoded() {
         assert_eq!(
-            Route::from(&RequestURI(b"/something".into())),
-            Route::Unknown
+            Route::from(&RequestURI::Origin {
+                path: b"/echo/a%20b/c".to_vec(),
+                query: Some(b"x=1".to_vec())
+            }),
+            Route::Echo(Echo {
+                command: b"a b/c".into()
+            })
         );
     }
+
+    #[test]
+    fn files_outside_directory() {
+        assert_eq!(Route::from(&uri(b"/files/..")), Route::Unknown);
+        assert_eq!(Route::from(&uri(b"/files/%2E%2E%2Fa")), Route::Unknown);
+        assert_eq!(Route::from(&uri(b"/files/.readyz-1-0")), Route::Unknown);
+        assert_eq!(Route::from(&uri(b"/files/a/b")), Route::Unknown);
+    }
+
+    #[test]
+    fn asterisk() {
+        assert_eq!(Route::from(&RequestURI::Asterisk), Route::Unknown);
+    }
 }
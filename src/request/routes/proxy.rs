@@ -0,0 +1,148 @@
+use std::{
+    io::{self, Read},
+    net::IpAddr,
+    time::Duration,
+};
+
+use crate::{
+    bytes::ToBytes,
+    proxy::{strip_hop_by_hop, UPSTREAMS},
+    request::HandleRequest,
+    spec::{
+        header,
+        protocol::HttpVersion,
+        request::{Request as RawRequest, RequestLine},
+        response::Status,
+        uri::RequestURI,
+    },
+};
+
+const X_FORWARDED_FOR: &str = "X-Forwarded-For";
+const FORWARDED: &str = "Forwarded";
+
+/// Forwards the request to `upstream` and answers with its response.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Proxy {
+    pub upstream: String,
+    pub timeout: Duration,
+}
+
+/// A `Forwarded` parameter value, quoted unless it is a token, RFC 7239 section 4.
+fn forwarded_value(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c));
+    match token {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace(['\\', '"'], "")),
+    }
+}
+
+impl HandleRequest for Proxy {
+    fn handle(
+        &self,
+        request: &mut crate::request::Request,
+    ) -> (
+        Option<crate::spec::response::Status>,
+        crate::request::AdditionalHeader,
+        crate::request::AdditionalBody,
+    ) {
+        // the body is sent with a length, whatever its framing from the client
+        let mut body = vec![];
+        if request.body.read_to_end(&mut body).is_err() {
+            return (Some(Status::BadRequest), vec![], vec![]);
+        }
+
+        let mut headers = request.inner.headers.clone();
+        strip_hop_by_hop(&mut headers);
+        // the client already got its `100 Continue`
+        headers.remove(header::EXPECT);
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::UPGRADE);
+        headers.remove(header::HTTP2_SETTINGS);
+        if !body.is_empty()
+            || request.inner.headers.contains_key(header::CONTENT_LENGTH)
+            || request
+                .inner
+                .headers
+                .contains_key(header::TRANSFER_ENCODING)
+        {
+            headers.append(header::CONTENT_LENGTH, body.len().to_string());
+        }
+        if !headers.contains_key(header::HOST) {
+            headers.append(header::HOST, &self.upstream);
+        }
+        if let Some(peer_addr) = request.inner.peer_addr {
+            let client = peer_addr.ip();
+            // previous proxies are kept, the closest client comes last
+            let forwarded_for = match headers.get(X_FORWARDED_FOR) {
+                Some(previous) => format!("{}, {client}", String::from_utf8_lossy(previous)),
+                None => client.to_string(),
+            };
+            headers.insert(X_FORWARDED_FOR, forwarded_for);
+
+            let mut forwarded = match client {
+                IpAddr::V4(ip) => format!("for={ip}"),
+                IpAddr::V6(ip) => format!("for=\"[{ip}]\""),
+            };
+            if let Some(host) = request.inner.headers.get(header::HOST) {
+                let host = String::from_utf8_lossy(host);
+                forwarded.push_str(&format!(";host={}", forwarded_value(&host)));
+            }
+            headers.append(FORWARDED, forwarded);
+        }
+
+        let request_uri = &request.inner.request_line.request_uri;
+        let method = request.method().clone();
+        let head = RawRequest {
+            request_line: RequestLine {
+                method: method.clone(),
+                // the upstream is an origin server, absolute-form is only for proxies
+                request_uri: RequestURI::Origin {
+                    path: request_uri.raw_path().to_vec(),
+                    query: request_uri.raw_query().map(<[u8]>::to_vec),
+                },
+                http_version: HttpVersion { major: 1, minor: 1 },
+            },
+            headers,
+            peer_addr: None,
+            received_at: None,
+        };
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&body);
+
+        let mut response = match UPSTREAMS.send(
+            &self.upstream,
+            &bytes,
+            &method,
+            self.timeout,
+            request.limits,
+        ) {
+            Ok(response) => response,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                return (Some(Status::GatewayTimeout), vec![], vec![]);
+            }
+            Err(_) => return (Some(Status::BadGateway), vec![], vec![]),
+        };
+        // nothing was asked to be upgraded, the connection cannot be handed over
+        if response.status() == Status::SwitchingProtocols {
+            return (Some(Status::BadGateway), vec![], vec![]);
+        }
+
+        strip_hop_by_hop(&mut response.headers);
+        // framed again by the server
+        response.headers.remove(header::CONTENT_LENGTH);
+        let headers = response
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    String::from_utf8_lossy(name).into_owned(),
+                    String::from_utf8_lossy(value).into_owned(),
+                )
+            })
+            .collect();
+        (Some(response.status()), headers, response.body().to_vec())
+    }
+}
@@ -9,7 +9,7 @@ pub(crate) struct Root;
 impl HandleRequest for Root {
     fn handle(
         &self,
-        request: &crate::request::Request,
+        request: &mut crate::request::Request,
     ) -> (
         Option<crate::spec::response::Status>,
         crate::request::AdditionalHeader,
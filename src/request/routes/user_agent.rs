@@ -1,6 +1,6 @@
 use crate::{
     request::HandleRequest,
-    spec::{request::Method, response::Status},
+    spec::{header, request::Method, response::Status},
 };
 
 #[derive(Debug, PartialEq, Eq)]
@@ -9,18 +9,18 @@ pub(crate) struct UserAgent;
 impl HandleRequest for UserAgent {
     fn handle(
         &self,
-        request: &crate::request::Request,
+        request: &mut crate::request::Request,
     ) -> (
         Option<crate::spec::response::Status>,
         crate::request::AdditionalHeader,
         crate::request::AdditionalBody,
     ) {
         match request.method() {
-            Method::Get => match request.inner.find_value(b"User-Agent") {
+            Method::Get => match request.inner.headers.get(header::USER_AGENT) {
                 Some(user_agent) => (
                     Some(Status::OK),
-                    vec![("Content-Type".into(), "text/plain".into())],
-                    user_agent,
+                    vec![(header::CONTENT_TYPE.into(), "text/plain".into())],
+                    user_agent.to_vec(),
                 ),
                 None => (None, vec![], vec![]),
             },
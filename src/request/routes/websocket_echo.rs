@@ -0,0 +1,59 @@
+use std::io;
+
+use crate::{
+    request::{HandleRequest, HandleWebSocket},
+    spec::{header, response::Status},
+    websocket::{self, Message, WebSocket},
+};
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WebSocketEcho;
+
+impl HandleRequest for WebSocketEcho {
+    fn handle(
+        &self,
+        request: &mut crate::request::Request,
+    ) -> (
+        Option<crate::spec::response::Status>,
+        crate::request::AdditionalHeader,
+        crate::request::AdditionalBody,
+    ) {
+        match websocket::handshake(&request.inner) {
+            Ok(accept) => (
+                Some(Status::SwitchingProtocols),
+                vec![
+                    (header::UPGRADE.into(), "websocket".into()),
+                    (header::CONNECTION.into(), "Upgrade".into()),
+                    (header::SEC_WEBSOCKET_ACCEPT.into(), accept),
+                ],
+                vec![],
+            ),
+            // tells the client what to ask for
+            Err(Status::UpgradeRequired) => (
+                Some(Status::UpgradeRequired),
+                vec![
+                    (header::UPGRADE.into(), "websocket".into()),
+                    (
+                        header::SEC_WEBSOCKET_VERSION.into(),
+                        websocket::VERSION.into(),
+                    ),
+                ],
+                vec![],
+            ),
+            Err(status) => (Some(status), vec![], vec![]),
+        }
+    }
+}
+
+impl HandleWebSocket for WebSocketEcho {
+    fn serve(&self, socket: &mut WebSocket) -> io::Result<()> {
+        loop {
+            match socket.receive()? {
+                message @ (Message::Text(_) | Message::Binary(_)) => socket.send(&message)?,
+                Message::Close(_) => return Ok(()),
+                // pings are answered by the socket
+                Message::Ping(_) | Message::Pong(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use crate::parser::is_tchar;
+
+use super::date::DateTime;
+
+/// Cookies sent by the client in `Cookie` headers, RFC 6265 section 5.4.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Cookies(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl Cookies {
+    /// Parses the values of `Cookie` headers, pairs without `=` are ignored.
+    pub fn parse<'a>(headers: impl IntoIterator<Item = &'a [u8]>) -> Cookies {
+        let cookies = headers
+            .into_iter()
+            .flat_map(|header| header.split(|&c| c == b';'))
+            .filter_map(|pair| {
+                let pair = pair.trim_ascii();
+                let (name, value) = pair.split_at(pair.iter().position(|&c| c == b'=')?);
+                let value = value[1..].trim_ascii();
+                // a value may be wrapped in double quotes, which are not part of it
+                let value = match value {
+                    [b'"', value @ .., b'"'] => value,
+                    value => value,
+                };
+                Some((name.trim_ascii().to_vec(), value.to_vec()))
+            })
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        Cookies(cookies)
+    }
+
+    /// The value of the cookie `name`, the first one if it was sent more than once.
+    pub fn get(&self, name: impl AsRef<[u8]>) -> Option<&[u8]> {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name.as_ref())
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_slice(), value.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent on cross-site requests too, which browsers only allow with `Secure`.
+    None,
+}
+
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidCookie {
+    #[error("invalid cookie name")]
+    Name,
+    #[error("invalid cookie value")]
+    Value,
+    #[error("invalid cookie attribute value")]
+    Attribute,
+}
+
+/// A `Set-Cookie` header value, built attribute by attribute (RFC 6265 section 4.1).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SetCookie {
+    name: String,
+    value: String,
+    expires: Option<SystemTime>,
+    max_age: Option<Duration>,
+    domain: Option<String>,
+    path: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    pub fn new(
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<SetCookie, InvalidCookie> {
+        let name = name.into();
+        let value = value.into();
+        if name.is_empty() || !name.bytes().all(is_tchar) {
+            return Err(InvalidCookie::Name);
+        }
+        let unquoted = match value.as_bytes() {
+            [b'"', value @ .., b'"'] => value,
+            value => value,
+        };
+        if !unquoted.iter().copied().all(is_cookie_octet) {
+            return Err(InvalidCookie::Value);
+        }
+        Ok(SetCookie {
+            name,
+            value,
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        })
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> SetCookie {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// How long the cookie is kept, zero removes it right away.
+    pub fn max_age(mut self, max_age: Duration) -> SetCookie {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Result<SetCookie, InvalidCookie> {
+        self.domain = Some(attribute_value(domain.into())?);
+        Ok(self)
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Result<SetCookie, InvalidCookie> {
+        self.path = Some(attribute_value(path.into())?);
+        Ok(self)
+    }
+
+    pub fn secure(mut self) -> SetCookie {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> SetCookie {
+        self.http_only = true;
+        self
+    }
+
+    /// `SameSite::None` also makes the cookie `Secure`.
+    pub fn same_site(mut self, same_site: SameSite) -> SetCookie {
+        self.same_site = Some(same_site);
+        self.secure |= same_site == SameSite::None;
+        self
+    }
+}
+
+impl fmt::Display for SetCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(expires) = self.expires {
+            write!(
+                f,
+                "; Expires={}",
+                DateTime::from_system_time(expires).http_date()
+            )?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={domain}")?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={path}")?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => write!(f, "; SameSite=Strict"),
+            Some(SameSite::Lax) => write!(f, "; SameSite=Lax"),
+            Some(SameSite::None) => write!(f, "; SameSite=None"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `cookie-octet`, visible characters except `"`, `,`, `;` and `\`.
+fn is_cookie_octet(c: u8) -> bool {
+    c.is_ascii_graphic() && !b"\",;\\".contains(&c)
+}
+
+fn attribute_value(value: String) -> Result<String, InvalidCookie> {
+    match value
+        .bytes()
+        .all(|c| (b' '..=b'~').contains(&c) && c != b';')
+    {
+        true => Ok(value),
+        false => Err(InvalidCookie::Attribute),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cookies = Cookies::parse([&b"a=1; b=\"two\";c"[..], b"a=3;  d = 4 "]);
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies.get("a"), Some(&b"1"[..]));
+        assert_eq!(cookies.get("b"), Some(&b"two"[..]));
+        assert_eq!(cookies.get("c"), None);
+        assert_eq!(
+            cookies.iter().collect::<Vec<_>>(),
+            vec![
+                (&b"a"[..], &b"1"[..]),
+                (b"b", b"two"),
+                (b"a", b"3"),
+                (b"d", b"4")
+            ]
+        );
+    }
+
+    #[test]
+    fn set_cookie() {
+        let cookie = SetCookie::new("id", "a3fWa")
+            .unwrap()
+            .expires(SystemTime::UNIX_EPOCH + Duration::from_secs(1445412480))
+            .max_age(Duration::from_secs(3600))
+            .domain("example.com")
+            .unwrap()
+            .path("/docs")
+            .unwrap()
+            .http_only()
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "id=a3fWa; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=3600; \
+             Domain=example.com; Path=/docs; HttpOnly; SameSite=Lax"
+        );
+    }
+
+    #[test]
+    fn set_cookie_same_site_none() {
+        let cookie = SetCookie::new("a", "\"b\"")
+            .unwrap()
+            .same_site(SameSite::None);
+        assert_eq!(cookie.to_string(), "a=\"b\"; Secure; SameSite=None");
+    }
+
+    #[test]
+    fn set_cookie_invalid() {
+        assert_eq!(SetCookie::new("a b", "c"), Err(InvalidCookie::Name));
+        assert_eq!(SetCookie::new("a", "b;c"), Err(InvalidCookie::Value));
+        assert_eq!(SetCookie::new("a", "b\r\nc"), Err(InvalidCookie::Value));
+        assert_eq!(
+            SetCookie::new("a", "b").unwrap().path("/\r\nX: 1"),
+            Err(InvalidCookie::Attribute)
+        );
+    }
+}
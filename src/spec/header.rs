@@ -0,0 +1,194 @@
+use super::message::{FieldName, FieldValue, MessageHeader};
+
+pub const ACCEPT: &str = "Accept";
+pub const ACCEPT_ENCODING: &str = "Accept-Encoding";
+pub const CACHE_CONTROL: &str = "Cache-Control";
+pub const CONNECTION: &str = "Connection";
+pub const CONTENT_DISPOSITION: &str = "Content-Disposition";
+pub const CONTENT_ENCODING: &str = "Content-Encoding";
+pub const CONTENT_LENGTH: &str = "Content-Length";
+pub const CONTENT_TYPE: &str = "Content-Type";
+pub const COOKIE: &str = "Cookie";
+pub const EXPECT: &str = "Expect";
+pub const HOST: &str = "Host";
+pub const HTTP2_SETTINGS: &str = "HTTP2-Settings";
+pub const LAST_EVENT_ID: &str = "Last-Event-ID";
+pub const REFERER: &str = "Referer";
+pub const SEC_WEBSOCKET_ACCEPT: &str = "Sec-WebSocket-Accept";
+pub const SEC_WEBSOCKET_KEY: &str = "Sec-WebSocket-Key";
+pub const SEC_WEBSOCKET_VERSION: &str = "Sec-WebSocket-Version";
+pub const SET_COOKIE: &str = "Set-Cookie";
+pub const TRANSFER_ENCODING: &str = "Transfer-Encoding";
+pub const UPGRADE: &str = "Upgrade";
+pub const USER_AGENT: &str = "User-Agent";
+pub const VARY: &str = "Vary";
+
+/// Message headers in the order they were added, names compare case-insensitively.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HeaderMap(pub(crate) Vec<MessageHeader>);
+
+impl FieldName {
+    fn matches(&self, name: &[u8]) -> bool {
+        self.0.eq_ignore_ascii_case(name)
+    }
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains_key(&self, name: impl AsRef<[u8]>) -> bool {
+        self.0.iter().any(|h| h.field_name.matches(name.as_ref()))
+    }
+
+    /// The last value of `name`, an empty value is `Some(b"")`.
+    pub fn get(&self, name: impl AsRef<[u8]>) -> Option<&[u8]> {
+        self.get_all(name).last()
+    }
+
+    /// The `Content-Length` of a parsed message.
+    pub(crate) fn content_length(&self) -> Option<usize> {
+        let content_length = self.get_field_value(CONTENT_LENGTH)?;
+        // the parser made sure every element of the list is the same
+        std::str::from_utf8(content_length.list().first()?)
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// Every value of `name`, in order.
+    pub fn get_all(&self, name: impl AsRef<[u8]>) -> impl Iterator<Item = &[u8]> {
+        self.headers(name).map(|h| value_bytes(&h.field_value))
+    }
+
+    /// Sets `name` to a single value, replacing the existing ones at the position of the first.
+    pub fn insert(&mut self, name: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        let header = header(name.as_ref(), value.as_ref());
+        let first = self
+            .0
+            .iter()
+            .position(|h| h.field_name.matches(name.as_ref()));
+        self.remove(name);
+        match first {
+            Some(first) => self.0.insert(first, header),
+            None => self.0.push(header),
+        }
+    }
+
+    /// Adds a value for `name`, keeping the existing ones.
+    pub fn append(&mut self, name: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.0.push(header(name.as_ref(), value.as_ref()));
+    }
+
+    /// Removes every value of `name`, returns whether there was any.
+    pub fn remove(&mut self, name: impl AsRef<[u8]>) -> bool {
+        let len = self.0.len();
+        self.0.retain(|h| !h.field_name.matches(name.as_ref()));
+        self.0.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.0
+            .iter()
+            .map(|h| (h.field_name.0.as_slice(), value_bytes(&h.field_value)))
+    }
+
+    /// The value of the last `name` field, `None` when that one is empty.
+    pub(crate) fn get_field_value(&self, name: impl AsRef<[u8]>) -> Option<&FieldValue> {
+        self.headers(name)
+            .last()
+            .and_then(|h| h.field_value.as_ref())
+    }
+
+    pub(crate) fn headers(&self, name: impl AsRef<[u8]>) -> impl Iterator<Item = &MessageHeader> {
+        self.0
+            .iter()
+            .filter(move |h| h.field_name.matches(name.as_ref()))
+    }
+}
+
+fn header(name: &[u8], value: &[u8]) -> MessageHeader {
+    MessageHeader {
+        field_name: FieldName(name.to_vec()),
+        field_value: match value.is_empty() {
+            true => None,
+            false => Some(FieldValue(value.to_vec())),
+        },
+    }
+}
+
+fn value_bytes(field_value: &Option<FieldValue>) -> &[u8] {
+    field_value.as_ref().map_or(&[], FieldValue::as_bytes)
+}
+
+impl From<Vec<MessageHeader>> for HeaderMap {
+    fn from(headers: Vec<MessageHeader>) -> Self {
+        HeaderMap(headers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.append("Accept", "text/plain");
+        headers.append("content-length", "3");
+        headers.append("ACCEPT", "text/html");
+        headers.append("X-Empty", "");
+        headers
+    }
+
+    #[test]
+    fn get_case_insensitive() {
+        let headers = headers();
+        assert_eq!(headers.get(CONTENT_LENGTH), Some(&b"3"[..]));
+        assert_eq!(headers.get("x-empty"), Some(&b""[..]));
+        assert_eq!(headers.get("Host"), None);
+        assert!(headers.contains_key("CONTENT-LENGTH"));
+    }
+
+    #[test]
+    fn get_multiple_values() {
+        let headers = headers();
+        assert_eq!(headers.get(ACCEPT), Some(&b"text/html"[..]));
+        assert_eq!(
+            headers.get_all(ACCEPT).collect::<Vec<_>>(),
+            vec![&b"text/plain"[..], b"text/html"]
+        );
+    }
+
+    #[test]
+    fn insert_replaces_in_place() {
+        let mut headers = headers();
+        headers.insert("accept", "*/*");
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![
+                (&b"accept"[..], &b"*/*"[..]),
+                (b"content-length", b"3"),
+                (b"X-Empty", b""),
+            ]
+        );
+    }
+
+    #[test]
+    fn append_and_remove() {
+        let mut headers = headers();
+        headers.append("X-Other", "1");
+        assert_eq!(headers.len(), 5);
+        assert!(headers.remove(ACCEPT));
+        assert!(!headers.remove(ACCEPT));
+        assert_eq!(headers.len(), 3);
+    }
+}
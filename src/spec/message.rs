@@ -1,11 +1,9 @@
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) struct FieldName(pub Vec<u8>);
 
+/// The exact octets of a field value, without surrounding whitespace.
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) struct FieldContent(pub Vec<u8>);
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-pub(crate) struct FieldValue(pub Vec<FieldContent>);
+pub(crate) struct FieldValue(pub Vec<u8>);
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub(crate) struct MessageHeader {
@@ -15,3 +13,121 @@ pub(crate) struct MessageHeader {
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct MessageBody(pub Vec<u8>);
+
+/// A `name=value` parameter, the value is unquoted.
+pub(crate) type Parameter<'a> = (&'a [u8], Vec<u8>);
+
+impl FieldValue {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Elements of a comma separated list, commas inside quoted strings do not split.
+    pub fn list(&self) -> Vec<&[u8]> {
+        split_unquoted(&self.0, b',')
+            .into_iter()
+            .filter(|element| !element.is_empty())
+            .collect()
+    }
+}
+
+/// Splits `value` on `separator` outside of quoted strings, trimming whitespace around parts.
+pub(crate) fn split_unquoted(value: &[u8], separator: u8) -> Vec<&[u8]> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, &c) in value.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            b'\\' if quoted => escaped = true,
+            b'"' => quoted = !quoted,
+            c if c == separator && !quoted => {
+                parts.push(value[start..i].trim_ascii());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(value[start..].trim_ascii());
+    parts
+}
+
+/// Splits a list element like `text/plain; charset="utf-8"` into its value and parameters,
+/// parameter names are kept as sent and quoted values are unquoted.
+pub(crate) fn parameters(element: &[u8]) -> (&[u8], Vec<Parameter<'_>>) {
+    let mut parts = split_unquoted(element, b';').into_iter();
+    let value = parts.next().unwrap_or_default();
+    let parameters = parts
+        .filter_map(|parameter| {
+            let (name, value) = parameter.split_at(parameter.iter().position(|&c| c == b'=')?);
+            let value = value[1..].trim_ascii();
+            let value = unquote(value).unwrap_or_else(|| value.to_vec());
+            Some((name.trim_ascii(), value))
+        })
+        .collect();
+    (value, parameters)
+}
+
+/// Removes the quotes and quoted-pair escapes of a quoted string.
+pub(crate) fn unquote(value: &[u8]) -> Option<Vec<u8>> {
+    let inner = value.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    let mut unquoted = Vec::with_capacity(inner.len());
+    let mut escaped = false;
+    for &c in inner {
+        match c {
+            b'\\' if !escaped => escaped = true,
+            b'"' if !escaped => return None,
+            c => {
+                unquoted.push(c);
+                escaped = false;
+            }
+        }
+    }
+    match escaped {
+        true => None,
+        false => Some(unquoted),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn list() {
+        assert_eq!(
+            FieldValue(b"gzip, deflate ,, br".to_vec()).list(),
+            vec![&b"gzip"[..], b"deflate", b"br"]
+        );
+    }
+
+    #[test]
+    fn list_quoted() {
+        assert_eq!(
+            FieldValue(br#"a, "b, c", d"#.to_vec()).list(),
+            vec![&b"a"[..], br#""b, c""#, b"d"]
+        );
+    }
+
+    #[test]
+    fn value_parameters() {
+        assert_eq!(
+            parameters(br#"text/plain; charset="utf-8" ;q=0.5"#),
+            (
+                &b"text/plain"[..],
+                vec![
+                    (&b"charset"[..], b"utf-8".to_vec()),
+                    (&b"q"[..], b"0.5".to_vec())
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn quoted_string() {
+        assert_eq!(unquote(br#""a \"b\"  c""#), Some(br#"a "b"  c"#.to_vec()));
+        assert_eq!(unquote(b"abc"), None);
+        assert_eq!(unquote(br#""a"b""#), None);
+    }
+}
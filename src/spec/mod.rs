@@ -1,4 +1,8 @@
+pub mod cookie;
+pub mod date;
+pub mod header;
 pub mod message;
 pub mod protocol;
 pub mod request;
 pub mod response;
+pub mod uri;
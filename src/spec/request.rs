@@ -1,8 +1,10 @@
-use crate::bytes::ToBytes;
+use std::{net::SocketAddr, time::Instant};
 
 use super::{
-    message::{FieldName, MessageBody, MessageHeader},
+    cookie::Cookies,
+    header::{self, HeaderMap},
     protocol::HttpVersion,
+    uri::RequestURI,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -12,8 +14,15 @@ pub(crate) enum Method {
     Extension(Vec<u8>),
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub(crate) struct RequestURI(pub Vec<u8>);
+impl Method {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Method::Get => b"GET",
+            Method::Post => b"POST",
+            Method::Extension(method) => method,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct RequestLine {
@@ -22,24 +31,61 @@ pub(crate) struct RequestLine {
     pub http_version: HttpVersion,
 }
 
+/// Framing of a message body, see RFC 9112 section 6.3.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BodyLength {
+    Length(usize),
+    Chunked,
+    /// The rest of the connection, only for responses.
+    UntilClose,
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Request {
     pub(crate) request_line: RequestLine,
-    pub(crate) headers: Vec<MessageHeader>,
-    pub(crate) body: Option<MessageBody>,
+    pub(crate) headers: HeaderMap,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) received_at: Option<Instant>,
 }
 
 impl Request {
-    pub(crate) fn find_header(&self, header: &[u8]) -> Option<&MessageHeader> {
-        self.headers
-            .iter()
-            .rev()
-            .find(|h| h.field_name == FieldName(header.into()))
+    pub fn request_uri(&self) -> &RequestURI {
+        &self.request_line.request_uri
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// Address of the client, unknown for requests not read from a connection.
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// When the head of the request was read.
+    pub fn received_at(&self) -> Option<Instant> {
+        self.received_at
+    }
+
+    /// Cookies from every `Cookie` header.
+    pub fn cookies(&self) -> Cookies {
+        Cookies::parse(self.headers.get_all(header::COOKIE))
+    }
+
+    /// Length of the body following the head, if the request declares one.
+    pub fn content_length(&self) -> Option<usize> {
+        self.headers.content_length()
     }
 
-    pub(crate) fn find_value(&self, header: &[u8]) -> Option<Vec<u8>> {
-        self.find_header(header)
-            .and_then(|header| header.field_value.as_ref())
-            .map(|field_value| field_value.clone().into_bytes())
+    /// How the body following the head is delimited.
+    pub fn body_length(&self) -> BodyLength {
+        match self.headers.contains_key(header::TRANSFER_ENCODING) {
+            true => BodyLength::Chunked,
+            false => BodyLength::Length(self.content_length().unwrap_or_default()),
+        }
     }
 }
@@ -1,41 +1,194 @@
 use super::{
-    message::{MessageBody, MessageHeader},
+    header::{self, HeaderMap},
+    message::MessageBody,
     protocol::HttpVersion,
+    request::{BodyLength, Method},
 };
 
-#[derive(Debug)]
-pub(crate) enum Status {
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Status {
+    Continue,
+    SwitchingProtocols,
     OK,
     Created,
+    BadRequest,
+    Unauthorized,
     NotFound,
+    Conflict,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    ExpectationFailed,
+    URITooLong,
+    UpgradeRequired,
+    RequestHeaderFieldsTooLarge,
+    InternalServerError,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    HTTPVersionNotSupported,
+    /// A status without a variant, like the ones relayed from another server.
+    Other(u16),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub(crate) struct StatusLine {
     pub http_version: HttpVersion,
     pub status: Status,
 }
 
+/// A 1xx response sent ahead of the final one, it never has headers or a body.
+#[derive(Debug)]
+pub(crate) struct InterimResponse {
+    pub status_line: StatusLine,
+}
+
 #[derive(Debug)]
 pub struct Response {
     pub(crate) status_line: StatusLine,
-    pub(crate) headers: Vec<MessageHeader>,
+    pub(crate) headers: HeaderMap,
     pub(crate) body: Option<MessageBody>,
 }
 
 impl Status {
     pub fn code(&self) -> u16 {
         match self {
+            Status::Continue => 100,
+            Status::SwitchingProtocols => 101,
             Status::OK => 200,
             Status::Created => 201,
+            Status::BadRequest => 400,
+            Status::Unauthorized => 401,
             Status::NotFound => 404,
+            Status::Conflict => 409,
+            Status::PayloadTooLarge => 413,
+            Status::URITooLong => 414,
+            Status::UnsupportedMediaType => 415,
+            Status::ExpectationFailed => 417,
+            Status::UpgradeRequired => 426,
+            Status::RequestHeaderFieldsTooLarge => 431,
+            Status::InternalServerError => 500,
+            Status::BadGateway => 502,
+            Status::ServiceUnavailable => 503,
+            Status::GatewayTimeout => 504,
+            Status::HTTPVersionNotSupported => 505,
+            Status::Other(code) => *code,
         }
     }
+
+    /// The status with the code `code`, `Other` for codes without a variant.
+    pub fn from_code(code: u16) -> Status {
+        [
+            Status::Continue,
+            Status::SwitchingProtocols,
+            Status::OK,
+            Status::Created,
+            Status::BadRequest,
+            Status::Unauthorized,
+            Status::NotFound,
+            Status::Conflict,
+            Status::PayloadTooLarge,
+            Status::UnsupportedMediaType,
+            Status::ExpectationFailed,
+            Status::URITooLong,
+            Status::UpgradeRequired,
+            Status::RequestHeaderFieldsTooLarge,
+            Status::InternalServerError,
+            Status::BadGateway,
+            Status::ServiceUnavailable,
+            Status::GatewayTimeout,
+            Status::HTTPVersionNotSupported,
+        ]
+        .into_iter()
+        .find(|status| status.code() == code)
+        .unwrap_or(Status::Other(code))
+    }
+
     pub fn reason_phrase(&self) -> &'static str {
         match self {
+            Status::Continue => "Continue",
+            Status::SwitchingProtocols => "Switching Protocols",
             Status::OK => "OK",
             Status::Created => "Created",
+            Status::BadRequest => "Bad Request",
+            Status::Unauthorized => "Unauthorized",
             Status::NotFound => "Not Found",
+            Status::Conflict => "Conflict",
+            Status::PayloadTooLarge => "Payload Too Large",
+            Status::URITooLong => "URI Too Long",
+            Status::UnsupportedMediaType => "Unsupported Media Type",
+            Status::ExpectationFailed => "Expectation Failed",
+            Status::UpgradeRequired => "Upgrade Required",
+            Status::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            Status::InternalServerError => "Internal Server Error",
+            Status::BadGateway => "Bad Gateway",
+            Status::ServiceUnavailable => "Service Unavailable",
+            Status::GatewayTimeout => "Gateway Timeout",
+            Status::HTTPVersionNotSupported => "HTTP Version Not Supported",
+            // clients ignore the reason phrase, it may be empty
+            Status::Other(_) => "",
+        }
+    }
+}
+
+impl Response {
+    /// An HTTP/1.1 response without headers or a body.
+    pub fn new(status: Status) -> Response {
+        Response {
+            status_line: StatusLine {
+                http_version: HttpVersion { major: 1, minor: 1 },
+                status,
+            },
+            headers: HeaderMap::new(),
+            body: None,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status_line.status
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        self.status_line.status = status;
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+
+    /// The body, empty when there is none.
+    pub fn body(&self) -> &[u8] {
+        self.body.as_ref().map_or(&[], |body| &body.0)
+    }
+
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = Some(MessageBody(body));
+    }
+
+    /// How the body following the head is delimited, see RFC 9112 section 6.3.
+    /// Responses to `HEAD` never have one.
+    pub(crate) fn body_length(&self, request_method: &Method) -> BodyLength {
+        let code = self.status().code();
+        if (100..200).contains(&code)
+            || code == 204
+            || code == 304
+            || *request_method == Method::Extension(b"HEAD".to_vec())
+        {
+            return BodyLength::Length(0);
+        }
+        if let Some(codings) = self.headers.get_field_value(header::TRANSFER_ENCODING) {
+            // the connection is closed after a body with another final coding
+            return match codings.list().last() {
+                Some(coding) if coding.eq_ignore_ascii_case(b"chunked") => BodyLength::Chunked,
+                _ => BodyLength::UntilClose,
+            };
+        }
+        match self.headers.content_length() {
+            Some(length) => BodyLength::Length(length),
+            None => BodyLength::UntilClose,
         }
     }
 }
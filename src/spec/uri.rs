@@ -0,0 +1,209 @@
+/// The request-target, in one of the forms of RFC 9112 section 3.2.
+///
+/// Paths and queries are kept as sent, escapes are only decoded on access.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RequestURI {
+    /// `/path?query`, used by most requests.
+    Origin {
+        path: Vec<u8>,
+        query: Option<Vec<u8>>,
+    },
+    /// `http://authority/path?query`, sent to proxies.
+    Absolute {
+        scheme: Vec<u8>,
+        authority: Vec<u8>,
+        path: Vec<u8>,
+        query: Option<Vec<u8>>,
+    },
+    /// `host:port`, only used by `CONNECT`.
+    Authority(Vec<u8>),
+    /// `*`, only used by a server wide `OPTIONS`.
+    Asterisk,
+}
+
+impl RequestURI {
+    /// The path as sent, empty for the authority and asterisk forms.
+    pub fn raw_path(&self) -> &[u8] {
+        match self {
+            RequestURI::Origin { path, .. } => path,
+            // an empty path in absolute-form is the root
+            RequestURI::Absolute { path, .. } if path.is_empty() => b"/",
+            RequestURI::Absolute { path, .. } => path,
+            RequestURI::Authority(_) | RequestURI::Asterisk => b"",
+        }
+    }
+
+    /// The request-target as sent.
+    pub fn target(&self) -> Vec<u8> {
+        let mut target = match self {
+            RequestURI::Origin { path, .. } => path.clone(),
+            RequestURI::Absolute {
+                scheme,
+                authority,
+                path,
+                ..
+            } => [&scheme[..], b"://", authority, path].concat(),
+            RequestURI::Authority(authority) => return authority.clone(),
+            RequestURI::Asterisk => return b"*".into(),
+        };
+        if let Some(query) = self.raw_query() {
+            target.push(b'?');
+            target.extend_from_slice(query);
+        }
+        target
+    }
+
+    /// Percent-decoded path segments, `/a%2Fb/c` is `["a/b", "c"]`.
+    pub fn path_segments(&self) -> Vec<Vec<u8>> {
+        match self.raw_path().strip_prefix(b"/") {
+            Some(path) => path
+                .split(|&c| c == b'/')
+                .map(|segment| percent_decode(segment).unwrap_or_else(|| segment.to_vec()))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// The query after `?` as sent.
+    pub fn raw_query(&self) -> Option<&[u8]> {
+        match self {
+            RequestURI::Origin { query, .. } | RequestURI::Absolute { query, .. } => {
+                query.as_deref()
+            }
+            RequestURI::Authority(_) | RequestURI::Asterisk => None,
+        }
+    }
+
+    /// The percent-decoded query.
+    pub fn query(&self) -> Option<Vec<u8>> {
+        let query = self.raw_query()?;
+        Some(percent_decode(query).unwrap_or_else(|| query.to_vec()))
+    }
+
+    /// Decoded `name=value` pairs of the query, in order.
+    pub fn query_pairs(&self) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+        self.raw_query().into_iter().flat_map(form_urlencoded)
+    }
+
+    /// Whether every `%` starts a valid escape.
+    pub(crate) fn is_valid(&self) -> bool {
+        percent_decode(self.raw_path()).is_some()
+            && self
+                .raw_query()
+                .map_or(true, |query| percent_decode(query).is_some())
+    }
+}
+
+/// Decodes `%XX` escapes, `None` if an escape is not followed by two hex digits.
+pub(crate) fn percent_decode(input: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+    while let Some(&c) = bytes.next() {
+        match c {
+            b'%' => {
+                let high = (*bytes.next()? as char).to_digit(16)?;
+                let low = (*bytes.next()? as char).to_digit(16)?;
+                decoded.push((high * 16 + low) as u8);
+            }
+            c => decoded.push(c),
+        }
+    }
+    Some(decoded)
+}
+
+/// Decodes `application/x-www-form-urlencoded` pairs, where `+` is a space.
+/// Invalid escapes are kept as sent.
+pub(crate) fn form_urlencoded(input: &[u8]) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + '_ {
+    let decode = |part: &[u8]| {
+        let part: Vec<u8> = part
+            .iter()
+            .map(|&c| if c == b'+' { b' ' } else { c })
+            .collect();
+        percent_decode(&part).unwrap_or(part)
+    };
+    input
+        .split(|&c| c == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(move |pair| match pair.iter().position(|&c| c == b'=') {
+            Some(i) => (decode(&pair[..i]), decode(&pair[i + 1..])),
+            None => (decode(pair), vec![]),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn origin(path: &[u8], query: Option<&[u8]>) -> RequestURI {
+        RequestURI::Origin {
+            path: path.to_vec(),
+            query: query.map(<[u8]>::to_vec),
+        }
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode(b"a%20b%2fc"), Some(b"a b/c".to_vec()));
+        assert_eq!(percent_decode(b"100%"), None);
+        assert_eq!(percent_decode(b"%zz"), None);
+    }
+
+    #[test]
+    fn target() {
+        assert_eq!(origin(b"/a%20b", Some(b"x=1")).target(), b"/a%20b?x=1");
+        assert_eq!(
+            RequestURI::Absolute {
+                scheme: b"http".to_vec(),
+                authority: b"example.com:80".to_vec(),
+                path: b"".to_vec(),
+                query: None,
+            }
+            .target(),
+            b"http://example.com:80"
+        );
+        assert_eq!(RequestURI::Asterisk.target(), b"*");
+    }
+
+    #[test]
+    fn path_segments() {
+        assert_eq!(
+            origin(b"/echo/a%20b%2Fc/", None).path_segments(),
+            vec![b"echo".to_vec(), b"a b/c".to_vec(), Vec::new()]
+        );
+        assert_eq!(origin(b"/", None).path_segments(), vec![Vec::<u8>::new()]);
+        assert!(RequestURI::Asterisk.path_segments().is_empty());
+    }
+
+    #[test]
+    fn absolute_empty_path() {
+        let uri = RequestURI::Absolute {
+            scheme: b"http".to_vec(),
+            authority: b"localhost".to_vec(),
+            path: vec![],
+            query: None,
+        };
+        assert_eq!(uri.raw_path(), b"/");
+    }
+
+    #[test]
+    fn query() {
+        let uri = origin(b"/", Some(b"a=1+2&b=%C3%A9&&c"));
+        assert_eq!(uri.raw_query(), Some(&b"a=1+2&b=%C3%A9&&c"[..]));
+        assert_eq!(uri.query(), Some("a=1+2&b=é&&c".into()));
+        assert_eq!(
+            uri.query_pairs().collect::<Vec<_>>(),
+            vec![
+                (b"a".to_vec(), b"1 2".to_vec()),
+                (b"b".to_vec(), "é".into()),
+                (b"c".to_vec(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn valid() {
+        assert!(origin(b"/a%20b", Some(b"q=%41")).is_valid());
+        assert!(!origin(b"/a%2", None).is_valid());
+        assert!(!origin(b"/", Some(b"q=%g1")).is_valid());
+    }
+}
@@ -0,0 +1,283 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock},
+};
+
+use rustls::{
+    client::verify_server_name,
+    crypto::{ring, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    server::{ClientHello, ParsedCertificate, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig, ServerConnection,
+};
+
+use crate::connection::Transport;
+
+/// Certificates picked by the name the client asks for (SNI).
+#[derive(Debug)]
+pub struct Certificates {
+    /// Certificate chain and private key files, in the order given.
+    files: Vec<(PathBuf, PathBuf)>,
+    keys: RwLock<Vec<Arc<CertifiedKey>>>,
+}
+
+impl Certificates {
+    /// Loads each certificate chain with its private key. The first one is used
+    /// when the client sends no name or a name no certificate is valid for.
+    pub fn load(files: Vec<(PathBuf, PathBuf)>) -> io::Result<Certificates> {
+        if files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no certificate given",
+            ));
+        }
+        let keys = load_all(&files)?;
+        Ok(Certificates {
+            files,
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Reads the files again for new connections, the loaded certificates are
+    /// kept if any of them fails.
+    pub fn reload(&self) -> io::Result<()> {
+        let keys = load_all(&self.files)?;
+        *self.keys.write().unwrap_or_else(PoisonError::into_inner) = keys;
+        Ok(())
+    }
+
+    /// A server configuration using these certificates, offering HTTP/2 and HTTP/1.1.
+    pub fn server_config(self: &Arc<Self>) -> io::Result<Arc<ServerConfig>> {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone());
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    fn find(&self, server_name: Option<&str>) -> Option<Arc<CertifiedKey>> {
+        let keys = self.keys.read().unwrap_or_else(PoisonError::into_inner);
+        let server_name = server_name.and_then(|name| ServerName::try_from(name).ok());
+        server_name
+            .and_then(|name| keys.iter().find(|key| is_valid_for(key, &name)))
+            .or_else(|| keys.first())
+            .cloned()
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.find(client_hello.server_name())
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(ring::default_provider())
+}
+
+fn load_all(files: &[(PathBuf, PathBuf)]) -> io::Result<Vec<Arc<CertifiedKey>>> {
+    files
+        .iter()
+        .map(|(cert, key)| load(cert, key).map(Arc::new))
+        .collect()
+}
+
+fn load(cert: &Path, key: &Path) -> io::Result<CertifiedKey> {
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| with_path(cert, e))?;
+    if chain.is_empty() {
+        return Err(with_path(cert, "no certificate found"));
+    }
+    let key = PrivateKeyDer::from_pem_file(key).map_err(|e| with_path(key, e))?;
+    CertifiedKey::from_der(chain, key, &provider()).map_err(invalid_data)
+}
+
+fn is_valid_for(key: &CertifiedKey, name: &ServerName) -> bool {
+    key.end_entity_cert()
+        .and_then(ParsedCertificate::try_from)
+        .and_then(|cert| verify_server_name(&cert, name))
+        .is_ok()
+}
+
+fn with_path(path: &Path, error: impl std::fmt::Display) -> io::Error {
+    invalid_data(format!("{}: {error}", path.display()))
+}
+
+fn invalid_data(error: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// A TLS connection accepted by the server, the handshake happens on the first reads.
+///
+/// Records are read from the socket without holding the lock on the TLS state,
+/// so responses can be written while the next request is awaited.
+pub struct TlsStream {
+    tcp: TcpStream,
+    tls: Mutex<ServerConnection>,
+}
+
+impl TlsStream {
+    pub fn new(tcp: TcpStream, config: Arc<ServerConfig>) -> io::Result<TlsStream> {
+        let tls = ServerConnection::new(config).map_err(invalid_data)?;
+        Ok(TlsStream {
+            tcp,
+            tls: Mutex::new(tls),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ServerConnection> {
+        self.tls.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends the records the TLS state has queued, like handshake messages.
+    fn flush(&self, tls: &mut ServerConnection) -> io::Result<()> {
+        while tls.wants_write() {
+            tls.write_tls(&mut &self.tcp)?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for TlsStream {
+    fn receive(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut records = [0; 16 * 1024];
+        loop {
+            {
+                let mut tls = self.lock();
+                match tls.reader().read(buf) {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    read => return read,
+                }
+            }
+
+            let read = (&self.tcp).read(&mut records)?;
+            if read == 0 {
+                // closed without close_notify, a clean end between requests for the parser
+                return Ok(0);
+            }
+            let mut tls = self.lock();
+            let mut records = &records[..read];
+            while !records.is_empty() {
+                tls.read_tls(&mut records)?;
+                if let Err(e) = tls.process_new_packets() {
+                    // let the client know with an alert
+                    let _ = self.flush(&mut tls);
+                    return Err(invalid_data(e));
+                }
+            }
+            self.flush(&mut tls)?;
+        }
+    }
+
+    fn send(&self, data: &[u8]) -> io::Result<()> {
+        let mut tls = self.lock();
+        let mut data = data;
+        // the TLS state only buffers so much, records are sent as they fill up
+        while !data.is_empty() {
+            let written = tls.writer().write(data)?;
+            data = &data[written..];
+            self.flush(&mut tls)?;
+        }
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp.peer_addr()
+    }
+
+    fn is_encrypted(&self) -> bool {
+        true
+    }
+
+    fn close(&self) {
+        let mut tls = self.lock();
+        tls.send_close_notify();
+        let _ = self.flush(&mut tls);
+        self.tcp.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::TcpListener, thread};
+
+    use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/tls")
+            .join(name)
+    }
+
+    fn certificates() -> Arc<Certificates> {
+        let certificates = Certificates::load(vec![
+            (fixture("localhost.pem"), fixture("localhost.key")),
+            (fixture("example.pem"), fixture("example.key")),
+        ])
+        .unwrap();
+        Arc::new(certificates)
+    }
+
+    #[test]
+    fn sni() {
+        let certificates = certificates();
+        let cert = |name| certificates.find(name).unwrap().cert[0].clone();
+        let localhost = cert(Some("localhost"));
+        let example = cert(Some("example.com"));
+        assert_ne!(localhost, example);
+        assert_eq!(cert(None), localhost);
+        assert_eq!(cert(Some("unknown.org")), localhost);
+
+        certificates.reload().unwrap();
+        assert_eq!(cert(Some("example.com")), example);
+    }
+
+    #[test]
+    fn load_error() {
+        let error = Certificates::load(vec![(fixture("missing.pem"), fixture("localhost.key"))])
+            .unwrap_err();
+        assert!(error.to_string().contains("missing.pem"));
+    }
+
+    #[test]
+    fn handshake() {
+        let config = certificates().server_config().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let stream = TlsStream::new(listener.accept().unwrap().0, config).unwrap();
+            let mut request = [0; 4];
+            let read = stream.receive(&mut request).unwrap();
+            stream.send(&request[..read]).unwrap();
+            stream.close();
+        });
+
+        let mut roots = RootCertStore::empty();
+        let example = CertificateDer::from_pem_file(fixture("example.pem")).unwrap();
+        roots.add(example).unwrap();
+        let mut config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        let name = ServerName::try_from("example.com").unwrap();
+        let client = ClientConnection::new(Arc::new(config), name).unwrap();
+        let mut client = StreamOwned::new(client, TcpStream::connect(addr).unwrap());
+
+        client.write_all(b"ping").unwrap();
+        let mut response = vec![];
+        client.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"ping");
+        assert_eq!(client.conn.alpn_protocol(), Some(&b"h2"[..]));
+        server.join().unwrap();
+    }
+}
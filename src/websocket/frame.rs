@@ -0,0 +1,189 @@
+use std::io::{self, Read};
+
+use super::close_code;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(super) enum Opcode {
+    Continuation = 0x0,
+    Text = 0x1,
+    Binary = 0x2,
+    Close = 0x8,
+    Ping = 0x9,
+    Pong = 0xa,
+}
+
+impl Opcode {
+    pub fn is_control(self) -> bool {
+        self as u8 & 0x8 != 0
+    }
+}
+
+/// A frame of RFC 6455 section 5.2, without extensions.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Frame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub(super) enum Error {
+    Io(io::Error),
+    /// The frame breaks the protocol, the connection is closed with this code.
+    Close(u16),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl Frame {
+    pub fn new(opcode: Opcode, payload: Vec<u8>) -> Frame {
+        Frame {
+            fin: true,
+            opcode,
+            payload,
+        }
+    }
+
+    /// Reads a frame sent by a client, which must be masked.
+    pub fn read(reader: &mut impl Read, max_payload: usize) -> Result<Frame, Error> {
+        let mut head = [0; 2];
+        reader.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        // no extension was negotiated to give the reserved bits a meaning
+        if head[0] & 0x70 != 0 {
+            return Err(Error::Close(close_code::PROTOCOL_ERROR));
+        }
+        let opcode = match head[0] & 0x0f {
+            0x0 => Opcode::Continuation,
+            0x1 => Opcode::Text,
+            0x2 => Opcode::Binary,
+            0x8 => Opcode::Close,
+            0x9 => Opcode::Ping,
+            0xa => Opcode::Pong,
+            _ => return Err(Error::Close(close_code::PROTOCOL_ERROR)),
+        };
+        if head[1] & 0x80 == 0 {
+            return Err(Error::Close(close_code::PROTOCOL_ERROR));
+        }
+        let length = match head[1] & 0x7f {
+            126 => {
+                let mut length = [0; 2];
+                reader.read_exact(&mut length)?;
+                u16::from_be_bytes(length) as u64
+            }
+            127 => {
+                let mut length = [0; 8];
+                reader.read_exact(&mut length)?;
+                u64::from_be_bytes(length)
+            }
+            length => length as u64,
+        };
+        // control frames are small and never fragmented
+        if opcode.is_control() && (!fin || length > 125) {
+            return Err(Error::Close(close_code::PROTOCOL_ERROR));
+        }
+        if length > max_payload as u64 {
+            return Err(Error::Close(close_code::MESSAGE_TOO_BIG));
+        }
+
+        let mut mask = [0; 4];
+        reader.read_exact(&mut mask)?;
+        let mut payload = vec![0; length as usize];
+        reader.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    /// Appends a frame sent by the server, which is never masked.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(if self.fin { 0x80 } else { 0 } | self.opcode as u8);
+        match self.payload.len() {
+            length @ 0..=125 => out.push(length as u8),
+            length @ 126..=0xffff => {
+                out.push(126);
+                out.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                out.push(127);
+                out.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        out.extend_from_slice(&self.payload);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encoded(frame: Frame) -> Vec<u8> {
+        let mut out = vec![];
+        frame.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn encode() {
+        // RFC 6455 section 5.7
+        assert_eq!(
+            encoded(Frame::new(Opcode::Text, b"Hello".to_vec())),
+            b"\x81\x05Hello"
+        );
+        assert_eq!(
+            encoded(Frame {
+                fin: false,
+                opcode: Opcode::Text,
+                payload: b"Hel".to_vec()
+            }),
+            b"\x01\x03Hel"
+        );
+        assert_eq!(
+            encoded(Frame::new(Opcode::Binary, vec![0; 256]))[..4],
+            [0x82, 0x7e, 0x01, 0x00]
+        );
+        assert_eq!(
+            encoded(Frame::new(Opcode::Binary, vec![0; 65536]))[..10],
+            [0x82, 0x7f, 0, 0, 0, 0, 0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn read_masked() {
+        // RFC 6455 section 5.7
+        let frame = b"\x81\x85\x37\xfa\x21\x3d\x7f\x9f\x4d\x51\x58";
+        assert_eq!(
+            Frame::read(&mut &frame[..], 1024).unwrap(),
+            Frame::new(Opcode::Text, b"Hello".to_vec())
+        );
+    }
+
+    fn read_error(frame: &[u8], max_payload: usize) -> Option<u16> {
+        match Frame::read(&mut &frame[..], max_payload) {
+            Err(Error::Close(code)) => Some(code),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn invalid_frames() {
+        // unmasked
+        assert_eq!(read_error(b"\x81\x05Hello", 1024), Some(1002));
+        // reserved bit
+        assert_eq!(read_error(b"\xc1\x80\0\0\0\0", 1024), Some(1002));
+        // unknown opcode
+        assert_eq!(read_error(b"\x83\x80\0\0\0\0", 1024), Some(1002));
+        // fragmented ping
+        assert_eq!(read_error(b"\x09\x80\0\0\0\0", 1024), Some(1002));
+        assert_eq!(read_error(b"\x82\xfe\x01\x00", 255), Some(1009));
+    }
+}
@@ -0,0 +1,480 @@
+//! WebSocket of RFC 6455, served on HTTP/1.1 connections once a route accepted
+//! the opening handshake with `101 Switching Protocols`.
+
+mod frame;
+mod sha1;
+
+use std::io::{self, Read};
+
+use crate::{
+    base64,
+    connection::Transport,
+    metrics::METRICS,
+    spec::{header, protocol::HttpVersion, request::Method, request::Request, response::Status},
+};
+use frame::{Error, Frame, Opcode};
+
+/// Appended to the client key before hashing it into `Sec-WebSocket-Accept`.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// The only version of the protocol, anything else gets `426 Upgrade Required`.
+pub const VERSION: &str = "13";
+/// Size of the fragments of the messages sent.
+const FRAGMENT_SIZE: usize = 64 * 1024;
+
+/// Status codes of close frames, RFC 6455 section 7.4.1.
+pub mod close_code {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const UNSUPPORTED_DATA: u16 = 1003;
+    pub const INVALID_PAYLOAD: u16 = 1007;
+    pub const POLICY_VIOLATION: u16 = 1008;
+    pub const MESSAGE_TOO_BIG: u16 = 1009;
+    pub const INTERNAL_ERROR: u16 = 1011;
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    /// Received pings are answered before being returned.
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+/// The `Sec-WebSocket-Accept` value answering a `Sec-WebSocket-Key`.
+pub fn accept_key(key: &[u8]) -> String {
+    let mut input = key.to_vec();
+    input.extend_from_slice(GUID.as_bytes());
+    base64::encode(&sha1::sha1(&input))
+}
+
+/// Whether the request asks to switch to WebSocket, valid or not.
+fn is_upgrade(request: &Request) -> bool {
+    request
+        .headers
+        .get_field_value(header::UPGRADE)
+        .is_some_and(|value| {
+            value
+                .list()
+                .iter()
+                .any(|protocol| protocol.eq_ignore_ascii_case(b"websocket"))
+        })
+}
+
+/// Checks an opening handshake, RFC 6455 section 4.2.1, and returns its `Sec-WebSocket-Accept`.
+///
+/// `426 Upgrade Required` is returned when the request does not ask for the
+/// supported version of WebSocket, its response should tell which one it is.
+pub(crate) fn handshake(request: &Request) -> Result<String, Status> {
+    let connection_upgrade = request
+        .headers
+        .get_field_value(header::CONNECTION)
+        .is_some_and(|value| {
+            value
+                .list()
+                .iter()
+                .any(|option| option.eq_ignore_ascii_case(b"upgrade"))
+        });
+    if !is_upgrade(request)
+        || !connection_upgrade
+        || request.headers.get(header::SEC_WEBSOCKET_VERSION) != Some(VERSION.as_bytes())
+    {
+        return Err(Status::UpgradeRequired);
+    }
+    // the upgrade is an HTTP/1.1 mechanism, the body would be lost
+    if request.request_line.method != Method::Get
+        || request.request_line.http_version != (HttpVersion { major: 1, minor: 1 })
+        || request.body_length() != crate::BodyLength::Length(0)
+    {
+        return Err(Status::BadRequest);
+    }
+    let mut keys = request.headers.get_all(header::SEC_WEBSOCKET_KEY);
+    match (keys.next(), keys.next()) {
+        (Some(key), None) if base64::decode(key).is_some_and(|nonce| nonce.len() == 16) => {
+            Ok(accept_key(key))
+        }
+        _ => Err(Status::BadRequest),
+    }
+}
+
+/// The server side of a WebSocket connection.
+///
+/// Control frames are handled while receiving: pings are answered and a close
+/// frame is acknowledged, after which the connection is closed.
+pub struct WebSocket<'a> {
+    transport: &'a dyn Transport,
+    reader: Box<dyn Read + 'a>,
+    /// Maximum size of a received message, fragments included.
+    max_message: usize,
+    /// The fragments received so far, control frames may come in between.
+    partial: Option<(Opcode, Vec<u8>)>,
+    /// A close frame was sent, only the one of the client is still expected.
+    closing: bool,
+    closed: bool,
+}
+
+impl<'a> WebSocket<'a> {
+    pub(crate) fn new(
+        transport: &'a dyn Transport,
+        reader: impl Read + 'a,
+        max_message: usize,
+    ) -> WebSocket<'a> {
+        WebSocket {
+            transport,
+            reader: Box::new(reader),
+            max_message,
+            partial: None,
+            closing: false,
+            closed: false,
+        }
+    }
+
+    /// Waits for the next message, fragmented ones are returned once complete.
+    ///
+    /// A `Close` message ends the connection, receiving afterwards fails.
+    /// Protocol violations close the connection with the matching status code.
+    pub fn receive(&mut self) -> io::Result<Message> {
+        loop {
+            if self.closed {
+                return Err(io::ErrorKind::NotConnected.into());
+            }
+            let frame = match Frame::read(&mut self.reader, self.max_message) {
+                Ok(frame) => frame,
+                Err(Error::Close(code)) => return Err(self.fail(code)),
+                Err(Error::Io(e)) => {
+                    self.closed = true;
+                    self.transport.close();
+                    return Err(e);
+                }
+            };
+
+            match frame.opcode {
+                Opcode::Ping => {
+                    if !self.closing {
+                        self.write(&Frame::new(Opcode::Pong, frame.payload.clone()))?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                Opcode::Pong => return Ok(Message::Pong(frame.payload)),
+                Opcode::Close => return self.acknowledge_close(&frame.payload),
+                Opcode::Text | Opcode::Binary if self.partial.is_none() => {
+                    self.partial = Some((frame.opcode, frame.payload));
+                }
+                Opcode::Continuation if self.partial.is_some() => {
+                    let (_, data) = self.partial.as_mut().expect("message started");
+                    if data.len() + frame.payload.len() > self.max_message {
+                        return Err(self.fail(close_code::MESSAGE_TOO_BIG));
+                    }
+                    data.extend_from_slice(&frame.payload);
+                }
+                // a new message in the middle of a fragmented one, or a continuation without one
+                _ => return Err(self.fail(close_code::PROTOCOL_ERROR)),
+            }
+
+            if frame.fin {
+                match self.partial.take().expect("message started") {
+                    (Opcode::Text, data) => match String::from_utf8(data) {
+                        Ok(text) => return Ok(Message::Text(text)),
+                        Err(_) => return Err(self.fail(close_code::INVALID_PAYLOAD)),
+                    },
+                    (_, data) => return Ok(Message::Binary(data)),
+                }
+            }
+        }
+    }
+
+    /// Sends a message, fragmenting large ones. Closing is done with [`WebSocket::close`].
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        if self.closing || self.closed {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        let (opcode, payload) = match message {
+            Message::Text(text) => (Opcode::Text, text.as_bytes()),
+            Message::Binary(data) => (Opcode::Binary, data.as_slice()),
+            Message::Ping(data) => (Opcode::Ping, data.as_slice()),
+            Message::Pong(data) => (Opcode::Pong, data.as_slice()),
+            Message::Close(frame) => return self.close(frame.clone()),
+        };
+        if opcode.is_control() {
+            if payload.len() > 125 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "control frames carry at most 125 bytes",
+                ));
+            }
+            return self.write(&Frame::new(opcode, payload.to_vec()));
+        }
+
+        // an empty message is still one frame
+        let mut fragments: Vec<&[u8]> = payload.chunks(FRAGMENT_SIZE).collect();
+        if fragments.is_empty() {
+            fragments.push(&[]);
+        }
+        let last = fragments.len() - 1;
+        for (i, fragment) in fragments.into_iter().enumerate() {
+            self.write(&Frame {
+                fin: i == last,
+                opcode: if i == 0 { opcode } else { Opcode::Continuation },
+                payload: fragment.to_vec(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Starts the closing handshake, the client's close frame is still returned by `receive`.
+    pub fn close(&mut self, frame: Option<CloseFrame>) -> io::Result<()> {
+        if self.closing || self.closed {
+            return Ok(());
+        }
+        self.closing = true;
+        self.write(&Frame::new(Opcode::Close, close_payload(frame.as_ref())))
+    }
+
+    fn acknowledge_close(&mut self, payload: &[u8]) -> io::Result<Message> {
+        let frame = match payload {
+            [] => None,
+            [_] => return Err(self.fail(close_code::PROTOCOL_ERROR)),
+            [high, low, reason @ ..] => {
+                let code = u16::from_be_bytes([*high, *low]);
+                if !is_valid_close_code(code) {
+                    return Err(self.fail(close_code::PROTOCOL_ERROR));
+                }
+                match String::from_utf8(reason.to_vec()) {
+                    Ok(reason) => Some(CloseFrame { code, reason }),
+                    Err(_) => return Err(self.fail(close_code::INVALID_PAYLOAD)),
+                }
+            }
+        };
+        if !self.closing {
+            // the code is echoed back, the reason is not needed
+            let echoed = frame.as_ref().map(|frame| CloseFrame {
+                code: frame.code,
+                reason: String::new(),
+            });
+            let _ = self.write(&Frame::new(Opcode::Close, close_payload(echoed.as_ref())));
+        }
+        self.closing = true;
+        self.closed = true;
+        self.transport.close();
+        Ok(Message::Close(frame))
+    }
+
+    /// Closes the connection after a protocol violation.
+    fn fail(&mut self, code: u16) -> io::Error {
+        if !self.closing && !self.closed {
+            let frame = CloseFrame {
+                code,
+                reason: String::new(),
+            };
+            let _ = self.write(&Frame::new(Opcode::Close, close_payload(Some(&frame))));
+        }
+        self.closing = true;
+        self.closed = true;
+        self.transport.close();
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("WebSocket closed with {code}"),
+        )
+    }
+
+    fn write(&self, frame: &Frame) -> io::Result<()> {
+        let mut data = vec![];
+        frame.encode(&mut data);
+        self.transport.send(&data)?;
+        METRICS.record_sent(data.len());
+        Ok(())
+    }
+}
+
+fn close_payload(frame: Option<&CloseFrame>) -> Vec<u8> {
+    let Some(frame) = frame else {
+        return vec![];
+    };
+    // control frames are limited to 125 bytes, the reason is cut on a character boundary
+    let mut end = frame.reason.len().min(123);
+    while !frame.reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut payload = frame.code.to_be_bytes().to_vec();
+    payload.extend_from_slice(&frame.reason.as_bytes()[..end]);
+    payload
+}
+
+/// Codes a peer may send, the others are reserved or only for local use.
+fn is_valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::{handle_stream, middleware::Chain, parser::Parse, Cli};
+
+    /// Connects to a server handling one connection and opens `/ws/echo`.
+    fn connect() -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let cli = Cli::parse_from(["server"]);
+            let (stream, _) = listener.accept().unwrap();
+            let _ = handle_stream(&cli, &Chain::default(), stream);
+        });
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .write_all(b"GET /ws/echo HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n")
+            .unwrap();
+
+        let mut head = vec![];
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(!head.contains("Content-Length"));
+        stream
+    }
+
+    /// Sends a masked frame, as clients do.
+    fn send(stream: &mut TcpStream, fin: bool, opcode: u8, payload: &[u8]) {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![if fin { 0x80 } else { 0 } | opcode];
+        match payload.len() {
+            length @ 0..=125 => frame.push(0x80 | length as u8),
+            length => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        stream.write_all(&frame).unwrap();
+    }
+
+    /// Reads an unmasked frame, returns its first byte and payload.
+    fn receive(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut head = [0; 2];
+        stream.read_exact(&mut head).unwrap();
+        let length = match head[1] {
+            126 => {
+                let mut length = [0; 2];
+                stream.read_exact(&mut length).unwrap();
+                u16::from_be_bytes(length) as usize
+            }
+            length => length as usize,
+        };
+        let mut payload = vec![0; length];
+        stream.read_exact(&mut payload).unwrap();
+        (head[0], payload)
+    }
+
+    #[test]
+    fn echo() {
+        let mut stream = connect();
+        send(&mut stream, true, 0x1, b"Hello");
+        assert_eq!(receive(&mut stream), (0x81, b"Hello".to_vec()));
+        send(&mut stream, true, 0x2, &[0; 300]);
+        assert_eq!(receive(&mut stream), (0x82, vec![0; 300]));
+    }
+
+    #[test]
+    fn fragments_and_pings() {
+        let mut stream = connect();
+        send(&mut stream, false, 0x1, b"Hel");
+        // control frames may come between fragments
+        send(&mut stream, true, 0x9, b"ping");
+        assert_eq!(receive(&mut stream), (0x8a, b"ping".to_vec()));
+        send(&mut stream, true, 0x0, b"lo");
+        assert_eq!(receive(&mut stream), (0x81, b"Hello".to_vec()));
+    }
+
+    #[test]
+    fn closing_handshake() {
+        let mut stream = connect();
+        send(&mut stream, true, 0x8, &[0x03, 0xe8, b'b', b'y', b'e']);
+        assert_eq!(receive(&mut stream), (0x88, vec![0x03, 0xe8]));
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+    }
+
+    #[test]
+    fn protocol_errors() {
+        let mut stream = connect();
+        send(&mut stream, true, 0x0, b"no message");
+        assert_eq!(receive(&mut stream), (0x88, vec![0x03, 0xea]));
+        assert_eq!(stream.read(&mut [0]).unwrap(), 0);
+
+        let mut stream = connect();
+        send(&mut stream, true, 0x1, &[0xff, 0xfe]);
+        assert_eq!(receive(&mut stream), (0x88, vec![0x03, 0xef]));
+    }
+
+    #[test]
+    fn accept() {
+        // RFC 6455 section 1.3
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    fn handshake_of(request: &str) -> Result<String, Status> {
+        handshake(&Request::convert(request).unwrap())
+    }
+
+    #[test]
+    fn opening_handshake() {
+        assert_eq!(
+            handshake_of("GET /ws/echo HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"),
+            Ok("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".into())
+        );
+        assert_eq!(
+            handshake_of("GET /ws/echo HTTP/1.1\r\nHost: a\r\n\r\n"),
+            Err(Status::UpgradeRequired)
+        );
+        assert_eq!(
+            handshake_of("GET /ws/echo HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 8\r\n\r\n"),
+            Err(Status::UpgradeRequired)
+        );
+        // the key must be 16 bytes
+        assert_eq!(
+            handshake_of("GET /ws/echo HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: c2hvcnQ=\r\nSec-WebSocket-Version: 13\r\n\r\n"),
+            Err(Status::BadRequest)
+        );
+        assert_eq!(
+            handshake_of("POST /ws/echo HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"),
+            Err(Status::BadRequest)
+        );
+    }
+
+    #[test]
+    fn close_payloads() {
+        assert_eq!(close_payload(None), b"");
+        let frame = CloseFrame {
+            code: close_code::GOING_AWAY,
+            reason: "é".repeat(100),
+        };
+        let payload = close_payload(Some(&frame));
+        assert_eq!(payload.len(), 124);
+        assert_eq!(payload[..2], [0x03, 0xe9]);
+        assert!(!is_valid_close_code(1005));
+        assert!(is_valid_close_code(4000));
+    }
+}
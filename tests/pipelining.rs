@@ -0,0 +1,205 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use clap::Parser;
+use codecrafters_http_server::{handle_stream, middleware::Chain, Cli};
+
+/// Serves a single connection on a free port with the given arguments.
+fn serve(args: &[&str]) -> TcpStream {
+    serve_connection(args).0
+}
+
+/// Like `serve`, the thread serving the connection ends once it is done with it.
+fn serve_connection(args: &[&str]) -> (TcpStream, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let cli = Cli::parse_from(["server"].iter().chain(args));
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        handle_stream(&cli, &Chain::default(), stream).unwrap();
+    });
+    (TcpStream::connect(address).unwrap(), server)
+}
+
+fn pipelined(args: &[&str]) -> String {
+    let mut stream = serve(args);
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\n\r\n\
+GET /echo/two HTTP/1.1\r\n\r\n\
+GET /missing HTTP/1.1\r\n\r\n\
+GET /user-agent HTTP/1.1\r\nUser-Agent: three\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[cfg(not(feature = "json"))]
+const NOT_FOUND: &str =
+    "HTTP/1.1 404 Not Found\r\nVary: Accept-Encoding\r\nContent-Length: 0\r\n\r\n";
+#[cfg(feature = "json")]
+const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\n\
+Content-Type: application/problem+json\r\nVary: Accept-Encoding\r\nContent-Length: 55\r\n\r\n\
+{\"status\":404,\"title\":\"Not Found\",\"type\":\"about:blank\"}";
+
+fn expected() -> String {
+    [
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVary: Accept-Encoding\r\nContent-Length: 3\r\n\r\none",
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVary: Accept-Encoding\r\nContent-Length: 3\r\n\r\ntwo",
+        NOT_FOUND,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVary: Accept-Encoding\r\nConnection: close\r\nContent-Length: 5\r\n\r\nthree",
+    ]
+    .concat()
+}
+
+#[test]
+fn pipelined_requests() {
+    assert_eq!(pipelined(&[]), expected());
+}
+
+#[test]
+fn pipelined_requests_concurrent() {
+    assert_eq!(pipelined(&["--concurrent-pipelining"]), expected());
+}
+
+#[test]
+fn pipelined_request_with_body() {
+    let directory = std::env::temp_dir().join(format!("pipelining-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let _ = std::fs::remove_file(directory.join("upload"));
+
+    let mut stream = serve(&["--directory", directory.to_str().unwrap()]);
+    stream
+        .write_all(
+            b"POST /files/upload HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+GET /files/upload HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert_eq!(
+        response,
+        "\
+HTTP/1.1 201 Created\r\nVary: Accept-Encoding\r\nContent-Length: 0\r\n\r\n\
+HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nVary: Accept-Encoding\r\nConnection: close\r\nContent-Length: 5\r\n\r\nhello"
+    );
+}
+
+/// A request pipelined after one closing the connection is not handled.
+fn pipelined_after_close(args: &[&str]) {
+    let directory = std::env::temp_dir().join(format!(
+        "pipelining-close-{}-{}",
+        std::process::id(),
+        args.len()
+    ));
+    std::fs::create_dir_all(&directory).unwrap();
+    let _ = std::fs::remove_file(directory.join("late"));
+
+    let args = [&["--directory", directory.to_str().unwrap()], args].concat();
+    let (mut stream, server) = serve_connection(&args);
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nConnection: close\r\n\r\n\
+POST /files/late HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.join().unwrap();
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVary: Accept-Encoding\r\nConnection: close\r\nContent-Length: 3\r\n\r\none"
+    );
+    assert!(!directory.join("late").exists());
+}
+
+#[test]
+fn pipelined_request_after_close() {
+    pipelined_after_close(&[]);
+}
+
+#[test]
+fn pipelined_request_after_close_concurrent() {
+    pipelined_after_close(&["--concurrent-pipelining"]);
+}
+
+#[test]
+fn chunked_body_over_limit() {
+    let (mut stream, server) = serve_connection(&["--max-body", "8"]);
+    stream
+        .write_all(
+            b"POST /echo HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\
+Transfer-Encoding: chunked\r\n\r\n5\r\ntext=\r\n5\r\nhello\r\n0\r\n\r\n\
+GET /echo/after HTTP/1.1\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    server.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    assert!(response.contains("Connection: close\r\n"));
+    // the rest of the body is not read as the next request
+    assert!(!response.contains("after"));
+}
+
+#[test]
+fn upload_existing_file() {
+    let directory = std::env::temp_dir().join(format!("pipelining-exists-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("exists"), "old").unwrap();
+
+    let mut stream = serve(&["--directory", directory.to_str().unwrap()]);
+    stream
+        .write_all(
+            b"POST /files/exists HTTP/1.1\r\nContent-Length: 3\r\n\r\nnew\
+GET /files/exists HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 409 Conflict\r\n"));
+    // the body is skipped, the next request is served
+    assert!(response.ends_with("\r\n\r\nold"));
+}
+
+#[test]
+fn idle_connection_closed_after_drain() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let cli = Cli::parse_from(["server"]);
+    let health = Arc::new(cli.health());
+    let chain = Chain::default().with(health.clone());
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        handle_stream(&cli, &chain, stream).unwrap();
+    });
+
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream.write_all(b"GET /echo/one HTTP/1.1\r\n\r\n").unwrap();
+    let expected = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVary: Accept-Encoding\r\nContent-Length: 3\r\n\r\none";
+    let mut response = vec![0; expected.len()];
+    stream.read_exact(&mut response).unwrap();
+    assert_eq!(String::from_utf8(response).unwrap(), expected);
+
+    // the connection waits for another request until the server drains
+    health.drain();
+    let mut rest = vec![];
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+    server.join().unwrap();
+}
@@ -0,0 +1,161 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use clap::Parser;
+use codecrafters_http_server::{handle_stream, middleware::Chain, Cli};
+
+/// Serves a single connection on a free port with the given arguments.
+fn serve(args: &[&str]) -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let cli = Cli::parse_from(["server"].iter().chain(args));
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        handle_stream(&cli, &Chain::default(), stream).unwrap();
+    });
+    TcpStream::connect(address).unwrap()
+}
+
+/// An upstream answering each request with the next of `responses`, on as many
+/// connections as needed. Sends back the requests it got, prefixed with the
+/// number of the connection.
+fn stub(responses: Vec<&'static str>) -> (String, Receiver<(usize, String)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut responses = responses.into_iter();
+        for (connection, stream) in listener.incoming().enumerate() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut request = String::new();
+                while !request.ends_with("\r\n\r\n") {
+                    if reader.read_line(&mut request).unwrap() == 0 {
+                        break;
+                    }
+                }
+                if request.is_empty() {
+                    break;
+                }
+                let length = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("Content-Length: "))
+                    .map_or(0, |length| length.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                sender.send((connection, request)).unwrap();
+
+                let Some(response) = responses.next() else {
+                    return;
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                if response.contains("Connection: close") {
+                    break;
+                }
+            }
+        }
+    });
+    (address, receiver)
+}
+
+fn exchange(stream: &mut TcpStream, request: &str) -> String {
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn forwarded_request() {
+    let (upstream, requests) = stub(vec![
+        "HTTP/1.1 201 Created\r\nConnection: keep-alive, X-Hop\r\nX-Hop: 1\r\nKeep-Alive: timeout=5\r\nLocation: /api/items/1\r\nContent-Length: 2\r\n\r\nok",
+    ]);
+    let mut stream = serve(&["--proxy", &format!("/api={upstream}")]);
+    let response = exchange(
+        &mut stream,
+        "POST /api/items?draft=1 HTTP/1.1\r\nHost: example.com\r\nX-Forwarded-For: 192.0.2.1\r\nConnection: close, X-Secret\r\nX-Secret: 1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+    );
+
+    let (_, request) = requests.recv().unwrap();
+    assert_eq!(
+        request,
+        "POST /api/items?draft=1 HTTP/1.1\r\nHost: example.com\r\n\
+X-Forwarded-For: 192.0.2.1, 127.0.0.1\r\nContent-Length: 3\r\n\
+Forwarded: for=127.0.0.1;host=example.com\r\n\r\nabc"
+    );
+    assert_eq!(
+        response,
+        "HTTP/1.1 201 Created\r\nLocation: /api/items/1\r\nVary: Accept-Encoding\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok"
+    );
+}
+
+#[test]
+fn pooled_upstream_connection() {
+    let (upstream, requests) = stub(vec![
+        "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\none\r\n0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+        "HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nthree",
+    ]);
+    let proxy = format!("/={upstream}");
+    let mut stream = serve(&["--proxy", &proxy]);
+    let response = exchange(
+        &mut stream,
+        "GET /one HTTP/1.1\r\n\r\nGET /two HTTP/1.1\r\n\r\nGET /three HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert_eq!(
+        response,
+        "HTTP/1.1 200 OK\r\nVary: Accept-Encoding\r\nContent-Length: 3\r\n\r\none\
+HTTP/1.1 200 OK\r\nVary: Accept-Encoding\r\nContent-Length: 3\r\n\r\ntwo\
+HTTP/1.1 200 OK\r\nVary: Accept-Encoding\r\nConnection: close\r\nContent-Length: 5\r\n\r\nthree"
+    );
+    // every request went through the first upstream connection
+    let connections: Vec<usize> = requests
+        .try_iter()
+        .map(|(connection, _)| connection)
+        .collect();
+    assert_eq!(connections, vec![0, 0, 0]);
+}
+
+#[test]
+fn unavailable_upstream() {
+    // nothing listens on a port that was just released
+    let upstream = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut stream = serve(&["--proxy", &format!("/api={upstream}")]);
+    let response = exchange(
+        &mut stream,
+        "GET /api HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+}
+
+#[test]
+fn upstream_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream = listener.local_addr().unwrap();
+    // accepted, never answered
+    thread::spawn(move || {
+        let (_stream, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(5));
+    });
+    let mut stream = serve(&[
+        "--proxy",
+        &format!("/api={upstream}"),
+        "--proxy-timeout",
+        "1",
+    ]);
+    let response = exchange(
+        &mut stream,
+        "GET /api HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+}